@@ -44,6 +44,65 @@
 // 동시에 실행 중일 때 이를 병렬로 작동하고 있다고 함. concurrency와 다르게 실행상태가 겹치는 구간이 병렬 실행 중이라고 함.
 // 컴퓨터 아키텍쳐, 하드웨어에서의 병렬성? 태스크 병렬성, 데이터 병렬성, 인스트럭션 레벨 병렬성 3종류로 나눔.
 //
+/// 위의 프로세스 상태와 동시/병렬 구간을 실행 기록기(timeline.rs)로 확인해보자. 세 스레드가 ch03의 Semaphore(1)을
+/// 번갈아 잡으며 계산하고, 하나는 수신 대기를 한다. 각 스레드의 실행/대기 구간을 간트 차트로 출력하고 Chrome trace
+/// JSON 파일로 저장한다.
+// #[test]
+pub fn func_6p() {
+    use crate::ch03_synchronous_processing01::{channel, Semaphore};
+    use crate::timeline::Timeline;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    // 계산하는 척 ms 동안 CPU를 사용
+    fn busy(ms: u64) {
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_millis(ms) {
+            std::hint::spin_loop();
+        }
+    }
+
+    let timeline = Timeline::new();
+    let sem = Arc::new(Semaphore::new(1));
+    let (tx, rx) = channel(1);
+
+    let mut v = Vec::new();
+    for name in ["A", "B"] {
+        let sem = sem.clone();
+        let tx = tx.clone();
+        v.push(timeline.spawn(name, move || {
+            for _ in 0..2 {
+                sem.wait(); // 다른 스레드가 잡고 있으면 대기 상태
+                busy(10);
+                sem.post();
+                busy(5); // 락 밖에서의 계산은 병렬로 실행될 수 있다
+            }
+            tx.send(name);
+        }));
+    }
+    v.push(timeline.spawn("C", move || {
+        for _ in 0..2 {
+            let name = rx.recv(); // 도착할 때까지 대기 상태
+            busy(2);
+            println!("recv: {}", name);
+        }
+    }));
+    for t in v {
+        t.join().unwrap();
+    }
+
+    print!("{}", timeline.gantt(60));
+    let overlap = timeline.overlap();
+    println!("concurrent: {:?}, parallel: {:?}", overlap.concurrent, overlap.parallel);
+
+    let path = std::env::temp_dir().join("timeline.json");
+    std::fs::write(&path, timeline.chrome_trace()).unwrap();
+    println!("chrome trace: {}", path.display());
+}
+// C는 처음부터 끝까지 계산 중 상태(대부분은 대기)이므로 A, B와 동시에 실행되고 있지만, 병렬로 실행되는 것은 A와 B가
+// 락 밖에서 동시에 계산할 때뿐이다. CPU가 하나뿐인 환경에서는 실행 상태가 겹치더라도 실제로는 시분할로 번갈아 실행되므로
+// parallel 구간이 있더라도 각 스레드의 계산은 느려진다. 저장한 JSON은 chrome://tracing이나 Perfetto에서 열어볼 수 있다.
+//
 // 1.3.1 태스크 병렬성? 프로세스 관점의 병렬성과 같음. 다른말로 스레드 병렬성이라 부르기도 함.
 // OS는 계산 처리를 OS프로세스 또는 스레드라 불리는 프로세스로 추상화하고 있으며,
 // 태스크 병렬 처리에서는 OS 프로세스 또는 스레드를 여러 CPU를 이용해 동시에 작동시킴.
@@ -252,6 +252,7 @@ pub fn spinlock_release(mut lock: bool) {
 //
 // 코드는 정상작동하지만 일반적으로 아토믹 명령은 실행 속도상의 페널티가 큼. 그래서 TAS를 호출하기 전에 검사를 하고 나서
 // TAS를 수행하도록 개선할 수 있으며 개선한 결과는 다음 코드와 같음.
+#[allow(clippy::while_immutable_condition)] // C 코드를 옮긴 의사 코드이므로 그대로 둔다
 pub fn spinlock_acquire2(mut lock: bool) { // c에서는 인자를 volatile 키워드를 붙여 최적화를 막음
     loop {
         while lock {}; // 1
@@ -302,6 +303,7 @@ use std::thread;
 
 static NUM: AtomicUsize = AtomicUsize::new(4);
 
+#[allow(clippy::never_loop)] // C 코드를 옮긴 의사 코드이므로 그대로 둔다
 pub fn semaphore_acquire(mut cnt: AtomicUsize) -> AtomicUsize { // 1
     loop {
         while cnt.load(Ordering::SeqCst) >= NUM.load(Ordering::SeqCst) {}; // 2
@@ -333,6 +335,7 @@ pub fn semaphore_release(mut cnt: AtomicUsize) -> AtomicUsize {
 // 제한이 있기 때문에 이용자 수에 제한을 거는 것과 같다. 당연하지만 주의할 점은 세마포어에서는 여러 프로세스가 lock을
 // 획득할 수 있으므로 Mutex에서는 피할 수 있었던 시뮬레이션을 피할 수 없는 경우가 많으므로 주의해야한다.
 // 다음 코드는 세마포어 이용 례
+#[allow(clippy::never_loop)]
 pub fn some_func4() {
     let mut cnt = AtomicUsize::new(0); // 공유 변수라 가정
     loop {
@@ -507,7 +510,11 @@ pub fn my_func() {
 /// 3.8.2 조건 변수
 /// Rust의 조건 변수는 Condvar type이며, 이용 방법은 Pthreads의 경우와 거의 같다. 락을 획득한 뒤 조건 변수를
 /// 이용해 wait 또는 notify를 수행함.
+#[cfg(not(test))]
 use std::sync::Condvar; // 1
+// 테스트 빌드에서는 일정 확률로 의사 각성을 일으키는 조건 변수로 바꿔서 조건 재확인 루프가 빠진 곳을 찾는다(spurious.rs).
+#[cfg(test)]
+use crate::spurious::SpuriousCondvar as Condvar;
 
 // Condvar type의 변수가 조건 변수이며 Mutex와 Condvar를 포함하는 튜플이 Arc에 포함되어 전달된다.
 fn child(id: u64, p: Arc<(Mutex<bool>, Condvar)>) { // 2
@@ -640,20 +647,31 @@ pub struct Semaphore {
     mutex: Mutex<isize>,
     cond: Condvar,
     max: isize,
+    site: crate::instrument::Site, // lockstat, race, timeline, chaos 계측 훅(instrument.rs)
 }
 
 impl Semaphore {
     pub fn new(max: isize) -> Self { // 2
+        Semaphore::with_name(max, "Semaphore")
+    }
+
+    // 경합 통계를 name으로 모음
+    pub fn with_name(max: isize, name: &'static str) -> Self {
         Semaphore {
             mutex: Mutex::new(0),
             cond: Condvar::new(),
             max,
+            site: crate::instrument::Site::new(name, "Semaphore::wait"),
         }
     }
 
     pub fn wait(&self) {
+        let mut wait = self.site.on_wait_begin();
         // 카운터가 최대값 이상이면 대기 3
         let mut cnt = self.mutex.lock().unwrap();
+        if *cnt >= self.max {
+            wait.on_block();
+        }
         while *cnt >= self.max {
             cnt = self.cond.wait(cnt).unwrap();
         }
@@ -669,10 +687,12 @@ impl Semaphore {
 
 
         *cnt += 1; // 4
+        wait.on_acquired_shared();
         // println!("critical section")
     }
 
     pub fn post(&self) {
+        self.site.on_released_shared();
         // 카운터 감소 5
         let mut cnt = self.mutex.lock().unwrap();
         *cnt -= 1;
@@ -740,12 +760,14 @@ pub struct Sender<T> {
     sem: Arc<Semaphore>, // 유한성을 구현하는 세마포어
     buf: Arc<Mutex<LinkedList<T>>>, // Queue
     cond: Arc<Condvar>, // 읽기 측의 조건 변수
+    site: Arc<crate::instrument::Site>, // 계측 훅(instrument.rs)
 }
 
 impl<T: Send> Sender<T> { // 2
     // 송신 함수
     pub fn send(&self, data: T) {
         self.sem.wait(); // Queue의 최대값에 도달하면 대기 3
+        self.site.on_sent();
         let mut buf = self.buf.lock().unwrap();
         buf.push_back(data); // 인큐
         self.cond.notify_one(); // 읽기 측에 대한 알림 4
@@ -768,18 +790,22 @@ pub struct Receiver<T> { // 수신단을 위한 type 1
     sem: Arc<Semaphore>, // 유한성을 구현하는 세마포어
     buf: Arc<Mutex<LinkedList<T>>>, // Queue
     cond: Arc<Condvar>, // 읽기 측의 Cond var
+    site: Arc<crate::instrument::Site>,
 }
 
 impl<T> Receiver<T> {
     pub fn recv(&self) -> T {
+        let mut wait = self.site.on_wait_begin();
         let mut buf = self.buf.lock().unwrap();
         loop {
             // Queue에서 추출 2
             if let Some(data) = buf.pop_front() {
+                wait.on_received();
                 self.sem.post(); // 3
                 return data;
             }
             // Queue가 빈 경우 wait 4
+            wait.on_block();
             buf = self.cond.wait(buf).unwrap();
         }
     }
@@ -799,15 +825,17 @@ use std::sync::mpsc::sync_channel;
 // 다음으로 channel 생성을 수행하는 함수를 살펴보자. 이 함수는 Queue의 최대 수를 받아 Sender와 Receiver type의 값을 생성한다.
 pub fn channel<T>(max: isize) -> (Sender<T>, Receiver<T>) {
     assert!(max > 0);
-    let sem = Arc::new(Semaphore::new(max));
+    let sem = Arc::new(Semaphore::with_name(max, "channel")); // 세마포어의 보유 시간 = 메시지가 큐에 머문 시간
     let buf = Arc::new(Mutex::new(LinkedList::new()));
     let cond = Arc::new(Condvar::new());
+    let site = Arc::new(crate::instrument::Site::new("channel", "Receiver::recv"));
     let tx = Sender {
         sem: sem.clone(),
         buf: buf.clone(),
         cond: cond.clone(),
+        site: site.clone(),
     };
-    let rx = Receiver { sem, buf, cond };
+    let rx = Receiver { sem, buf, cond, site };
     (tx, rx)
 }
 // 위의 채널 생성 함수로 채널을 이용해보자
@@ -848,6 +876,60 @@ pub fn some_func10_133p() {
 // 채널의 고속화 방법으로서는 벌크 데이터 전송(bulk data transfer)라 불리는 방법이 알려져 있다. 일반적으로 Mutex
 // 등의 락 획득은 비용이 높은 계산이다. 그래서 락 획득 횟수를 줄이기 위해 여러 데이터를 일괄로 큐에 넣으면 전송 처리량의
 // 향상을 기대할 수 있다. 단 소수의 데이터만 있는 경우에는 일정 시간이 지난 후 큐에 넣는 등의 개선이 필요하다.
+//
+/// 위의 채널이 FIFO 큐로서 올바른지를 "받은 개수가 맞았다"가 아니라 선형화 가능성 검사기(linearizability.rs)로
+/// 확인해보자. send와 recv의 호출/응답 시각을 기록하고 그 기록을 큐의 순차 명세로 설명할 수 있는지 검사한다.
+// #[test]
+pub fn some_func10_133p_2() {
+    use crate::linearizability::{check, Operation, Queue, QueueOp, Recorder};
+
+    let (tx, rx) = channel(2);
+    let rec = Arc::new(Recorder::new());
+    let mut v = Vec::new();
+
+    // 송신용 스레드 3개가 3개씩 송신
+    for i in 0..3 {
+        let tx0 = tx.clone();
+        let rec = rec.clone();
+        v.push(thread::spawn(move || {
+            for j in 0..3 {
+                let n = i * 10 + j;
+                rec.call(i, QueueOp::Enqueue(n), || {
+                    tx0.send(n);
+                    None
+                });
+            }
+        }));
+    }
+
+    // 수신용 스레드
+    let rec0 = rec.clone();
+    v.push(thread::spawn(move || {
+        for _ in 0..9 {
+            rec0.call(3, QueueOp::Dequeue, || Some(rx.recv()));
+        }
+    }));
+
+    for t in v {
+        t.join().unwrap();
+    }
+    println!("channel: {:?}", check::<Queue<usize>>(&rec.history()));
+
+    // 잘못된 큐의 기록: Enqueue(1)이 끝난 뒤에 Enqueue(2)를 시작했는데 2가 먼저 나옴
+    let op = |thread, op, ret, invoke, response| Operation { thread, op, ret: Some(ret), invoke, response: Some(response) };
+    let broken = vec![
+        op(0, QueueOp::Enqueue(1), None, 0, 1),
+        op(0, QueueOp::Enqueue(2), None, 2, 3),
+        op(1, QueueOp::Enqueue(3), None, 4, 7), // 모순과 상관없는 연산. 최소 기록에서는 제거된다.
+        op(2, QueueOp::Dequeue, Some(2), 4, 6),
+    ];
+    if let Err(e) = check::<Queue<usize>>(&broken) {
+        println!("{}", e);
+    }
+}
+// 송신 쪽은 send가 돌아온 시점, 수신 쪽은 recv가 값을 반환한 시점을 응답으로 기록한다. 채널이 올바른 FIFO라면
+// 어떤 인터리빙에서도 선형화 가능하다. 실패하면 연산을 하나씩 빼 보면서 여전히 실패하는 가장 작은 기록을 타임라인으로
+// 출력하므로, 수백 개의 연산 중 어떤 몇 개가 모순인지 바로 알 수 있다.
 
 
 // 3.9 베이커리 알고리즘(Leslie Lamport's Bakery Algorithm)
@@ -918,11 +1000,13 @@ struct BakeryLock {
 impl BakeryLock {
     // 락 함수, idx는 스레드 번호
     fn lock(&mut self, idx: usize) -> LockGuard {
+        let mut wait = LOCK_SITE.on_wait_begin();
         ///////////////////// 여기부터 티켓 취득 처리 5
         fence(Ordering::SeqCst); // 스레드 idx가 티켓 취득 중 상태임을 나타내기 위해 entering[idx]를
         // write_mem!(&mut self.entering[idx], true); // true로 설정하는데, 그 전후에 메모리 배리어를 걸어둬서
         // unsafe 매크로 -> safe 함수
         write2(&mut self.entering[idx], true);
+        crate::chaos::point();
         fence(Ordering::SeqCst); // out-of-order에서의 메모리 읽기 및 쓰기가 수행되는 것을 방지함.
 
         // 현재 배포되어 있는 티켓의 최대값 취득 6
@@ -938,6 +1022,7 @@ impl BakeryLock {
         }
         // 최대값 + 1을 자신의 티켓 번호로 한다. 7
         let ticket = max + 1;
+        crate::chaos::point();
         // write_mem!(&mut self.tickets[idx], Some(ticket));
         // unsafe 매크로 -> safe 함수
         write(&mut self.tickets[idx], Some(ticket));
@@ -957,7 +1042,9 @@ impl BakeryLock {
             // 스레드 i가 티켓 취득 중이면 대기
             // while read_mem!(&self.entering[i]) {} // 10
             // unsafe 매크로 -> safe 함수
-            while read2(&self.entering[i]) {};
+            while read2(&self.entering[i]) {
+                wait.on_spin();
+            }
 
             loop {
                 // 스레드 i와 자신의 순서를 비교해 자신의 순서가 높거나 스레드 i가 처리 중이 아니면 대기 종료 11
@@ -968,7 +1055,7 @@ impl BakeryLock {
                         // 스레드 i의 티켓 번호보다 자신의 번호가 낮거나 티켓 번호가 같고
                         // 자신의 스레드 번호가 작으면 대기 종료
                         if ticket < t ||
-                            (ticket == t && idx < i) { // 이것은 타이밍에 따라 같은 티켓 번호를 취득하는 경우가 있기 때문(아토믹이 아닌 것도).
+                            (ticket == t && idx < i) { // 최대값 읽기와 티켓 쓰기가 아토믹하지 않아 타이밍에 따라 같은 티켓 번호를 취득하는 경우가 있기 때문.
                             break;
                         }
                     }
@@ -977,12 +1064,14 @@ impl BakeryLock {
                         break;
                     }
                 }
+                wait.on_spin();
             }
         }
 
         fence(Ordering::SeqCst);
         LockGuard {
-            idx
+            idx,
+            held: wait.on_acquired(),
         }
     }
 }
@@ -990,12 +1079,14 @@ impl BakeryLock {
 // 락 관리용 타입 12
 struct LockGuard {
     idx: usize,
+    held: crate::lockstat::Held,
 }
 
 impl Drop for LockGuard {
     // 락 해제 처리 13
     // 락 획득 후 자동으로 해제되도록 drop trait 구현. 락 해제는 ticket의 반환을 수행하기 위해 tickets[self.idx]에 None을 저장해서 수행.
     fn drop(&mut self) {
+        LOCK_SITE.on_released(self.held);
         fence(Ordering::SeqCst);
         write_mem!(&mut LOCK.tickets[self.idx], None);
     }
@@ -1007,6 +1098,9 @@ static mut LOCK: BakeryLock = BakeryLock { // mut 글로벌 변수는 권장되
     tickets: [None; NUM_THREADS_2],
 };
 
+// BakeryLock의 계측 훅(instrument.rs). LOCK이 static mut이므로 따로 둔다.
+static LOCK_SITE: crate::instrument::Site = crate::instrument::Site::new("BakeryLock", "BakeryLock::lock");
+
 static mut COUNT: u64 = 0;
 
 pub fn some_func11_138p() {
@@ -1072,4 +1166,71 @@ pub fn some_func11_138p() {
 //
 // 여기까지 베이커리 알고리즘이었음. 여기에서는 메모리 배리어 처리를 사용했지만 이를 제거했을 때 출력이 어떻게 달라지는지
 // 확인해보자. 또한 read_mem과 write_mem 매크로 또한 함수로 바꿔보자. 꼭 시도해보자!!! 특히 out-of-order 실행을
-// 적극적으로 수행하는 AArch64에서는 그 차이를 보다 명확하게 알 수 있을지도?
\ No newline at end of file
+// 적극적으로 수행하는 AArch64에서는 그 차이를 보다 명확하게 알 수 있을지도?
+//
+/// 위의 "메모리 배리어를 제거해보자"를 store buffer 시뮬레이터(weak_memory.rs)로 확인해보자. BakeryLock의 fence를
+/// 하나씩 제거하면서 SC, TSO(x86-64), PSO 모델에서 상호 배제가 유지되는지 출력하고, 깨지는 경우의 반례를 보여준다.
+// #[test]
+pub fn some_func11_138p_2() {
+    use crate::weak_memory::{bakery, dekker, fence_report, peterson, Model};
+
+    fence_report(|removed| bakery(2, removed));
+    fence_report(peterson);
+    fence_report(dekker);
+
+    // entering[idx] = true 뒤의 fence(F2)를 빼면 x86-64에서도 깨진다.
+    println!("bakery without F2 on TSO: {}", bakery(2, &["F2"]).check(Model::Tso));
+}
+// TSO에서는 W->R 리오더링만 일어나므로 "쓰기 후 다른 변수 읽기" 사이의 fence(Bakery의 F2, Peterson의 F2,
+// Dekker의 F1)만 필요하다. 쓰기끼리의 순서까지 바뀌는 PSO에서는 Bakery의 F3(티켓 쓰기와 entering = false 사이)과
+// Peterson의 F1(flag와 turn 쓰기 사이)도 필요해진다. 나머지 fence는 이 두 모델에서는 없어도 상호 배제가 유지된다.
+// 단, 여기서는 읽기끼리의 리오더링(R->R, R->W)은 모델링하지 않았으므로 AArch64에서 필요한 fence는 더 많을 수 있다.
+//
+/// 위의 COUNT처럼 락으로 보호해야 하는 변수를 race::Shared로 감싸서 벡터 클록 레이스 검출기(race.rs)로 확인해보자.
+/// 락 없이 증가하면 두 스레드의 읽기/쓰기 위치가 보고되고, race::Mutex나 Semaphore(1)로 보호하면 보고되지 않는다.
+// #[test]
+pub fn some_func11_138p_3() {
+    use crate::race::{self, Shared};
+
+    static RACY: Shared<u64> = Shared::new("RACY", 0);
+    static LOCKED: Shared<u64> = Shared::new("LOCKED", 0);
+    static SEM: Shared<u64> = Shared::new("SEM", 0);
+
+    let lock = Arc::new(race::Mutex::new(()));
+    let sem = Arc::new(Semaphore::new(1));
+    let v: Vec<_> = (0..2)
+        .map(|_| {
+            let lock = lock.clone();
+            let sem = sem.clone();
+            race::spawn(move || {
+                for _ in 0..100 {
+                    RACY.write(RACY.read() + 1); // 보호되지 않음
+
+                    let _guard = lock.lock().unwrap();
+                    LOCKED.write(LOCKED.read() + 1);
+                    drop(_guard);
+
+                    sem.wait();
+                    SEM.write(SEM.read() + 1);
+                    sem.post();
+                }
+            })
+        })
+        .collect();
+    for th in v {
+        th.join().unwrap();
+    }
+
+    // join 이후의 읽기는 두 스레드의 쓰기보다 나중이므로 레이스가 아니다.
+    println!("RACY = {}, LOCKED = {}, SEM = {}", RACY.read(), LOCKED.read(), SEM.read());
+    for var in ["RACY", "LOCKED", "SEM"] {
+        println!("{}: {} race(s)", var, race::take_races(var).len());
+    }
+    if !race::enabled() {
+        println!("race detection is disabled (build with --features race)");
+    }
+}
+// 레이스 검출은 실제로 값이 어긋났는지와 상관없이 happens-before 관계만 보고 판단한다. 따라서 스레드가 우연히
+// 겹치지 않고 실행되어 RACY의 값이 200이 되더라도 레이스는 보고된다. 반대로 일반 메모리 읽기/쓰기와 fence만으로 만든
+// 락은 검출기가 동기화로 인식하지 못하므로 락 내부의 변수 자체가 레이스로 보고된다. BakeryLock은 획득과 해제 때
+// SyncClock을 갱신해서 검출기에 알려 준다.
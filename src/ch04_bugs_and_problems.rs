@@ -12,11 +12,13 @@ use crate::ch03_synchronous_processing01::{spinlock_acquire, spinlock_release};
 /// 식사하는 철학자 문제
 /// 철학자는 포크 2개를 동시에 들어야 본인 앞의 음식을 먹을 수 있음.
 ///
+/// ```text
 ///     철학자 1───────┬───────철학자 4
 ///      │  (음식)  포크 1  (음식)  │
 ///      ├── 포크 2       포크 4 ──┤
 ///      │  (음식)  포크 3  (음식)  │
 ///     철학자 2───────┴───────철학자 3
+/// ```
 /// 1) 왼쪽 포크가 비기를 기다렸다가 왼쪽 포크를 사용할 수 있는 상태가 되면 포크를 듬
 /// 2) 오른쪽 포크가 비기를 기다렸다가 오른쪽 포크를 사용할 수 있는 상태가 되면 포크를 듬
 /// 3) 식사를 함.
@@ -68,6 +70,41 @@ pub fn func_144p() {
 // 철학자 두명 모두 식사할 수도 있지만, c0과 c1을 서로 가져갔을 경우 데드락이 발생한다.
 // 주의 Arc::clone()은 deep copy 아닌 참조 횟수를 증가 시킴.
 
+/// func_144p는 운 나쁜 타이밍에서만 데드락이 발생한다. 두 철학자가 첫 번째 포크를 든 뒤 Barrier로 서로를 기다리게
+/// 해서 "둘 다 왼쪽 포크를 든 상태"를 항상 만들어보자.
+// #[test]
+pub fn func_144p_2() {
+    use std::sync::{Arc, Barrier, Mutex};
+    use std::thread;
+
+    let c0 = Arc::new(Mutex::new(()));
+    let c1 = Arc::new(Mutex::new(()));
+    let barrier = Arc::new(Barrier::new(2)); // 두 철학자가 첫 번째 포크를 들 때까지 대기
+
+    let (c0_p0, c1_p0, b0) = (c0.clone(), c1.clone(), barrier.clone());
+
+    // 철학자 1
+    let p0 = thread::spawn(move || {
+        let _n1 = c0_p0.lock().unwrap();
+        b0.wait(); // 철학자 2가 c1을 들 때까지 기다림
+        let _n2 = c1_p0.lock().unwrap(); // 데드락
+        println!("0: eating");
+    });
+
+    // 철학자 2
+    let p1 = thread::spawn(move || {
+        let _n1 = c1.lock().unwrap();
+        barrier.wait();
+        let _n2 = c0.lock().unwrap(); // 데드락
+        println!("1: eating");
+    });
+
+    p0.join().unwrap();
+    p1.join().unwrap();
+}
+// Barrier::wait은 지정한 수의 스레드가 모두 도착할 때까지 블록한다. 두 철학자 모두 첫 번째 포크를 든 채로 Barrier를
+// 통과하므로 두 번째 포크는 항상 상대가 들고 있으며, 어떤 인터리빙에서도 데드락이 된다.
+
 /// RW락은 특히나 데드락을 주의해야 함. 다음 예제는 B.Quin 등이 보고한 데드락을 발생시키는 예다.
 /// Rust로 구현된 앱에서실제로 발견된 버그이기도 하다.
 // #[test]
@@ -139,6 +176,7 @@ pub fn func_147p_2() {
     let val = Arc::new(RwLock::new(true));
 
     let t = thread::spawn(move || {
+        #[allow(let_underscore_lock)] // 즉시 해제되는 것을 보이기 위한 예제이므로 허용
         let _ = val.read().unwrap(); // Read락의 값이 즉시 파기되고 락이 해제됨. Rust는 _라는 변수에 저장된 값을 즉시 파기함.
         *val.write().unwrap() = false; // Write락 획득
         println!("not deadlock");
@@ -177,6 +215,60 @@ pub fn func_147p_2() {
 //
 // NOTE_ 엄밀하게 생각하면 데드락이 발생하는 스테이트 머신도 굶주림을 일으키는 스테이트 머신에 해당함
 
+/// 위에서 설명한 "왼쪽 포크를 들고 오른쪽 포크를 못 들면 내려놓는" 철학자를 구현하고 progress_monitor로 측정해보자.
+/// 식사가 진행(progress), 포크를 들거나 내려놓는 것이 상태 전이(state_change)이다. 감시 스레드는 window마다 진행하지
+/// 못한 철학자(굶주림)와 포크만 들었다 놨다 하는 상태(라이브락)를 출력하고, 종료 시 Jain index로 공평성을 요약한다.
+// #[test]
+pub fn func_149p() {
+    use crate::progress_monitor::ProgressMonitor;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    const NUM_MEALS: usize = 1_000;
+
+    let monitor = ProgressMonitor::new(Duration::from_millis(100));
+    let c0 = Arc::new(Mutex::new(()));
+    let c1 = Arc::new(Mutex::new(()));
+    let deadline = Instant::now() + Duration::from_secs(2);
+
+    let mut v = Vec::new();
+    for i in 0..2 {
+        // 철학자 0은 c0 -> c1, 철학자 1은 c1 -> c0 순서로 포크를 든다.
+        let (left, right) = if i == 0 {
+            (c0.clone(), c1.clone())
+        } else {
+            (c1.clone(), c0.clone())
+        };
+        let h = monitor.register(&format!("philosopher {}", i));
+        let t = thread::spawn(move || {
+            let mut meals = 0;
+            while meals < NUM_MEALS && Instant::now() < deadline {
+                let _l = left.lock().unwrap(); // 왼쪽 포크를 듦
+                h.state_change();
+                match right.try_lock() { // 오른쪽 포크를 들 수 없으면 왼쪽 포크를 내려놓고 처음부터
+                    Ok(_r) => {
+                        meals += 1;
+                        h.progress(); // 식사
+                    }
+                    Err(_) => h.state_change(),
+                }
+            }
+            h.done();
+        });
+        v.push(t);
+    }
+
+    for t in v {
+        t.join().unwrap();
+    }
+
+    let summary = monitor.shutdown();
+    println!("livelock alerts = {}", summary.alerts.len());
+}
+// 타이밍에 따라 두 철학자가 같은 횟수만큼 식사하기도 하고(jain index ≒ 1), 한쪽만 계속 식사하기도 한다(≒ 0.5).
+// try_lock이 계속 실패하는 구간이 window보다 길어지면 라이브락으로 출력된다.
+
 /// 4.3 은행원 알고리즘
 /// 데드락을 회피하기 위한 알고리즘으로 Dikstra가 고안한 Banker's algorithm이 유명하다. 은행원 알고리즘을 살펴보고
 /// 식사하는 철학자 문제에 은행원 알고리즘을 적용해 데드락이 발생하지 않고 철학자들이 식사를 마칠 수 있음을 증명해보자.
@@ -426,7 +518,7 @@ pub fn func_158p() {
 /// 러스트의 idioms에 따르면 다른 스레드에 공유 리소스를 전달할때만 클론하고, 동일 스레드 안에서는
 /// 클론해서 이용하지 않는다는 규칙이 있으니 지키자.
 // #[test]
-fn func_161p() {
+pub fn func_161p() {
     use std::sync::{Arc, Mutex};
 
     // Mutex를 Arc로 작성하고 클론
@@ -509,6 +601,112 @@ pub fn func_167p() -> Result<(), Box<dyn Error>>{
 // 싶을 때는 crossbeam-channel crate를 이용하면 좋다. crossbeam-channel은 multi-producer, multi-consumer의
 // 송수신을 실현하는 crate. 공식 문서를 살펴보자
 
+/// func_167p의 수신 스레드를 재사용할 수 있게 만든 SignalHub를 이용해보자. 데몬처럼 SIGHUP을 받으면 설정을 다시 읽고,
+/// SIGTERM을 받으면 작업 스레드를 정상 종료시킨다. 여기서는 kill 함수로 자기 자신에게 시그널을 보내 확인한다.
+/// $ kill -HUP <pid> 처럼 외부에서 보내도 같다. 작업 스레드가 꺼내기 전에 SIGHUP이 두 번 오면 한 번의 알림으로
+/// 합쳐지므로 설정은 한 번만 다시 읽는다. (설정의 세대, 합쳐진 SIGHUP 수)를 반환한다.
+// #[test]
+pub fn func_167p_2() -> Result<(u64, u64), Box<dyn Error>> {
+    use crate::signal_hub::{SignalHub, SIGHUP, SIGINT, SIGTERM, SIGUSR1};
+    use nix::sys::signal::{kill, Signal};
+    use nix::unistd::Pid;
+    use std::{thread, time::Duration};
+
+    let hub = SignalHub::new(&[SIGHUP, SIGTERM, SIGINT, SIGUSR1])?;
+    let reload = hub.subscribe(&[SIGHUP])?; // 설정 재로드 담당
+    let stop = hub.subscribe(&[SIGTERM, SIGINT])?; // 종료 담당(kill과 Ctrl-C)
+
+    let pid = Pid::this();
+    for i in 1..=2 {
+        kill(pid, Signal::SIGHUP)?;
+        // 커널은 보류 중인 같은 시그널을 합치므로 한 번씩 받은 것을 확인하고 보냄
+        while hub.count(SIGHUP) < i {
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+    kill(pid, Signal::SIGUSR1)?; // 구독자가 없는 시그널은 횟수만 기록됨
+
+    // 작업 스레드. SIGTERM이나 SIGINT를 받을 때까지 작업을 반복하고, SIGHUP을 받으면 설정을 다시 읽음
+    let worker = thread::spawn(move || {
+        let (mut generation, mut coalesced) = (0, 0);
+        loop {
+            if let Some(n) = reload.try_recv() {
+                generation += 1;
+                coalesced += n.count;
+                println!("reload config: generation = {}, coalesced = {}", generation, n.count);
+            }
+            if let Some(n) = stop.recv_timeout(Duration::from_millis(10)) {
+                println!("{}: graceful stop", if n.signal == SIGINT { "SIGINT" } else { "SIGTERM" });
+                break;
+            }
+        }
+        (generation, coalesced)
+    });
+
+    thread::sleep(Duration::from_millis(100));
+    kill(pid, Signal::SIGTERM)?; // 기본 동작(프로세스 종료) 대신 hub가 받음
+
+    let (generation, coalesced) = worker.join().unwrap();
+    println!(
+        "generation = {}, SIGHUP = {}, SIGUSR1 = {}, SIGTERM = {}",
+        generation,
+        hub.count(SIGHUP),
+        hub.count(SIGUSR1),
+        hub.count(SIGTERM)
+    );
+    hub.shutdown(); // 수신 스레드 정지
+    Ok((generation, coalesced))
+}
+// SignalHub가 살아 있는 동안은 SIGTERM, SIGINT의 기본 동작(종료)이 일어나지 않으므로, 종료 시그널을 구독한 쪽에서
+// 반드시 종료 처리를 해야 한다. hub를 shutdown(또는 drop)하면 다른 hub가 없는 시그널은 hub가 등록하기 전의 처리
+// 방법으로 돌아간다. signal_hook은 등록을 해제해도 자신의 핸들러를 남겨 두므로 signal_hub.rs에서 직접 SIG_DFL로
+// 되돌린다.
+
+/// 데드락 예제가 멈췄을 때 누가 무엇을 잡고 있는지 보기 위해 thread_registry를 이용해보자. 철학자 1이 포크 1을 잡은 채
+/// 다른 일을 기다리는 동안 철학자 0은 포크 0을 잡고 포크 1을 기다린다. 이때 SIGUSR1을 보내면 두 철학자의 상태와
+/// 잡고 있는 포크가 출력된다. 여기서는 끝까지 멈추지 않도록 철학자 1이 나중에 포크를 내려놓게 했다.
+// #[test]
+pub fn func_167p_3() -> Result<(), Box<dyn Error>> {
+    use crate::thread_registry::{self, TrackedMutex};
+    use nix::sys::signal::{kill, Signal};
+    use nix::unistd::Pid;
+    use std::sync::mpsc::channel;
+    use std::{thread, time::Duration};
+
+    let _dump = thread_registry::install_sigusr1_dump(None)?; // SIGUSR1로 스냅샷 출력
+
+    let c0 = Arc::new(TrackedMutex::new("fork 0", ()));
+    let c1 = Arc::new(TrackedMutex::new("fork 1", ()));
+    let (tx, rx) = channel::<()>();
+
+    let c1_p1 = c1.clone();
+    let p1 = thread_registry::spawn("philosopher 1", move || {
+        let _n1 = c1_p1.lock();
+        thread_registry::waiting("release request", || rx.recv().unwrap()); // 포크 1을 잡은 채 대기
+    });
+
+    let p0 = thread_registry::spawn("philosopher 0", move || {
+        thread::sleep(Duration::from_millis(10));
+        let _n1 = c0.lock();
+        let _n2 = c1.lock(); // 철학자 1이 포크 1을 내려놓을 때까지 대기
+        println!("0: eating");
+    });
+
+    thread::sleep(Duration::from_millis(100));
+    kill(Pid::this(), Signal::SIGUSR1)?; // 덤프 요청
+    thread::sleep(Duration::from_millis(100));
+    print!("{}", thread_registry::snapshot()); // 시그널 없이 직접 얻을 수도 있음
+
+    tx.send(())?;
+    p1.join().unwrap();
+    p0.join().unwrap();
+    Ok(())
+}
+// 출력 예
+// #1 philosopher 1       up    0.200s  waiting for release request  held: [fork 1]
+// #2 philosopher 0       up    0.200s  waiting for fork 1           held: [fork 0]
+// 실제 데드락이라면 두 스레드가 서로 상대방이 잡은 락을 기다리는 순환이 스냅샷에 그대로 나타난다.
+
 /// 4.7 Memory barrier
 /// 현대 CPU에서는 반드시 기계어 명령 순서대로 처리를 수행하지는 않는다. 이런 실행 방법을 out-of-order실행이라 부른다.
 /// out-of-order 실행 이유는 파이프라인 처리 시 '단위 시간당 실행 명령 수(Instructions-Per-Second: IPS)'를 높이기
@@ -524,6 +722,7 @@ pub fn func_167p() -> Result<(), Box<dyn Error>>{
 ///
 /// 다음 그림은 lock용 명령을 넘어 out-of-order 실행이 되었다고 했을 때 일어나는 문제를 보여준다.
 ///
+/// ```text
 ///               ┆      lock 획득        ┆
 ///               ┆<-------------------->┆
 ///               ┆ read v    write(v+1) ┆
@@ -539,6 +738,7 @@ pub fn func_167p() -> Result<(), Box<dyn Error>>{
 ///                               ┆ read v     write(v+1) ┆
 ///                               ┆<--------------------->┆
 ///                               ┆       lock 획득        ┆
+/// ```
 ///
 /// 여기서 프로세스 A와 B는 공유 변수에 접근하기 위해 lock을 획득하고, lock 획득 중에 공유 변수를 증가한다고 가정한다.
 /// 여기서 만약 프로세스 B의 read 명령이 lock용 명령 이전에 실행되면 그림에서 보는 것과 같이 시간적으로 이전의 공유 변수의
@@ -584,12 +784,14 @@ pub fn func_172p() {
     struct SpinLock<T> {
         lock: AtomicBool, // lock용 공유 변수
         data: UnsafeCell<T>, // 보호 대상 데이터
+        site: crate::instrument::Site, // lockstat, race, timeline, chaos 계측 훅(instrument.rs)
     }
 
     // 락 해제 및 락 중에 보호 대상 데이터를 조작하기 위한 type. 이 타입의 값이 스코프로부터 제외되었을 때 자동적으로
     // 락이 해제되지만 락을 해제하기 위해 SpinLock type의 참조(라이프타임)를 유지하고 있다.
     struct SpinLockGuard<'a, T> {
         spin_lock: &'a SpinLock<T>,
+        held: crate::lockstat::Held,
     }
 
     impl<T> SpinLock<T> {
@@ -597,16 +799,21 @@ pub fn func_172p() {
             SpinLock {
                 lock: AtomicBool::new(false),
                 data: UnsafeCell::new(v),
+                site: crate::instrument::Site::new("SpinLock", "SpinLock::lock"),
             }
         }
 
         // lock을 수행하는 lock 함수. TTAS에 의해 lock용 공유 변수가 false가 되어 lock이 해제되는 것을 기다린다.
         // 공유 변수가 false인 경우에는 memory ordering에 Acquire를 지정하여 아토믹하게 공유 변수를 true로 설정한다.
         fn lock(&self) -> SpinLockGuard<T> {
+            let mut wait = self.site.on_wait_begin();
             loop {
 
                 // lock용 공유 변수(SpinLock의 AtomicBool)가 false가 될 때까지 대기
-                while self.lock.load(Ordering::Relaxed) {}
+                while self.lock.load(Ordering::Relaxed) {
+                    wait.on_spin();
+                }
+                crate::chaos::point(); // 빈 것을 확인한 뒤 CAS 전에 끼어들 기회
 
                 // lock용 공유 변수를 아토믹하게 씀
                 if let Ok(_) = self.lock
@@ -618,8 +825,9 @@ pub fn func_172p() {
                 {
                     break; // self.lock.compare_exchange_weak()가 failure(Err)가 아닌 success(Ok)일 때
                 }          // loop를 끝냄. falure라면 루프를 다시 돌음.
+                wait.on_spin();
             }
-            SpinLockGuard { spin_lock: self } // lock 획득에 성공하면 루프를 벗어나 SpinLockGuard type의 값에
+            SpinLockGuard { spin_lock: self, held: wait.on_acquired() } // lock 획득에 성공하면 루프를 벗어나 SpinLockGuard type의 값에
         }                                     // 자신의 참조를 전달해 lock 획득 처리를 종료한다.
     }
     // SpinLock type은 스레드 사이에서 공유 가능하도록 지정
@@ -631,6 +839,7 @@ pub fn func_172p() {
     // 자동으로 락 해제. 락 해제를 잊을 경우 방지. 락 해제에 필요한 memory ordering은 Release이므로 false 기록시 지정됨.
     impl<'a, T> Drop for SpinLockGuard<'a, T> {
         fn drop(&mut self) {
+            self.spin_lock.site.on_released(self.held);
             self.spin_lock.lock.store(false, Ordering::Release); // drop되면 Release ordering
         }                                                                 // 방식으로 false를 store함.
     }
@@ -695,4 +904,37 @@ pub fn func_172p() {
 // 이것은 얼핏 보기에는 번거로워 보이지만 Memory Ordering을 지정함으로써 서로 다른 CPU 아키텍처에서
 // '최적의 코드'를 컴파일러가 생성해주므로 Assembly를 쓰는 것보다 범용성이 높아진다!!!!!
 // ############################################################################################
-// 이 예에서는 스핀락을 이용했지만 단순히 횟수를 셀 뿐이라면 Relaxed로 충분한 것을 알 수 있다.
\ No newline at end of file
+// 이 예에서는 스핀락을 이용했지만 단순히 횟수를 셀 뿐이라면 Relaxed로 충분한 것을 알 수 있다.
+/// 리오더링이 실제로 일어나는지 리트머스 테스트(litmus.rs)로 확인해보자. 각 테스트를 Relaxed로 실행한 뒤, 리오더링을
+/// 막는 Ordering(또는 fence)으로 다시 실행해 결과 분포를 비교한다.
+// #[test]
+pub fn func_172p_2() {
+    use crate::litmus::{run, Config, Test};
+    use std::sync::atomic::Ordering;
+
+    for test in [Test::Sb, Test::Mp, Test::Lb, Test::Iriw] {
+        let relaxed = Config::new(test); // 기본 2만 회. LITMUS_ITERATIONS=1000000으로 백만 회
+        let report = run(relaxed);
+        print!("{}", report);
+
+        // SB와 IRIW는 SeqCst가 아니면 막을 수 없고, MP와 LB는 Release/Acquire로 충분하다.
+        let strong = match test {
+            Test::Sb | Test::Iriw => Config {
+                store: Ordering::SeqCst,
+                load: Ordering::SeqCst,
+                ..relaxed
+            },
+            Test::Mp | Test::Lb => Config {
+                store: Ordering::Release,
+                load: Ordering::Acquire,
+                ..relaxed
+            },
+        };
+        let report = run(strong);
+        print!("{}", report);
+        assert_eq!(report.weak_count(), 0);
+    }
+}
+// x86-64에서 실행하면 Relaxed인 SB에서만 weak 결과(r0 = r1 = 0)가 관찰된다. x86-64는 TSO이므로 store buffer에 의한
+// W->R 리오더링만 일어나기 때문이다. 이때 mov 명령으로 컴파일되는 Release/Acquire로는 SB를 막을 수 없고, xchg나
+// mfence가 생성되는 SeqCst가 필요하다. AArch64에서 실행하면 MP와 LB에서도 weak 결과가 관찰될 수 있다.
@@ -49,7 +49,7 @@ pub fn func_178p() {
         let mut buf = String::new();
         reader.read_line(&mut buf).unwrap();
         println!("1: {}", writer.buffer().len());
-        writer.write(buf.as_bytes()).unwrap(); // writer에 byte code로 쓰고
+        writer.write_all(buf.as_bytes()).unwrap(); // writer에 byte code로 쓰고
         println!("2: {}", writer.buffer().len());
         writer.flush().unwrap(); // 버퍼링되어 있는 데이터를 모두 송신함
         println!("3: {}", writer.buffer().len());
@@ -177,7 +177,7 @@ pub fn func_181p() {
                 print!("read: fd = {}, buf = {}", fd, buf);
 
                 // n이 0이 아닐 경우 읽은 데이터를 그대로 쓴다.
-                writer.write(buf.as_bytes()).unwrap();
+                writer.write_all(buf.as_bytes()).unwrap();
                 writer.flush().unwrap();
             }
         }
@@ -349,12 +349,14 @@ pub fn func_186p() {
 /// 구현에 앞서 선행되어야 할 사전 지식이 있다. 먼저 구현할 역할을 알아보자.
 /// 역할은 크게 Executor, Task, Waker 세가지로 나뉜다.
 ///
+/// ```text
 ///                                wake
 /// Executor <------- 실행 Queue <------- Waker[Task 정보, ...]
 ///      \                                      /
 ///  poll \                                   /
 ///         ↘                               ↙
 ///       Task[Future[Future, Future, ...], ...]
+/// ```
 ///
 /// - Task가 스케줄링의 대상이 되는 계산의 실행 단위인 '프로세스'에 해당한다.
 /// - Executor는 실행 가능한 Task를 적당한 순서로 실행(Task 안의 Future를 poll)
@@ -363,98 +365,24 @@ pub fn func_186p() {
 /// 이 장에서는 Waker와 Task를 동일 type으로 구현한다.
 // #[test]
 pub fn func_189p() {
-    use futures::future::{BoxFuture, FutureExt};
-    use futures::task::{waker_ref, ArcWake};
+    use crate::runtime::Executor; // Task, Executor, Spawner는 runtime.rs에 정의되어 있다.
     use std::future::Future;
     use std::pin::Pin;
-    use std::sync::mpsc::{sync_channel, Receiver, SyncSender}; // 통신 채널을 위한 함수와 타입. 채널을
-    // 경유하면 스레드 사이에서 데이터 송수신을 수행할 수 있다. Rust에서는 많은 채널 구현에서 송신단과 수신단을 구별하며,
-    // Receiver와 SyncSender type이 수신과 송신용 endpoint의 type이 된다. mpsc는 말 그대로 송신은 여러 스레드에서.
-    // 수신은 단일 스레드에서만 가능한 채널이다.
-    use std::sync::{Arc, Mutex};
     use std::task::{Context, Poll};
 
-    // 간략화를 위해 Task 자체를 Waker로 구현
-    struct Task {
-        // 실행하는 코루틴
-        future: Mutex<BoxFuture<'static, ()>>, // 실행할 코루틴(Future). Future의 실행을 완료할 때까지
-                                               // Executor가 실행을 수행한다.
-        // Executor에 스케줄링하기 위한 채널
-        sender: SyncSender<Arc<Task>>, // Executor로 Task를 전달하고 스케줄링을 수행하기 위한 채널
-    }
-
-    impl ArcWake for Task {
-        fn wake_by_ref(arc_self: &Arc<Self>) { // 자신의 Arc 참조를 Executor로 송신하고 스케줄링한다.
-            // 자신을 스케줄링
-            let self0 = arc_self.clone(); // 송신은 여러 스레드에서 할 것이기 때문에 참조 카운트 업
-            arc_self.sender.send(self0).unwrap();
-        }
-    }
-    // 이렇게 Task는 실행할 코루틴을 저장하고 자신을 스케줄링 가능하도록 ArcWake trait을 실행한다. 스케줄링은
-    // 단순히 Task로의 Arc 참조를 채널로 송신(실행 Queue에 넣음)한다.
-
-    // Task의 실행을 수행하는 Executor를 구현해보자. 여기서 구현한 Executor는 단일 채널에서 실행 가능한 Task를 받아
-    // Task 안의 Future를 poll하는 단순한 것이다.
-    struct Executor { // Executor type은 단순히 Task를 송수신하는 채널(실행 Queue)의 endpoint를 저장한다.
-        // 실행 Queue
-        sender: SyncSender<Arc<Task>>,
-        receiver: Receiver<Arc<Task>>,
-    }
-
-    impl Executor {
-        fn new() -> Self {
-            // 채널 생성. Queue의 사이즈는 최대 1024
-            let (sender, receiver) = sync_channel(1024);
-            Executor {
-                sender: sender.clone(), // mp 다중 송신. 참조 증가
-                receiver, // sc 단일 수신
-            }
-        }
-
-        // 새롭게 Task를 생성하고 실행 Queue에 넣기위한 객체를 반환함. spawn 함수에 해당하는 작동을 수행하기 위한 객체.
-        fn get_spawner(&self) -> Spawner {
-            Spawner {
-                sender: self.sender.clone(), // 참조 증가.
-            }
-        }
-
-        fn run(&self) { // 채널에서 Task를 수신해서 순서대로 실행한다. 이번 구현에서는 Task와 Waker가 같으므로
-                        // Task에서 Waker를 생성하고 Waker에서 Context를 생성한 뒤 context를 인수로 poll() 호출
-            while let Ok(task) = self.receiver.recv() {
-                // context 생성
-                let mut future = task.future.lock().unwrap();
-                let waker = waker_ref(&task); // 수신한 task(future)로 waker_ref를 만듬
-                let mut ctx = Context::from_waker(&waker); // waker_ref로부터 context를 만듬
-                // poll을 호출해서 실행
-                let _ = future.as_mut().poll(&mut ctx);
-            }
-        }
-    }
-    // context는 실행 상태를 저장하는 객체이며 Future 실행 시 이를 전달해야 한다.
-    // Rust의 context는 내부에 Waker 및 _marker(lifetime을 명시해 수명을 불변으로 강제하여 분산 변경에 대한
-    // future를 보장함 (phantomdata))를 가지고 있다. 이번 구현에서는 Waker와 Task가 같으므로 context에서 Waker를
-    // 꺼낼 때 Task가 꺼내진다.
-
-    // 다음 코드는 Task를 생성하는 Spawner type의 정의와 구현이다. Spawner는 Future를 받아 Task로 감사서
-    // 실행 Queue에 넣기 위한(channel로 송신) type이다.
-    struct Spawner { // 단순히 실행 Queue에 추가하기 위해 channel의 송수신 endpoint를 저장할 뿐이다.
-        sender: SyncSender<Arc<Task>>,
-    }
-
-    impl Spawner {
-        // Task를 생성해서 실행 Queue에 추가한다. 이 함수는 Future를 받아 Box화해서 Task에 감싸서 실행 Queue에 넣는다.
-        fn spawn(&self, future: impl Future<Output = ()> + 'static + Send) {
-            let future = future.boxed();  // Future를 Box화
-            let task = Arc::new(Task {  // Task 생성
-                future: Mutex::new(future),
-                sender: self.sender.clone(),
-            });
-
-            // 실행 Queue에 인큐
-            self.sender.send(task).unwrap();
-        }
-    }
-
+    // Task, Executor, Spawner의 구현은 runtime.rs를 보자. 요점은 다음과 같다.
+    // - Task는 실행할 코루틴(Future)과 Executor로 자신을 보내기 위한 채널(SyncSender)을 저장하고, 간략화를 위해
+    //   Task 자체가 ArcWake trait을 구현해 Waker가 된다. 스케줄링(wake)은 단순히 Task로의 Arc 참조를 채널로
+    //   송신(실행 Queue에 넣음)하는 것이다. 채널은 std::sync::mpsc::sync_channel로 만들며, mpsc는 말 그대로 송신은
+    //   여러 스레드에서, 수신은 단일 스레드에서만 가능한 채널이다.
+    // - Executor는 실행 Queue의 송수신 endpoint를 저장하고, run에서 Task를 수신해서 순서대로 실행한다. Task와
+    //   Waker가 같으므로 Task에서 Waker를 생성하고 Waker에서 Context를 생성한 뒤 context를 인수로 poll을 호출한다.
+    //   context는 실행 상태를 저장하는 객체이며 Future 실행 시 이를 전달해야 한다. Rust의 context는 내부에 Waker 및
+    //   _marker(lifetime을 명시해 수명을 불변으로 강제하여 분산 변경에 대한 future를 보장함 (phantomdata))를 가지고
+    //   있다. 이번 구현에서는 Waker와 Task가 같으므로 context에서 Waker를 꺼낼 때 Task가 꺼내진다.
+    // - Spawner는 Future를 받아 Box화해서 Task로 감싸서 실행 Queue에 넣는(channel로 송신) type이다.
+    // 지금의 runtime.rs는 성능을 위해 Task를 Slab에 두고 Task 번호를 실행 Queue에 넣으며, Waker는 RawWakerVTable로
+    // 직접 만든다. 위 구현과의 비교는 func_189p_3을 보자.
 
     // 실행을 위한 구조체, impl block
     struct Hello { // 함수의 상태와 변수를 저장하는 Hello type 정의.
@@ -539,6 +467,211 @@ pub fn func_189p() {
 // 자동 실행된다. 스케줄링 실행을 수행하면 프로그래머가 코루틴 호출을 고려할 필요가 없으며, 자동으로 코루틴을 실행할
 // 수 있게 된다.
 
+/// runtime.rs의 Executor는 Task를 Slab에 두고 실행 Queue(RunQueue)에는 Slab의 번호만 넣는다. Spawner를 다른 스레드로
+/// 보낼 수 있으므로 Rc나 RefCell을 가지는 future(Send가 아닌)는 spawn할 수 없으며, 한 스레드에서만 실행하는 경우에도
+/// wake마다 Task 헤더의 상태 워드를 아토믹하게 바꾸고 RunQueue의 Mutex를 잡는다. LocalExecutor는 Task를 RefCell에 두고
+/// Task 번호만 큐에 넣는다. 여러 Task가 여러 번 양보(yield)할 때의 poll/wake 비용을 비교해보자.
+// #[test]
+pub fn func_189p_2() {
+    use crate::runtime::{spawn_local, Executor, LocalExecutor};
+    use std::cell::RefCell;
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::rc::Rc;
+    use std::task::{Context, Poll};
+    use std::time::Instant;
+
+    const TASKS: usize = 100;
+    const YIELDS: usize = 1000;
+
+    // 한 번 Pending을 반환하고 자신을 wake하는 future
+    struct Yield(bool);
+
+    impl Future for Yield {
+        type Output = ();
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+            if self.0 {
+                return Poll::Ready(());
+            }
+            self.0 = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+
+    let polls = (TASKS * (YIELDS + 1)) as f64;
+
+    let executor = Executor::new();
+    let spawner = executor.get_spawner();
+    let start = Instant::now();
+    for _ in 0..TASKS {
+        spawner.spawn(async {
+            for _ in 0..YIELDS {
+                Yield(false).await;
+            }
+        });
+    }
+    executor.run();
+    let elapsed = start.elapsed();
+    println!("{:>14}: {:>6.1} ns/poll ({:?})", "Executor", elapsed.as_nanos() as f64 / polls, elapsed);
+
+    // Rc<RefCell>로 상태를 공유하는 Task. Executor에서는 컴파일 에러가 된다.
+    let executor = LocalExecutor::new();
+    let count = Rc::new(RefCell::new(0));
+    let start = Instant::now();
+    let c = count.clone();
+    executor.block_on(async move {
+        for _ in 0..TASKS {
+            let c = c.clone();
+            spawn_local(async move {
+                for _ in 0..YIELDS {
+                    Yield(false).await;
+                    *c.borrow_mut() += 1;
+                }
+            });
+        }
+    });
+    executor.run();
+    let elapsed = start.elapsed();
+    assert_eq!(*count.borrow(), TASKS * YIELDS);
+    println!("{:>14}: {:>6.1} ns/poll ({:?})", "LocalExecutor", elapsed.as_nanos() as f64 / polls, elapsed);
+}
+// Executor는 wake마다 상태 워드를 compare_exchange로 바꾸고 RunQueue에 Task 번호를 넣으며, 실행 루프는 Task 번호를
+// 하나 꺼낼 때마다 RunQueue의 락을 잡는다. LocalExecutor의 wake는 Task 번호를 Vec에 넣을 뿐이며, 실행 루프는 Vec을
+// 통째로 꺼내서 락 한 번으로 모아 둔 Task를 차례로 poll한다.
+
+/// poll/wake 마이크로벤치마크. 처음 구현(Arc<Task>, Mutex<Option<BoxFuture>>, Task마다 복제한 Sender, wake마다
+/// Arc를 채널로 송신)과 runtime.rs의 Executor(Task는 Slab의 Slot, Waker는 RawWakerVTable로 직접 구현, Task 헤더의
+/// 상태 워드로 중복된 wake를 합침)를 비교해보자.
+// #[test]
+pub fn func_189p_3() {
+    use crate::runtime::Executor;
+    use futures::future::{BoxFuture, FutureExt};
+    use futures::task::{waker_ref, ArcWake};
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::sync::mpsc::{channel, Receiver, Sender};
+    use std::sync::{Arc, Mutex};
+    use std::task::{Context, Poll};
+    use std::time::Instant;
+
+    const TASKS: usize = 100;
+    const YIELDS: usize = 1000;
+    const WAKES: usize = 8; // Pending을 반환하기 전의 wake 횟수
+
+    // 처음 구현의 Task, Executor
+    struct Task {
+        future: Mutex<Option<BoxFuture<'static, ()>>>,
+        sender: Sender<Arc<Task>>,
+    }
+
+    impl ArcWake for Task {
+        fn wake_by_ref(arc_self: &Arc<Self>) {
+            arc_self.sender.send(arc_self.clone()).unwrap();
+        }
+    }
+
+    struct ArcExecutor {
+        sender: Sender<Arc<Task>>,
+        receiver: Receiver<Arc<Task>>,
+    }
+
+    impl ArcExecutor {
+        fn new() -> Self {
+            // 합쳐지지 않은 wake가 모두 들어갈 수 있도록 크기 제한이 없는 채널을 이용한다.
+            let (sender, receiver) = channel();
+            ArcExecutor { sender, receiver }
+        }
+
+        fn spawn(&self, future: impl Future<Output = ()> + Send + 'static) {
+            let task = Arc::new(Task {
+                future: Mutex::new(Some(future.boxed())),
+                sender: self.sender.clone(),
+            });
+            self.sender.send(task).unwrap();
+        }
+
+        // 모든 Task가 완료할 때까지 실행. 완료한 Task로의 wake는 poll하지 않는다.
+        fn run(&self, mut live: usize) {
+            while live > 0 {
+                let task = self.receiver.recv().unwrap();
+                let mut future = task.future.lock().unwrap();
+                if let Some(mut f) = future.take() {
+                    let waker = waker_ref(&task);
+                    let mut ctx = Context::from_waker(&waker);
+                    if f.as_mut().poll(&mut ctx).is_pending() {
+                        *future = Some(f);
+                    } else {
+                        live -= 1;
+                    }
+                }
+            }
+        }
+    }
+
+    // wakes번 wake하고 한 번 Pending을 반환하는 future
+    struct Yield {
+        done: bool,
+        wakes: usize,
+    }
+
+    impl Future for Yield {
+        type Output = ();
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+            if self.done {
+                return Poll::Ready(());
+            }
+            self.done = true;
+            for _ in 0..self.wakes {
+                cx.waker().wake_by_ref();
+            }
+            Poll::Pending
+        }
+    }
+
+    async fn task(wakes: usize) {
+        for _ in 0..YIELDS {
+            Yield { done: false, wakes }.await;
+        }
+    }
+
+    for wakes in [1, WAKES] {
+        let report = |name: &str, start: Instant, stale: usize| {
+            let elapsed = start.elapsed();
+            println!(
+                "{:>4} (wake x{}): {:>6.1} ns/yield, 실행 Queue에 남은 항목 {:>6} ({:?})",
+                name,
+                wakes,
+                elapsed.as_nanos() as f64 / (TASKS * YIELDS) as f64,
+                stale,
+                elapsed
+            );
+        };
+
+        let executor = ArcExecutor::new();
+        let start = Instant::now();
+        for _ in 0..TASKS {
+            executor.spawn(task(wakes));
+        }
+        executor.run(TASKS);
+        report("Arc", start, executor.receiver.try_iter().count());
+
+        let executor = Executor::new();
+        let spawner = executor.get_spawner();
+        let start = Instant::now();
+        for _ in 0..TASKS {
+            spawner.spawn(task(wakes));
+        }
+        executor.run();
+        report("Slab", start, 0); // 완료한 Task는 실행 Queue에 없다.
+    }
+}
+// 처음 구현은 wake마다 Arc를 복제해서 채널로 보내고, poll마다 Mutex를 잡는다. 같은 poll 안에서 여러 번 wake하면 그
+// 횟수만큼 실행 Queue에 들어가므로 큐가 계속 커지고, Task가 완료한 뒤에도 남은 항목을 꺼내서 확인해야 한다.
+// Slab 구현의 wake는 상태 워드의 CAS 한 번이며 이미 SCHEDULED나 NOTIFIED면 아무것도 하지 않으므로, wake 횟수와
+// 상관없이 실행 Queue에는 Task가 한 번만 들어간다. Waker의 clone/drop도 참조 카운트의 증감뿐이고 할당이 없다.
+// Slab 구현은 JoinHandle, panic 처리, 완료하지 않은 Task 수를 함께 관리하는데도 wake가 1회일 때 처음 구현과 비슷하다.
+
 /// 5.3 async/await
 ///
 /// https://rust-lang.github.io/async-book/01_getting_started/02_why_async.html
@@ -621,361 +754,31 @@ fn func_194p() {
 /// 다음 코드는 기본적으로는 epoll, TCP/IP, async/await을 이용하기 위해 필요한 것들을 조합한 것이다.
 // #[test]
 pub fn func_197() {
-    use futures::{
-        future::{BoxFuture, FutureExt},
-        task::{waker_ref, ArcWake},
-    };
-    use nix::{
-        errno::Errno,
-        sys::{
-            epoll::{
-                epoll_create1, epoll_ctl, epoll_wait,
-                EpollCreateFlags, EpollEvent, EpollFlags, EpollOp,
-            },
-            eventfd::{eventfd, EfdFlags}, // eventfd용 import. eventfd? 리눅스 고유의 이벤트 알림용 인터페이스.
-            // eventfd에서는 커널 안에 8bytes의 정수값을 저장하며 그 값이 0보다 큰 경우 읽기 event가 발생함. 값에 대한
-            // 읽기 쓰기는 read와 write 시스템 콜로 수행할 수 있다. 이번 구현에서는 IO Selector에 대한 알림에
-            // 이 eventfd를 이용해 보자.
-        },
-        unistd::write,
-    };
-    use std::{
-        collections::{HashMap, VecDeque},
-        future::Future,
-        io::{BufRead, BufReader, BufWriter, Write},
-        net::{SocketAddr, TcpListener, TcpStream},
-        os::unix::io::{AsRawFd, RawFd},
-        pin::Pin,
-        sync::{
-            mpsc::{sync_channel, Receiver, SyncSender},
-            Arc, Mutex,
-        },
-        task::{Context, Poll, Waker},
-    };
-
-    fn write_eventfd(fd: RawFd, n: usize) {
-        // usize를 *const u8로 변환
-        let ptr = &n as *const usize as *const u8;
-        let val = unsafe {
-            std::slice::from_raw_parts(
-                ptr, std::mem::size_of_val(&n))
-        };
-        // write 시스템 콜 호출
-        write(fd, &val).unwrap();
-    }
-    // 이번 구현에서는 이 함수를 이용해 eventfd에 1을 입력함으로써 IO Selector에 알리고,
-    // IOSelector는 읽기 후에 0을 입력함으로써 event 알림을 해제한다.
-
-    // Implementating IOSelector type
-    // 먼저 IOOps와 IOSelector type을 정의해보자
-    enum IOOps {
-        ADD(EpollFlags, RawFd, Waker), // epoll에 추가
-        REMOVE(RawFd),                 // epoll에서 삭제
-    }
-
-    struct IOSelector {
-        wakers: Mutex<HashMap<RawFd, Waker>>, // fd에서 waker
-        queue: Mutex<VecDeque<IOOps>>,        // IO Queue: 그림 5-3의 IO Queue의 변수
-        epfd: RawFd,  // epoll의 fd
-        event: RawFd, // eventfd의 fd
-    }
-    // IOOps type은 IOSelector에 Task와 파일 descriptor의 등록과 삭제를 수행하는 조작을 정의한 type이다.
-    // epoll의 감시대상으로 추가할 때는 ADD에 Flag, file descriptor(RawFd), Waker를 감싸서 IO Queue에 넣고,
-    // 삭제할 때는 file descriptor(RawFd)를 REMOVE에 감싸서 Queue에 넣는다.
-    // IO 다중화를 수행하기 위해서는 file descriptor에 event가 발생했을 때 이에 대응하는 Waker를 호출해야 하기 때문에
-    // file descriptor에서 Waker로의 맵을 저장해야 한다. IOSelector type은 그것을 수행하기 위한 정보를 저장하는
-    // type이 된다. Queue 변수가 [그림 5-3]의 IO Queue가 된다. 이 변수는 LinkedList가 아니라 VecDeque type으로
-    // 정의했는데 이는 계산량을 줄이기 위해서다. LinkedList type에서는 추가와 삭제를 할 때마다 메모리 확보와 해제를
-    // 수행하지만 VecDeque type은 내부적인 데이터 구조는 Vector List로 되어 있기 때문에 메모리 확보와 해제를 수행하는
-    // 횟수가 적어진다. 따라서 stack이나 queue로 이용한다면 VecDeque를 사용하는 편이 효율이 좋다. 단, LinkedList
-    // type과 같이 임의 위치로의 요소 추가 등은 할 수 없다는 제한이 있다.
-
-    // Implemetating IOSelector type
-    impl IOSelector {
-        fn new() -> Arc<Self> { // 1
-            let s = IOSelector {
-                wakers: Mutex::new(HashMap::new()),
-                queue: Mutex::new(VecDeque::new()),
-                epfd: epoll_create1(EpollCreateFlags::empty()).unwrap(),
-                // eventfd 생성
-                event: eventfd(0, EfdFlags::empty()).unwrap(),
-            };
-            let result = Arc::new(s); // result에 Arc로 감싼 s(IOSelector)를 할당하고
-            let s = result.clone(); // s에 result의 clone(참조)을 붙임 기존의 s는 없어짐
-                                                  // 모두 Arc로 감싸져 있음
-            // epoll용 스레드 생성. IOSelector에서는 별도 스레드에서 epoll에 의한 event 관리를 수행하기 위해
-            // epoll용 스레드를 생성하고 select 함수를 호출
-            std::thread::spawn(move || s.select());
-
-            result
-        }
-
-        // epoll로 감시하기 위한 함수. file descriptor의 epoll로의 추가와 Waker에 대한 대응을 수행한다.
-        fn add_event(
-            &self,
-            flag: EpollFlags, // epoll flag
-            fd: RawFd, // 감시 대상 file descriptor
-            waker: Waker,
-            wakers: &mut HashMap<RawFd, Waker>,
-        ) {
-            // 각 정의의 숏컷
-            let epoll_add = EpollOp::EpollCtlAdd;
-            let epoll_mod = EpollOp::EpollCtlMod;
-            let epoll_one = EpollFlags::EPOLLONESHOT;
-
-            // EPOLLONESHOT을 지정하여 일단 event가 발생하면
-            // 그 fd로의 event는 재설정하기 전까지 알림이 발생하지 않게 한다(oneshot. epoll로의 연관성이 삭제되는 것은 아님)
-            let mut ev = EpollEvent::new(flag | epoll_one, fd as u64);
-
-            // 감시 대상에 추가
-            if let Err(err) = epoll_ctl(self.epfd, epoll_add, fd, &mut ev) {
-                match err {
-                    nix::Error::Sys(Errno::EEXIST) => {
-                        // 이미 추가되어 있는 경우에 재설정. epoll_ctl을 호출해서 지정된 file descriptor를
-                        // 감시 대상으로 추가한다. 이미 추가되어 있는 경우에는 EpollCtlMod를 지정해 재설정한다.
-                        // 이것은 EPOLLONESHOT으로 비활성화된 event를 설정하기 위해 필요하다. 보다 효율적인 구현을
-                        // 하기 위해서는 이미 epoll에 추가했는지 기록해두고 시스템 콜 호출 횟수를 줄여야 하지만
-                        // EPOLLONESHOT의 이해를 위해 이렇게 구현했음.
-                        epoll_ctl(self.epfd, epoll_mod, fd, &mut ev).unwrap();
-                    }
-                    _ => {
-                        panic!("epoll_ctl: {}", err);
-                    }
-                }
-            }
-
-            assert!(!wakers.contains_key(&fd));
-            wakers.insert(fd, waker); // file descriptor와 Waker를 k, v쌍으로 wakers에 넣음
-        }
-
-        // epoll의 감시에서 삭제하기 위한 함수. 지정한 파일 디스크럽터를 epoll의 감시 대상에서 삭제한다. 여기서는
-        // 단순히 epoll_ctl 함수에 EpollCtlDel을 지정해 감시 대상에서 제외하고 file descriptor와 Waker의 관련성도
-        // 삭제할 뿐이다.
-        fn rm_event(&self, fd: RawFd, wakers: &mut HashMap<RawFd, Waker>) {
-            let epoll_del = EpollOp::EpollCtlDel;
-            let mut ev = EpollEvent::new(EpollFlags::empty(), fd as u64);
-            epoll_ctl(self.epfd, epoll_del, fd, &mut ev).ok();
-            wakers.remove(&fd);
-        }
-
-        fn select(&self) { // 전용 스레드로 file descriptor의 감시를 수행하기 위한 함수
-            // 각 정의의 숏컷
-            let epoll_in = EpollFlags::EPOLLIN;
-            let epoll_add = EpollOp::EpollCtlAdd;
-
-            // eventfd를 epoll의 감시 대상에 추가.
-            let mut ev = EpollEvent::new(epoll_in, self.event as u64);
-            epoll_ctl(self.epfd, epoll_add, self.event, &mut ev).unwrap();
-
-            let mut events = vec![EpollEvent::empty(); 1024];
-            //event 발생 감시
-            while let Ok(nfds) = epoll_wait(self.epfd, // 위에서 eventfd를 감시 대상에 추가하고 이벤트 발생 감시
-                                            &mut events, -1) {
-                let mut t = self.wakers.lock().unwrap();
-                for n in 0..nfds {
-                    if events[n].data() == self.event as u64 {
-                        // eventfd의 경우 file descriptor와 Waker를 등록 및 삭제 요구 처리
-                        let mut q = self.queue.lock().unwrap();
-                        while let Some(op) = q.pop_front() {
-                            match op {
-                                // 추가
-                                IOOps::ADD(flag, fd, waker) =>
-                                    self.add_event(flag, fd, waker, &mut t),
-                                IOOps::REMOVE(fd) => self.rm_event(fd, &mut t),
-                            }
-                        }
-                    } else {
-                        // 생성한 event가 file descriptor인 경우에는 Waker의 wake_by_ref를 호출해 실행 큐에 추가
-                        let data = events[n].data() as i32;
-                        let waker = t.remove(&data).unwrap();
-                        waker.wake_by_ref();
-                    }
-                }
-            }
-        }
-
-        // file descriptor 등록용 함수. file descriptor와 Waker를 IOSelector에 등록한다. 이것은 Future가
-        // IO Queue에 요청을 넣기 위해 이용됨.
-        fn register(&self, flags: EpollFlags, fd: RawFd, waker: Waker) {
-            let mut q = self.queue.lock().unwrap();
-            q.push_back(IOOps::ADD(flags, fd, waker));
-            write_eventfd(self.event, 1);
-        }
-
-        // file descriptor 삭제용 함수. file descriptor와 Waker의 연관성을 삭제함.
-        fn unregister(&self, fd: RawFd) {
-            let mut q = self.queue.lock().unwrap();
-            q.push_back(IOOps::REMOVE(fd));
-            write_eventfd(self.event, 1);
-        }
-    }
-    // 이렇게 IOSelctor type은 file descriptor와 Waker를 연관짓는다. IOSelector로의 요청은 queue 변수에
-    // 요청을 넣고 eventfd에 알린다. channel이 아닌 eventfd에서 수행하는 이유는 IOSelector는 epoll을 이용한
-    // file descriptor 감시도 수행해야 하기 때문이다.
-
-    // 다양한 Future 구현
-    // TCP connection request를 받아들이고 해당 connection에서의 데이터 '읽기'를 비동기화시키는 구현을 살펴보자.
-    // (쓰기에 대해서도 비동기화가 필요하지만 구현을 단순하게 하기 위해 생략)
-    // 비동기에 TCP의 listen, request를 받아들이기 위한 AsyncListener type을 구현해보자.
-    // 중요한 점은 connection request를 받아들일 때 이를 위한 함수를 직접 호출하는 것이 아니라 Future를 반환한다는 것이다.
-    // 즉 언젠가 Future의 request가 받아들여진다는 것을 의미한다.
-    struct AsyncListener { // async listen용 AsyncListener type은 내부적으로는 TcpListener와 앞에서 구현한
-                           // IOSelector type의 값을 가질 뿐이다.
-        listener: TcpListener,
-        selector: Arc<IOSelector>,
-    }
-
-    impl AsyncListener {
-        // TcpListener의 초기화 처리를 감싼 함수. listen용 함수정의로 non-blocking으로 설정해 비동기 프로그래밍을 가능케 함.
-        fn listen(addr: &str, selector: Arc<IOSelector>) -> AsyncListener {
-            // listen 주소 지정
-            let listener = TcpListener::bind(addr).unwrap();
-            // non-blocking으로 설정
-            listener.set_nonblocking(true).unwrap();
-
-            AsyncListener {
-                listener: listener,
-                selector: selector,
-            }
-        }
-
-        // connection request를 받아들이기 위한 Future 리턴. 실제로 요청을 받아들이지는 않고 이를 수행할 Future를 반환한다.
-        // 따라서 accept().await로 하면 실제 request를 비동기로 받아들인다.
-        fn accept(&self) -> Accept {
-            Accept { listener: self }
-        }
-    }
-
-    impl Drop for AsyncListener {
-        fn drop(&mut self) { // 객체 파기 처리이며 단순히 epoll에 대한 등록을 해제한다.
-            self.selector.unregister(self.listener.as_raw_fd());
-        }
-    }
-    // listen함수는 TcpListener type의 초기화 처리를 감싼 것(일반적으로 쓰는 new와 비슷)이지만 TcpListener를
-    // non-blocking화한 것이 특징이다(AsyncListener의 listener filed를 non-blocking 처리). 보통 connection을
-    // 받아들이는 함수는 blocking 호출이며 받아들일 connection이 도착할 때까지 해당 함수는 정지한다. 한편 non-blocking으로
-    // 설정하면 받아들일 connection이 없을 때는 error를 반환하고 즉시 함수를 종료한다. 함수 호출이 blocking되면 해당 스레드를
-    // 점유하게 되므로 동시에 실행하기 위해서는 non-blocking해서 필요할 때 호출할 수 있도록 해야 한다.
-    //
-    // 다음은 비동기로 request를 받아들이는 Future를 구현한 예. 이 Future에는 non-blocking으로 request를 받고,
-    // request를 받을 수 있을 때는 읽기와 쓰기 stream 및 주소를 반환하고 종료한다. 받아들일 connection이 없을 때는
-    // listen socket을 epoll에 감시 대상으로 추가하고 실행을 중단한다.
-    struct Accept<'a> {
-        listener: &'a AsyncListener,
-    }
-
-    impl<'a> Future for Accept<'a> {
-        // 반환값 type
-        type Output = (
-            AsyncReader,            // 비동기 읽기 스트림
-            BufWriter<TcpStream>,   // 쓰기 스트림
-            SocketAddr,             // 주소
-        );
-
-        fn poll(self: Pin<&mut Self>,
-                cx: &mut Context<'_>) -> Poll<Self::Output> {
-            // request를 non-blocking으로 받아들임
-            match self.listener.listener.accept() { // accept()를 호출해 connection을 받아들인다. 단 이는
-                                                    // 앞에서 설정했으므로 non-blocking으로 실행된다.
-                Ok((stream, addr)) => {
-                    // 요청을 받아들이면 읽기와 쓰기용 객체 스트림을 생성하고 객체 및 주소 반환
-                    let stream0 = stream.try_clone().unwrap();
-                    Poll::Ready((
-                        AsyncReader::new(stream0, self.listener.selector.clone()),
-                        BufWriter::new(stream),
-                        addr,
-                    ))
-                }
-                Err(err) => {
-                    // 받아들일 connection이 없는 경우 WouldBlock이 Err로 반환된다. WouldBlock이 반환되면
-                    // epoll의 감시 대상에 listen socket을 등록해 Pending을 반환하고 함수를 중단한다.
-                    if err.kind() == std::io::ErrorKind::WouldBlock {
-                        self.listener.selector.register(
-                            EpollFlags::EPOLLIN,
-                            self.listener.listener.as_raw_fd(),
-                            cx.waker().clone(),
-                        );
-                        Poll::Pending
-                    } else {
-                        panic!("accept: {}", err);
-                    }
-                }
-            }
-        }
-    }
-    // 이번 구현에서는 '읽기'에만 비동기로 대응하므로 읽기 스트림에 AsyncReader를 반환한다. 받아들일 connection이 없으면
-    // WouldBlock이 error로 반환된다. 반환된 후 epoll의 감시 대상에 listen socket을 추가해 Pending을 반환하고 함수를 중단한다.
-
-    // 비동기 읽기용 type을 구현한 예. 여기서는 단순히 TcpStream을 non-blocking으로 설정해서 1행을 읽는 Future를 반환한다.
-    struct AsyncReader {
-        fd: RawFd,
-        reader: BufReader<TcpStream>,
-        selector: Arc<IOSelector>,
-    }
-
-    impl AsyncReader {
-        fn new(stream: TcpStream, selector: Arc<IOSelector>) -> AsyncReader {
-            // TcpStream을 non-blocking으로 설정
-            stream.set_nonblocking(true).unwrap();
-            AsyncReader {
-                fd: stream.as_raw_fd(),
-                reader: BufReader::new(stream),
-                selector: selector
-            }
-        }
-
-        // 1행을 읽기 위한 Future 반환
-        fn read_line(&mut self) -> ReadLine {
-            ReadLine { reader: self }
-        }
-    }
-
-    impl Drop for AsyncReader {
-        fn drop(&mut self) {
-            self.selector.unregister(self.fd);
-        }
-    }
-
-    // 다음은 실제로 비동기 읽기를 수행하는 Future의 구현이다. 여기서는 Accept와 마찬가지로 non-blocking으로 읽기를
-    // 수행하여 읽기에 성공한 경우에는 결과를 반환하고 읽을 수 없는 경우에는 epoll의 감시 대상에 file descriptor를 등록한다
-    struct ReadLine<'a> {
-        reader: &'a mut AsyncReader,
-    }
-
-    impl<'a> Future for ReadLine<'a> {
-        // 반환값의 type
-        type Output = Option<String>;
-
-        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-            let mut line = String::new();
-            // 비동기 읽기
-            match self.reader.reader.read_line(&mut line) { // 1행 읽기를 비동기로 실행. 읽기 바이트 수가
-                Ok(0) => Poll::Ready(None),         // 0인 경우에는 connection 클로즈. None 반환
-                Ok(_) => Poll::Ready(Some(line)),   // 1행 읽기 성공시 읽은 행 반환
-                Err(err) => {
-                    // 읽을 수 없으면 epoll에 등록
-                    // 읽어야 할 데이터가 없는 경우에는 WoludBlock 에러가 반환되므로 epoll에 file descriptor를 감시 대상에
-                    // 등록하고 Pending을 반환한다.
-                    if err.kind() == std::io::ErrorKind::WouldBlock {
-                        self.reader.selector.register(
-                            EpollFlags::EPOLLIN,
-                            self.reader.fd,
-                            cx.waker().clone(),
-                        );
-                        Poll::Pending
-                    } else {
-                        Poll::Ready(None)
-                    }
-                }
-            }
-        }
-    }
-    // 여기까지가 connection을 받아들이고 데이터를 읽는 Future의 구현이다. 이들을 사용하면 동시서버를 보다 추상적으로 기술할 수 있다다.
+    use crate::runtime::{AsyncListener, Runtime}; // Executor, IOSelector 등은 runtime.rs에 정의되어 있다.
+    use std::io::Write;
+
+    // 구현은 runtime.rs를 보자. 요점은 다음과 같다.
+    // - IOSelector는 fd에서 Waker로의 맵과 IO Queue(그림 5-3)를 가진다. register/unregister는 IO Queue에 요청을 넣고
+    //   eventfd에 1을 써서 알린다. eventfd는 리눅스 고유의 이벤트 알림용 인터페이스로, 커널 안에 8bytes의 정수값을
+    //   저장하며 그 값이 0보다 큰 경우 읽기 event가 발생한다. channel이 아닌 eventfd를 이용하는 이유는 IOSelector는
+    //   epoll을 이용한 file descriptor 감시도 수행해야 하기 때문이다.
+    // - IOSelector는 전용 스레드에서 epoll_wait을 호출하고, eventfd의 event면 IO Queue의 요청을 처리하고 그 밖의 fd의
+    //   event면 대응하는 Waker의 wake_by_ref를 호출해 실행 큐에 Task를 넣는다. 감시 대상에는 EPOLLONESHOT을 지정하여
+    //   일단 event가 발생하면 그 fd로의 event는 재설정하기 전까지 알림이 발생하지 않게 한다.
+    // - IO Queue는 LinkedList가 아니라 VecDeque type인데 이는 계산량을 줄이기 위해서다. LinkedList type에서는 추가와
+    //   삭제를 할 때마다 메모리 확보와 해제를 수행하지만 VecDeque type은 내부적인 데이터 구조가 Vector List로 되어
+    //   있기 때문에 메모리 확보와 해제를 수행하는 횟수가 적어진다.
+    // - AsyncListener는 non-blocking으로 설정한 TcpListener를 가지며 accept는 connection request를 받아들이는 Future를
+    //   반환한다. 즉 accept().await로 하면 실제 request를 비동기로 받아들인다. 받아들일 connection이 없으면 WouldBlock이
+    //   반환되므로 listen socket을 epoll의 감시 대상에 등록하고 Pending을 반환해 중단한다. 함수 호출이 blocking되면
+    //   해당 스레드를 점유하게 되므로 동시에 실행하기 위해서는 non-blocking해서 필요할 때 호출할 수 있도록 해야 한다.
+    // - AsyncReader도 마찬가지로 TcpStream을 non-blocking으로 설정해서 1행을 읽는 Future(ReadLine)를 반환한다.
+    //   (쓰기에 대해서도 비동기화가 필요하지만 구현을 단순하게 하기 위해 생략)
 
     // async/await을 이용한 동시 echo server 구현
-    let executor = Executor::new();
-    let selector = IOSelector::new();
-    let spawner = executor.get_spawner();
+    let rt = Runtime::new();
+    let selector = rt.selector();
+    let spawner = rt.spawner();
 
     let server = async move { // 비동기 프로그래밍, 컴파일러에 의해 Future trait을 구현한 객체 생성됨.
         //  비동기 accept listener 생성. echo server용 TCP listen socket을 생성하고 로컬호스트의 10000번 포트 listen.
@@ -996,7 +799,7 @@ pub fn func_197() {
                 // connection별 처리. 1행씩 비동기 읽어서 응답
                 while let Some(buf) = reader.read_line().await {
                     print!("read: {}, {}", addr, buf);
-                    writer.write(buf.as_bytes()).unwrap();
+                    writer.write_all(buf.as_bytes()).unwrap();
                     writer.flush().unwrap();
                 }
                 println!("close: {}", addr);
@@ -1005,8 +808,7 @@ pub fn func_197() {
     };
 
     // Task를 생성하고 실행
-    executor.get_spawner().spawn(server);
-    executor.run();
+    rt.block_on(server);
     // 이와 같이 async/await을 이용하면 epoll같은 원시적 조작은 감춰지고, connection별 비동기 처리는 동기 프로그래밍과
     // 완전히 동일하게 기술할 수 있다. 이렇게 하면 가독성과 유지보수성이 높아진다.
     // Rust에서는 runtime에 coroutine이나 경량 스레드 등의 기능을 지원하지 않아 구현이 다소 번잡했지만 바텀으로 들어가볼
@@ -1016,143 +818,185 @@ pub fn func_197() {
     // 한편 Rust에서는 경량 스레드 같은 high-level 언어 기능에 의존하지 않고 async/await을 구현하고 있으므로
     // OS나 내장 소프트웨어 등에 쉽게 적용할 수 있다. 즉, 내장 소프트웨어, OS, 장치 드라이버 등 하드웨어에 가까운 소프트웨어를
     // async/await을 이용해 구현할 수 있다!!
+}
 
-
-
-
-    // 여기는 위에서 구현했던 비동기 서버 구현을 위한 struct, impl block들. async server에 재활용하기 위해 구현.
-    struct Task {
-        // 실행하는 코루틴
-        future: Mutex<BoxFuture<'static, ()>>, // 실행할 코루틴(Future). Future의 실행을 완료할 때까지
-        // Executor가 실행을 수행한다.
-        // Executor에 스케줄링하기 위한 채널
-        sender: SyncSender<Arc<Task>>, // Executor로 Task를 전달하고 스케줄링을 수행하기 위한 채널
-    }
-
-    impl ArcWake for Task {
-        fn wake_by_ref(arc_self: &Arc<Self>) { // 자신의 Arc 참조를 Executor로 송신하고 스케줄링한다.
-            // 자신을 스케줄링
-            let self0 = arc_self.clone(); // 송신은 여러 스레드에서 할 것이기 때문에 참조 카운트 업
-            arc_self.sender.send(self0).unwrap();
+/// 앞의 echo server를 runtime.rs의 단일 스레드 런타임, work-stealing 멀티스레드 런타임, 그리고 뒤에서 살펴볼 Tokio의
+/// 멀티스레드 런타임에서 각각 실행하고, 여러 클라이언트 스레드가 1행씩 보내고 응답을 기다리기를 반복할 때의 처리량을
+/// 비교해보자.
+// #[test]
+pub fn func_197_2() {
+    use crate::runtime::{AsyncListener, Runtime};
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::{SocketAddr, TcpStream};
+    use std::sync::{Arc, Barrier};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    const CLIENTS: usize = 8;
+    const LINES: usize = 1000;
+    const WORKERS: usize = 4;
+
+    // 클라이언트 스레드를 동시에 시작하고 모두 끝날 때까지의 시간
+    fn clients(addr: SocketAddr) -> Duration {
+        let barrier = Arc::new(Barrier::new(CLIENTS + 1));
+        let handles: Vec<_> = (0..CLIENTS)
+            .map(|_| {
+                let barrier = barrier.clone();
+                thread::spawn(move || {
+                    let mut stream = TcpStream::connect(addr).unwrap();
+                    stream.set_nodelay(true).unwrap();
+                    let mut reader = BufReader::new(stream.try_clone().unwrap());
+                    let mut line = String::new();
+                    barrier.wait();
+                    for i in 0..LINES {
+                        stream.write_all(format!("{}\n", i).as_bytes()).unwrap();
+                        line.clear();
+                        reader.read_line(&mut line).unwrap();
+                    }
+                })
+            })
+            .collect();
+        barrier.wait();
+        let start = Instant::now();
+        for h in handles {
+            h.join().unwrap();
         }
-    }
-    // 이렇게 Task는 실행할 코루틴을 저장하고 자신을 스케줄링 가능하도록 ArcWake trait을 실행한다. 스케줄링은
-    // 단순히 Task로의 Arc 참조를 채널로 송신(실행 Queue에 넣음)한다.
-
-    // Task의 실행을 수행하는 Executor를 구현해보자. 여기서 구현한 Executor는 단일 채널에서 실행 가능한 Task를 받아
-    // Task 안의 Future를 poll하는 단순한 것이다.
-    struct Executor { // Executor type은 단순히 Task를 송수신하는 채널(실행 Queue)의 endpoint를 저장한다.
-    // 실행 Queue
-    sender: SyncSender<Arc<Task>>,
-        receiver: Receiver<Arc<Task>>,
+        start.elapsed()
     }
 
-    impl Executor {
-        fn new() -> Self {
-            // 채널 생성. Queue의 사이즈는 최대 1024
-            let (sender, receiver) = sync_channel(1024);
-            Executor {
-                sender: sender.clone(), // mp 다중 송신. 참조 증가
-                receiver, // sc 단일 수신
-            }
-        }
+    let report = |name: &str, elapsed: Duration| {
+        let lines = (CLIENTS * LINES) as f64;
+        println!("{:>24}: {:>8.0} lines/s ({:?})", name, lines / elapsed.as_secs_f64(), elapsed);
+    };
 
-        // 새롭게 Task를 생성하고 실행 Queue에 넣기위한 객체를 반환함. spawn 함수에 해당하는 작동을 수행하기 위한 객체.
-        fn get_spawner(&self) -> Spawner {
-            Spawner {
-                sender: self.sender.clone(), // 참조 증가.
+    // 직접 구현한 런타임
+    for (name, rt) in [
+        ("runtime current_thread", Runtime::new()),
+        ("runtime multi_thread", Runtime::multi_thread(WORKERS)),
+    ] {
+        let listener = AsyncListener::listen("127.0.0.1:0", rt.selector());
+        let addr = listener.local_addr();
+        let spawner = rt.spawner();
+        let client = thread::spawn(move || clients(addr));
+        rt.block_on(async move {
+            let mut handles = Vec::new();
+            for _ in 0..CLIENTS {
+                let (mut reader, mut writer, _addr) = listener.accept().await;
+                handles.push(spawner.spawn(async move {
+                    while let Some(buf) = reader.read_line().await {
+                        writer.write_all(buf.as_bytes()).unwrap();
+                        writer.flush().unwrap();
+                    }
+                }));
             }
-        }
-
-        fn run(&self) { // 채널에서 Task를 수신해서 순서대로 실행한다. 이번 구현에서는 Task와 Waker가 같으므로
-            // Task에서 Waker를 생성하고 Waker에서 Context를 생성한 뒤 context를 인수로 poll() 호출
-            while let Ok(task) = self.receiver.recv() {
-                // context 생성
-                let mut future = task.future.lock().unwrap();
-                let waker = waker_ref(&task); // 수신한 task(future)로 waker_ref를 만듬
-                let mut ctx = Context::from_waker(&waker); // waker_ref로부터 context를 만듬
-                // poll을 호출해서 실행
-                let _ = future.as_mut().poll(&mut ctx);
+            for h in handles {
+                h.await.unwrap();
             }
-        }
-    }
-    // context는 실행 상태를 저장하는 객체이며 Future 실행 시 이를 전달해야 한다.
-    // Rust의 context는 내부에 Waker 및 _marker(lifetime을 명시해 수명을 불변으로 강제하여 분산 변경에 대한
-    // future를 보장함 (phantomdata))를 가지고 있다. 이번 구현에서는 Waker와 Task가 같으므로 context에서 Waker를
-    // 꺼낼 때 Task가 꺼내진다.
-
-    // 다음 코드는 Task를 생성하는 Spawner type의 정의와 구현이다. Spawner는 Future를 받아 Task로 감사서
-    // 실행 Queue에 넣기 위한(channel로 송신) type이다.
-    struct Spawner { // 단순히 실행 Queue에 추가하기 위해 channel의 송수신 endpoint를 저장할 뿐이다.
-    sender: SyncSender<Arc<Task>>,
-    }
-
-    impl Spawner {
-        // Task를 생성해서 실행 Queue에 추가한다. 이 함수는 Future를 받아 Box화해서 Task에 감싸서 실행 Queue에 넣는다.
-        fn spawn(&self, future: impl Future<Output = ()> + 'static + Send) {
-            let future = future.boxed();  // Future를 Box화
-            let task = Arc::new(Task {  // Task 생성
-                future: Mutex::new(future),
-                sender: self.sender.clone(),
-            });
-
-            // 실행 Queue에 인큐
-            self.sender.send(task).unwrap();
-        }
-    }
-
-
-    // 실행을 위한 구조체, impl block
-    struct Hello { // 함수의 상태와 변수를 저장하는 Hello type 정의.
-    state: StateHello, // Hello, World!에는 변수가 없으므로 함수의 실행 위치 상태만 필드로 가진다.
-    }
-
-    // 함수의 실행 상태를 나타내는 StateHello type.
-    enum StateHello {
-        HELLO, // 초기 상태는 Hello 상태고
-        WORLD, // Python version의 첫 번째 yield를 나타내는 상태가 WORLD 상태
-        END,   // 두 번째 yield를 나타내는 상태가 END 상태가 된다.
+        });
+        report(name, client.join().unwrap());
     }
 
-    impl Hello {
-        fn new() -> Self {
-            Hello {
-                state: StateHello::HELLO, // 초기 상태
+    // Tokio의 멀티스레드 런타임
+    {
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+        let rt = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(WORKERS)
+            .enable_all()
+            .build()
+            .unwrap();
+        let listener = rt.block_on(tokio::net::TcpListener::bind("127.0.0.1:0")).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let client = thread::spawn(move || clients(addr));
+        rt.block_on(async move {
+            let mut handles = Vec::new();
+            for _ in 0..CLIENTS {
+                let (mut socket, _addr) = listener.accept().await.unwrap();
+                handles.push(tokio::spawn(async move {
+                    let (r, mut w) = socket.split();
+                    let mut reader = tokio::io::BufReader::new(r);
+                    let mut line = String::new();
+                    while reader.read_line(&mut line).await.unwrap() > 0 {
+                        w.write_all(line.as_bytes()).await.unwrap();
+                        line.clear();
+                    }
+                }));
             }
-        }
+            for h in handles {
+                h.await.unwrap();
+            }
+        });
+        report("tokio multi_thread", client.join().unwrap());
     }
+}
+// 클라이언트가 응답을 기다린 뒤 다음 행을 보내므로 처리량은 한 행의 왕복 지연으로 정해진다. 단일 스레드 런타임은
+// IOSelector 스레드가 wake해서 RunQueue에 넣은 Task 번호를 꺼내 실행하고, 멀티스레드 런타임은 injector에서 꺼낸
+// Worker가 실행한다.
+// Tokio는 epoll을 Worker 스레드가 직접 호출하므로 IO 이벤트를 다른 스레드로 넘기는 비용이 없다.
+
+/// func_197의 echo server가 응답하지 않을 때 어느 Task가 무엇을 기다리는지 알아보자. runtime.rs의 Executor는 Task마다
+/// 상태, poll 횟수, poll에 걸린 시간, 마지막 wake 이후 지난 시간, 기다리는 fd 또는 타이머를 기록하며, Runtime::dump로
+/// 목록을 얻거나 install_sigusr1_dump로 SIGUSR1을 받을 때마다 출력할 수 있다.
+// #[test]
+pub fn func_197_3() -> Result<(), Box<dyn std::error::Error>> {
+    use crate::runtime::{install_sigusr1_dump, sleep, AsyncListener, Runtime};
+    use nix::sys::signal::{kill, Signal};
+    use nix::unistd::Pid;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpStream;
+    use std::thread;
+    use std::time::Duration;
+
+    let rt = Runtime::new();
+    let _dump = install_sigusr1_dump(&rt.spawner())?;
+    let listener = AsyncListener::listen("127.0.0.1:0", rt.selector());
+    let addr = listener.local_addr();
+    let (selector, spawner, dump) = (rt.selector(), rt.spawner(), rt.spawner());
+
+    // 1행을 주고받은 뒤 아무것도 보내지 않는 클라이언트와, 접속만 하고 아무것도 보내지 않는 클라이언트
+    let client = thread::spawn(move || -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let mut a = TcpStream::connect(addr)?;
+        let _b = TcpStream::connect(addr)?;
+        a.write_all(b"hello\n")?;
+        BufReader::new(a.try_clone()?).read_line(&mut String::new())?;
+        thread::sleep(Duration::from_millis(100));
+
+        println!("--- Spawner::dump");
+        print!("{}", dump.dump());
+        println!("--- SIGUSR1");
+        kill(Pid::this(), Signal::SIGUSR1)?;
+        thread::sleep(Duration::from_millis(100)); // 덤프 스레드가 출력할 때까지
+        Ok(()) // 연결을 닫으면 서버의 Task가 완료한다.
+    });
 
-    impl Future for Hello {
-        type Output = ();
-
-        // poll 함수가 실제 함수 호출(Python에서 h = hello()). 인수의 Pin type은 Box등과 같은 type(https://rust-lang.github.io/async-book/04_pinning/01_chapter.html)
-        // Pin type은 내부적인 메모리 복사로의 move를 할 수 없어서 주소 변경을 할 수 없는 type이지만 이것은 Rust 특유의 성질에 속한다.(unpinn을 구현해야함)
-        // _cx는 Waker 및 future의 내부구조부터 파악하고 뜯어 보길 바란다.
-        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
-            match (*self).state {
-                StateHello::HELLO => {
-                    print!("Hello, ");
-                    // WORLD 상태로 전이
-                    (*self).state = StateHello::WORLD;
-                    // 추가
-                    cx.waker().wake_by_ref(); // 자신을 실행 Queue에 넣음
-                    return Poll::Pending // 다시 호출 가능
-                }
-                StateHello::WORLD => {
-                    println!("World!");
-                    // END 상태로 전이
-                    (*self).state = StateHello::END;
-                    // 추가
-                    cx.waker().wake_by_ref(); // 자신을 실행 Queue에 넣음
-                    return Poll::Pending // 다시 호출 가능
-                }
-                StateHello::END => {
-                    return Poll::Ready(()) // 종료
+    rt.block_on(async move {
+        let mut handles = Vec::new();
+        for _ in 0..2 {
+            let (mut reader, mut writer, _addr) = listener.accept().await;
+            handles.push(spawner.spawn(async move {
+                while let Some(buf) = reader.read_line().await {
+                    writer.write_all(buf.as_bytes()).unwrap();
+                    writer.flush().unwrap();
                 }
+            }));
+        }
+        // 주기적으로 깨어나는 Task
+        let ticker = spawner.spawn(async move {
+            for _ in 0..3 {
+                sleep(Duration::from_millis(100), selector.clone()).await;
             }
+        });
+        for h in handles {
+            h.await.unwrap();
         }
-    }
+        ticker.await.unwrap();
+    });
+    client.join().unwrap().map_err(|e| e.to_string())?;
+    Ok(())
 }
+// 출력에서 접속만 한 클라이언트의 Task는 idle로 fd를 기다리고 있고, 마지막 wake가 오래 전이므로 상대가 보내지 않아서
+// 멈춰 있다는 것을 알 수 있다. 기다리는 대상은 poll이 Pending을 반환하기 직전에 IOSelector::register나 sleep이 기록하고,
+// 다음 poll을 시작할 때 지운다. block_on의 Task처럼 JoinHandle이나 채널을 기다리면 "-"가 된다. SIGUSR1의 덤프는 시그널
+// 수신 스레드에서 출력하므로 Executor의 스레드가 블록되어 있어도 출력된다.
 
 /// 5.4 async library
 /// Rust의 async/await을 이용한 비동기 라이브러리의 실질적 표준인 Tokio를 이용한 비동기를 알아보자. Rust에서 비동기 라이브러리는
@@ -1259,6 +1103,68 @@ pub fn func_213p() {
     }
 }
 
+/// sleep 같은 blocking 호출이 아니어도, 항상 준비된 IO만 반복하는 Task는 await에서 Pending을 반환하지 않으므로
+/// 제어권을 넘겨주지 않는다. runtime.rs의 Executor는 poll마다 준비된 연산의 예산을 주고, 예산을 다 쓰면 준비된 IO도
+/// Pending을 반환하게 해서 강제로 양보시킨다. 미리 많은 행을 보내 둔 connection을 읽는 Task와 yield_now로 양보하면서
+/// tick을 세는 Task를 같이 실행해보자.
+// #[test]
+pub fn func_213p_2() {
+    use crate::runtime::{yield_now, AsyncListener, Runtime, DEFAULT_BUDGET};
+    use std::io::Write;
+    use std::net::TcpStream;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+
+    const LINES: usize = 10_000;
+
+    for budget in [u32::MAX, DEFAULT_BUDGET] {
+        let rt = Runtime::new();
+        rt.set_budget(budget);
+        let listener = AsyncListener::listen("127.0.0.1:0", rt.selector());
+        let addr = listener.local_addr();
+        // 모든 행을 한 번에 보내고 닫는 클라이언트. 행은 소켓 버퍼에 들어간다.
+        thread::spawn(move || {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            let buf: String = (0..LINES).map(|i| format!("{}\n", i)).collect();
+            stream.write_all(buf.as_bytes()).unwrap();
+        })
+        .join()
+        .unwrap();
+
+        let spawner = rt.spawner();
+        let (done, ticks) = (Arc::new(AtomicBool::new(false)), Arc::new(AtomicUsize::new(0)));
+        let (d, t) = (done.clone(), ticks.clone());
+        spawner.spawn(async move {
+            while !d.load(Ordering::Relaxed) {
+                t.fetch_add(1, Ordering::Relaxed);
+                yield_now().await;
+            }
+        });
+        let (lines, ticks, metrics) = rt.block_on(async move {
+            let (mut reader, _writer, _addr) = listener.accept().await;
+            let start = ticks.load(Ordering::Relaxed);
+            let mut lines = 0;
+            while reader.read_line().await.is_some() {
+                lines += 1;
+            }
+            let metrics = spawner.metrics(); // 두 Task 모두 아직 완료하지 않았다.
+            done.store(true, Ordering::Relaxed);
+            (lines, ticks.load(Ordering::Relaxed) - start, metrics)
+        });
+        rt.run();
+
+        let budget = if budget == u32::MAX { "무제한".to_string() } else { budget.to_string() };
+        println!("budget = {}: {} lines, 읽는 동안의 tick = {}", budget, lines, ticks);
+        for m in metrics {
+            println!("  {:?}", m);
+        }
+    }
+}
+// 예산이 무제한이면 읽기 Task는 모든 행을 poll 한 번에 읽으므로 그동안 tick이 늘지 않는다. 예산이
+// DEFAULT_BUDGET(128)이면 128행마다 forced_yields가 늘고 그 사이에 tick Task가 실행된다. 예산은 준비된 연산을 세는
+// 것이므로 func_213p처럼 스레드를 블록하는 호출은 막을 수 없다.
+
 /// Tokio 같은 비동기 라이브러리를 사용할 때는 Mutex의 사용도 문제가 된다. Mutex는 일반적인
 /// std::sync::Mutex를 사용가능한 경우와 비동기 라이브러리가 제공하는 Mutex를 사용해야 하는 경우가 있다.
 /// std::sync::Mutex를 사용한 예. 공유 변수를 lock해서 증가시키는 간단한 예이다.
@@ -1297,6 +1203,62 @@ pub fn func_214p() {
     // 뛰어나다. 한편 lock을 획득한 상태에서 await을 수행하려면 비동기 라이브러리가 제공하는 Mutex를 사용해야 한다.
 }
 
+/// func_213p의 sleep이나 경합하는 std::sync::Mutex처럼 async 안에서 스레드를 블록하는 호출은 그 Task만이 아니라 같은
+/// 스레드의 모든 Task를 멈추지만, 코드만 봐서는 찾기 어렵다. runtime.rs의 Executor는 poll마다 시간을 재서 기준보다 오래
+/// 걸린 poll을 Task 번호, spawn한 위치와 함께 출력한다. strict 모드에서는 출력하는 대신 panic한다.
+// #[test]
+pub fn func_214p_2() {
+    use crate::runtime::Executor;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+    use std::sync::{Arc, Barrier, Mutex};
+    use std::thread;
+    use std::time::Duration;
+
+    let executor = Executor::new();
+    executor.set_slow_poll(Some(Duration::from_millis(10)), false);
+    let spawner = executor.get_spawner();
+
+    // async 안에서 일반 스레드용 sleep
+    spawner.spawn(async {
+        thread::sleep(Duration::from_millis(30));
+    });
+
+    // 다른 스레드가 50ms 동안 잡고 있는 Mutex를 async 안에서 기다림
+    let val = Arc::new(Mutex::new(0));
+    let barrier = Arc::new(Barrier::new(2));
+    let (v, b) = (val.clone(), barrier.clone());
+    let holder = thread::spawn(move || {
+        let mut n = v.lock().unwrap();
+        b.wait();
+        thread::sleep(Duration::from_millis(50));
+        *n += 1;
+    });
+    barrier.wait();
+    let v = val.clone();
+    spawner.spawn(async move {
+        *v.lock().unwrap() += 1;
+    });
+
+    // 금방 끝나는 poll은 출력하지 않는다.
+    spawner.spawn(async {});
+    executor.run();
+    holder.join().unwrap();
+    println!("val = {}", *val.lock().unwrap());
+
+    // strict 모드에서는 오래 걸린 poll이 끝난 뒤 run이 panic한다.
+    executor.set_slow_poll(Some(Duration::from_millis(10)), true);
+    spawner.spawn(async {
+        thread::sleep(Duration::from_millis(30));
+    });
+    match catch_unwind(AssertUnwindSafe(|| executor.run())) {
+        Ok(()) => println!("no slow poll"),
+        Err(e) => println!("strict: {}", e.downcast_ref::<String>().unwrap()),
+    }
+}
+// 출력의 spawned at은 spawn을 호출한 위치다. Spawner::spawn에 #[track_caller]를 붙였으므로 spawn 안에서
+// Location::caller()를 호출하면 spawn의 호출자 위치를 얻는다. 기준을 넘은 poll이 있어도 poll 자체를 중단할 수는 없으므로
+// 이미 멈춘 시간을 되돌리지는 못한다. 찾은 호출은 tokio::time::sleep이나 비동기 Mutex, spawn_blocking 등으로 바꾼다.
+
 /// lock을 획득한 상태에서 await을 수행하는 예
 fn func_215p() {
     use std::{sync::Arc, time};
@@ -1469,4 +1431,50 @@ fn func_218p() {
 }
 // blocking 함수인 do_block을 여기서는 단순히 슬립 한다. 이 함수에서는 std::thread::sleep을 사용하고 있으므로,
 // sleep상태라 해도 worker thread들 점유하게 된다. 그러나 spawn_blocking 함수에서 blocking용 스레드를 생성하고
-// 거기서 이 함수를 호출하므로 deadlock에 빠지지 않는다
\ No newline at end of file
+// 거기서 이 함수를 호출하므로 deadlock에 빠지지 않는다
+/// func_218p를 runtime.rs의 런타임으로 옮겨보자. 단일 스레드 Executor에서 do_block을 그대로 호출하면 그 동안
+/// do_print를 포함한 모든 Task가 멈추지만, runtime::spawn_blocking은 블로킹 전용 스레드 풀에서 실행하고 결과를
+/// JoinHandle로 돌려준다. 여기서는 시간을 줄이기 위해 200ms 블록하고, 풀의 최대 스레드 수를 8로 제한했다.
+// #[test]
+pub fn func_218p_2() {
+    use crate::runtime::{sleep, spawn_blocking, IOSelector, Runtime};
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    fn do_block(n: u64) -> u64 {
+        std::thread::sleep(Duration::from_millis(200));
+        n
+    }
+
+    async fn do_print(rt: Arc<Runtime>, selector: Arc<IOSelector>) {
+        for _ in 0..10 {
+            sleep(Duration::from_millis(100), selector.clone()).await;
+            let m = rt.blocking_metrics();
+            println!("wake up: threads = {}, idle = {}, queued = {}", m.threads, m.idle_threads, m.queue_depth);
+        }
+    }
+
+    let rt = Arc::new(Runtime::new());
+    rt.set_blocking_limits(8, Duration::from_millis(300));
+    let (rt0, selector, spawner) = (rt.clone(), rt.selector(), rt.spawner());
+    let start = Instant::now();
+    rt.block_on(async move {
+        let mut v = Vec::new();
+        for n in 0..32 {
+            v.push(spawn_blocking(move || do_block(n)));
+        }
+        let p = spawner.spawn(do_print(rt0, selector));
+        for t in v {
+            let n = t.await.unwrap();
+            println!("finished: {} ({:?})", n, start.elapsed());
+        }
+        p.await.unwrap();
+    });
+    // 작업이 없는 스레드는 idle timeout이 지나면 종료한다.
+    std::thread::sleep(Duration::from_millis(500));
+    println!("after idle timeout: {:?}", rt.blocking_metrics());
+}
+// 32개의 작업을 8개의 스레드가 4번에 나눠 처리하므로 약 800ms가 걸리며, 그 동안 do_print는 100ms마다 깨어나서 큐에
+// 남은 작업 수(queue depth)가 줄어드는 것을 보여준다. 블로킹 스레드가 결과를 기록하면 JoinHandle을 await하는 Task의
+// Waker를 호출하므로 Executor는 일반 Task의 wake와 똑같이 처리한다. 최대 스레드 수가 작으면 블록하는 작업이 많을 때
+// 큐에서 기다리는 시간이 늘어나고, 너무 크면 스레드가 메모리를 차지하므로 Tokio의 기본값은 512, idle timeout은 10초다.
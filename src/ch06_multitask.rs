@@ -97,6 +97,59 @@
 // 실행 방법을 채택하여 각 프로세스가 공평하게 CPU 시간을 소비 가능하게 변경되었다. 리눅스는 스케줄링 방식을 몇 가지
 // 선택할 수 있으며, IO의 deadline 근방의 process를 우선하는 스케줄러 등도 선택할 수 있다. 이렇게 현실적인 시스템에서
 // 공평성을 논할 때는 실행 가능성은 물론 리소스 소비의 관점도 고려해야 한다.
+//
+/// 위의 약한/강한 공평성을 LTL 검사기(ltl.rs)로 확인해보자. 태스크 3개를 스케줄링하는 세 가지 스케줄러를 흉내 내어
+/// 실행 기록을 만들고, 정상 상태 한 주기를 반복 구간으로 지정해 태스크별로 공평성을 검사한다.
+// #[test]
+pub fn func_224p() {
+    use crate::ltl::{weak_fairness, Sim};
+
+    // 라운드 로빈: 실행 가능한 태스크를 차례로 실행
+    let mut sim = Sim::new();
+    (0..3).for_each(|t| sim.set_runnable(t, true));
+    let start = sim.position();
+    for k in 0..3 {
+        sim.run(k);
+    }
+    let trace = sim.trace().lasso(start);
+    println!("round robin : {}", trace);
+    println!("  weak unfair = {:?}, strong unfair = {:?}", trace.unfair_tasks(false), trace.unfair_tasks(true));
+
+    // 고정 우선순위: 항상 번호가 가장 작은 태스크를 실행. 태스크 2는 계속 실행 가능한데도 실행되지 않는다.
+    let mut sim = Sim::new();
+    (0..3).for_each(|t| sim.set_runnable(t, true));
+    let start = sim.position();
+    for _ in 0..3 {
+        sim.run(0);
+        sim.set_runnable(0, false); // 0이 블록된 동안 1이 실행됨
+        sim.run(1);
+        sim.set_runnable(0, true);
+    }
+    let trace = sim.trace().lasso(start);
+    println!("priority    : {}", trace);
+    println!("  weak unfair = {:?}, strong unfair = {:?}", trace.unfair_tasks(false), trace.unfair_tasks(true));
+    println!("  {} = {}", weak_fairness(2), trace.holds(&weak_fairness(2)));
+
+    // 태스크 2는 실행 가능과 블록을 반복하는데, 스케줄러가 2를 확인하는 순간에는 항상 블록되어 있다.
+    // 무한히 자주 실행 가능해지지만 계속 실행 가능하지는 않으므로 약한 공평성은 만족하고 강한 공평성은 만족하지 않는다.
+    let mut sim = Sim::new();
+    (0..2).for_each(|t| sim.set_runnable(t, true));
+    let start = sim.position();
+    for k in 0..4 {
+        sim.set_runnable(2, k % 2 == 1);
+        if k % 2 == 0 && sim.is_runnable(2) {
+            sim.run(2);
+        } else {
+            sim.run(k / 2 % 2);
+        }
+    }
+    let trace = sim.trace().lasso(start);
+    println!("unlucky     : {}", trace);
+    println!("  weak unfair = {:?}, strong unfair = {:?}", trace.unfair_tasks(false), trace.unfair_tasks(true));
+}
+// 출력의 +t는 Runnable(t), -t는 Blocked(t), *t는 Ran(t)이며 | 사이가 무한히 반복되는 구간이다. 라운드 로빈은
+// 두 공평성을 모두 만족하고, 고정 우선순위는 태스크 2에 대해 약한 공평성(따라서 강한 공평성도)을 위반한다. 세 번째
+// 스케줄러는 약한 공평성만 만족한다. 실제 스케줄러나 락에서는 ltl::Recorder로 이벤트를 기록해 같은 방식으로 검사할 수 있다.
 
 
 // 6.1.2 협조적/비협조적 multi-task
@@ -307,17 +360,17 @@ fn src_green_rs() {
     use std::ptr;
 }
 
-/// 6.2.2 context
-/// context는 process의 실행 상태에 관한 정보이며, 가장 중요한 정보는 register 값이다. [그림 6-4]에 이번 구현에서
-/// 저장하는 context와 CPU 및 메모리의 관계를 나타냈다.
-///
-/// text영역은 실행 명령이 놓인 메모리 영역. 그림에서는 set_context라는 context를 저장하는 함수가 호출되면 caller
-/// 저장 register는 컴파일러가 출력한 코드에 따라 stack으로 회피된다. 한편 callee 저장 register는 회피되지 않으므로
-/// set_context 함수가 heap상의 확보된 영역에 저장된다.
-/// (set_context 호출시: caller 저장 register -> stack으로 회피, callee 저장 register -> 회피불가. heap에 저장됨)
-/// ret 명령에서의 반환 위치 주소를 나타내는 link 주소인 x30 register와 stack pointer를 나타내는 sp register도
-/// 마찬가지로 저장된다. 그러면 다른 process 실행 후 context에 저장된 register 정보를 복원하고 ret 명령어로 반환하면
-/// set_context 함수를 호출한 다음 주소(x30 register가 지정된 주소)에서 실행을 재개함.
+// 6.2.2 context
+// context는 process의 실행 상태에 관한 정보이며, 가장 중요한 정보는 register 값이다. [그림 6-4]에 이번 구현에서
+// 저장하는 context와 CPU 및 메모리의 관계를 나타냈다.
+//
+// text영역은 실행 명령이 놓인 메모리 영역. 그림에서는 set_context라는 context를 저장하는 함수가 호출되면 caller
+// 저장 register는 컴파일러가 출력한 코드에 따라 stack으로 회피된다. 한편 callee 저장 register는 회피되지 않으므로
+// set_context 함수가 heap상의 확보된 영역에 저장된다.
+// (set_context 호출시: caller 저장 register -> stack으로 회피, callee 저장 register -> 회피불가. heap에 저장됨)
+// ret 명령에서의 반환 위치 주소를 나타내는 link 주소인 x30 register와 stack pointer를 나타내는 sp register도
+// 마찬가지로 저장된다. 그러면 다른 process 실행 후 context에 저장된 register 정보를 복원하고 ret 명령어로 반환하면
+// set_context 함수를 호출한 다음 주소(x30 register가 지정된 주소)에서 실행을 재개함.
 // fn f() {
 //
 // }
\ No newline at end of file
@@ -0,0 +1,126 @@
+// 4장 chaos 모드: 동기 지점에 무작위 지연 주입
+// 이 크레이트의 동시성 버그 상당수는 운 나쁜 타이밍에서만 나타난다. 특히 CPU가 적은 환경에서는 스레드가 거의 순서대로
+// 실행되어 버려 스트레스 테스트를 아무리 돌려도 재현되지 않는다. 그래서 cargo feature chaos를 켜면 직접 구현한 동기
+// 프리미티브(Semaphore, channel, SpinLock, BakeryLock, IOSelector)의 동기 지점마다 일정 확률로 다음 중 하나를
+// 주입하도록 해보자.
+// - yield_now로 CPU를 양보
+// - 짧은 sleep(최대 100us)
+// - 추가 스핀(최대 1000회)
+//
+// 확률과 seed는 환경 변수 CHAOS="확률:seed"(예: 0.1:42)로 지정하며, 지정하지 않으면 0.01:0이다.
+//   $ CHAOS=0.1:42 cargo test --features chaos
+// 난수는 스레드별로 seed와 스레드 번호(처음 주입 지점에 도달한 순서)로 초기화하므로, 같은 seed라도 스레드의 실행 순서가
+// 달라지면 주입 위치도 달라진다. feature를 끄면 point는 아무것도 하지 않고 인라인되어 사라진다.
+
+#[cfg(feature = "chaos")]
+use rand::{rngs::StdRng, Rng, SeedableRng};
+#[cfg(feature = "chaos")]
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Config {
+    pub rate: f64, // 동기 지점 1회당 주입 확률
+    pub seed: u64,
+}
+
+// set_config로 바꾸기 전까지는 환경 변수의 값을 이용
+static CONFIG: Mutex<Option<Config>> = Mutex::new(None);
+
+// 설정이 바뀔 때마다 증가. 스레드별 난수는 세대가 바뀌면 다시 초기화한다.
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+// 주입한 횟수
+static INJECTED: AtomicU64 = AtomicU64::new(0);
+
+fn parse(v: &str) -> Config {
+    let mut config = Config {
+        rate: 0.01,
+        seed: 0,
+    };
+    let mut it = v.split(':');
+    if let Some(r) = it.next().and_then(|r| r.parse().ok()) {
+        config.rate = r;
+    }
+    if let Some(s) = it.next().and_then(|s| s.parse().ok()) {
+        config.seed = s;
+    }
+    config
+}
+
+pub fn config() -> Config {
+    *CONFIG
+        .lock()
+        .unwrap()
+        .get_or_insert_with(|| parse(&std::env::var("CHAOS").unwrap_or_default()))
+}
+
+// 이후의 주입 설정을 변경
+pub fn set_config(config: Config) {
+    *CONFIG.lock().unwrap() = Some(config);
+    GENERATION.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn injected() -> u64 {
+    INJECTED.load(Ordering::Relaxed)
+}
+
+pub fn enabled() -> bool {
+    cfg!(feature = "chaos")
+}
+
+#[cfg(feature = "chaos")]
+struct Local {
+    generation: u64,
+    rate: f64,
+    rng: StdRng,
+}
+
+#[cfg(feature = "chaos")]
+thread_local! {
+    static LOCAL: RefCell<Option<Local>> = const { RefCell::new(None) };
+}
+
+#[cfg(feature = "chaos")]
+static NEXT_THREAD: AtomicU64 = AtomicU64::new(0);
+
+#[cfg(feature = "chaos")]
+fn inject() {
+    use std::time::Duration;
+
+    let action = LOCAL.with(|l| {
+        let mut l = l.borrow_mut();
+        let generation = GENERATION.load(Ordering::Relaxed);
+        if l.as_ref().is_none_or(|l| l.generation != generation) {
+            let c = config();
+            let n = NEXT_THREAD.fetch_add(1, Ordering::Relaxed);
+            *l = Some(Local {
+                generation,
+                rate: c.rate,
+                rng: StdRng::seed_from_u64(c.seed ^ n.wrapping_mul(0x9e37_79b9_7f4a_7c15)),
+            });
+        }
+        let l = l.as_mut().unwrap();
+        if l.rate <= 0.0 || !l.rng.gen_bool(l.rate.min(1.0)) {
+            return None;
+        }
+        Some((l.rng.gen_range(0..3), l.rng.gen_range(1..=100u32)))
+    });
+
+    if let Some((kind, n)) = action {
+        INJECTED.fetch_add(1, Ordering::Relaxed);
+        match kind {
+            0 => std::thread::yield_now(),
+            1 => std::thread::sleep(Duration::from_micros(n as u64)),
+            _ => (0..n * 10).for_each(|_| std::hint::spin_loop()),
+        }
+    }
+}
+
+/// 동기 지점. chaos feature가 켜져 있으면 일정 확률로 yield, sleep, 스핀 중 하나를 주입한다.
+#[inline(always)]
+pub fn point() {
+    #[cfg(feature = "chaos")]
+    inject();
+}
@@ -0,0 +1,121 @@
+// 동기 지점 계측: 직접 만든 동기 프리미티브의 훅을 한 곳에 모으기
+// ch03의 Semaphore, channel, BakeryLock과 ch04의 SpinLock은 락을 기다리고, 획득하고, 해제하는 지점마다 다음 네 가지를
+// 기록해야 한다.
+// - lockstat: 경합 통계(스핀 횟수, 대기 시간, 보유 시간)
+// - race: 벡터 클록의 acquire/release
+// - timeline: 다른 스레드를 기다리는 동안 Waiting, 획득하면 Running
+// - chaos: 무작위 지연 주입
+// 이것을 각 프리미티브에 따로 적으면 지점마다 호출이 네 줄씩 쌓이고 하나를 빼먹기 쉽다. 그래서 프리미티브는 Site 하나를
+// 가지고 지점마다 훅을 한 번만 호출하며, 훅이 네 모듈로 나누어 전달한다.
+//
+//   let mut wait = site.on_wait_begin();      // 획득 시도 시작
+//   while 잠겨 있음 { wait.on_spin(); }       // 스핀 1회 (조건 변수로 잠들 때는 on_block)
+//   let held = wait.on_acquired();           // 획득
+//   ...
+//   site.on_released(held);                  // 해제
+//
+// 각 모듈의 feature가 꺼져 있으면 해당 부분은 아무것도 하지 않는다.
+
+use crate::lockstat::{Attempt, Held, Probe};
+use crate::race::SyncClock;
+use crate::timeline::{self, State};
+
+/// 동기 프리미티브 하나의 계측 지점. static 변수에도 넣을 수 있다.
+pub struct Site {
+    reason: &'static str, // timeline에 기록할 대기 이유(예: "SpinLock::lock")
+    probe: Probe,
+    clock: SyncClock,
+}
+
+/// 획득 시도 하나
+pub struct Wait<'a> {
+    site: &'a Site,
+    attempt: Attempt,
+    waited: bool, // 다른 스레드를 기다렸는가. 처음 기다릴 때만 Waiting을 기록한다.
+}
+
+impl Site {
+    /// name은 lockstat의 통계 이름, reason은 timeline의 대기 이유
+    pub const fn new(name: &'static str, reason: &'static str) -> Self {
+        Site {
+            reason,
+            probe: Probe::new(name),
+            clock: SyncClock::new(),
+        }
+    }
+
+    pub fn on_wait_begin(&self) -> Wait<'_> {
+        crate::chaos::point();
+        Wait {
+            site: self,
+            attempt: self.probe.begin(),
+            waited: false,
+        }
+    }
+
+    // 락 해제
+    pub fn on_released(&self, held: Held) {
+        crate::chaos::point();
+        self.probe.released(held);
+        self.clock.release();
+    }
+
+    // 세마포어 해제. 가장 먼저 획득한 것이 해제되었다고 본다.
+    pub fn on_released_shared(&self) {
+        crate::chaos::point();
+        self.probe.released_shared();
+        self.clock.release();
+    }
+
+    // 채널 송신. 보유하는 것이 없으므로 lockstat에는 기록하지 않는다.
+    pub fn on_sent(&self) {
+        crate::chaos::point();
+        self.clock.release();
+    }
+}
+
+impl Wait<'_> {
+    fn waiting(&mut self) {
+        if !self.waited {
+            self.waited = true;
+            timeline::emit(State::Waiting, self.site.reason);
+        }
+    }
+
+    // 스핀 1회
+    pub fn on_spin(&mut self) {
+        self.attempt.spin();
+        self.waiting();
+        crate::chaos::point();
+    }
+
+    // 조건 변수 등으로 잠듦
+    pub fn on_block(&mut self) {
+        self.attempt.block();
+        self.waiting();
+    }
+
+    fn running(&self) {
+        self.site.clock.acquire();
+        if self.waited {
+            timeline::emit(State::Running, self.site.reason);
+        }
+    }
+
+    // 락 획득. 반환값은 해제할 때 Site::on_released에 넘긴다.
+    pub fn on_acquired(self) -> Held {
+        self.running();
+        self.site.probe.acquired(self.attempt)
+    }
+
+    // 세마포어 획득
+    pub fn on_acquired_shared(self) {
+        self.running();
+        self.site.probe.acquired_shared(self.attempt);
+    }
+
+    // 채널 수신. 채널의 경합 통계는 안쪽의 세마포어가 기록하므로 lockstat에는 기록하지 않는다.
+    pub fn on_received(self) {
+        self.running();
+    }
+}
@@ -4,6 +4,25 @@ mod ch03_synchronous_processing01;
 mod ch04_bugs_and_problems;
 mod ch05_async_programming;
 mod ch06_multitask;
+pub mod progress_monitor;
+pub mod signal_hub;
+pub mod thread_registry;
+pub mod weak_memory;
+pub mod litmus;
+pub mod race;
+pub mod linearizability;
+pub mod ltl;
+pub mod chaos;
+pub mod timeline;
+pub mod lockstat;
+pub mod instrument;
+pub mod runtime;
+#[cfg(test)]
+mod spurious;
+#[cfg(test)]
+mod watchdog;
+#[cfg(test)]
+mod model_check;
 mod build;
 mod green;
 
@@ -23,6 +42,7 @@ mod tests {
     fn it_works() {
         let result = add(2, 2);
         assert_eq!(result, 4);
+        ch01_concurrency_and_parallelism::func_6p();
         assert_eq!(ch02_basic_programming::fun1(), 400);
         ch02_basic_programming::fun3();
         ch02_basic_programming::my_func3();
@@ -44,16 +64,1619 @@ mod tests {
         ch03_synchronous_processing01::some_func7_126p();
         ch03_synchronous_processing01::some_func8_127p();
         // ch03_synchronous_processing01::some_func9_129p();
+        ch03_synchronous_processing01::some_func10_133p_2();
         ch03_synchronous_processing01::some_func11_138p();
-        ch04_bugs_and_problems::func_144p();
-        ch04_bugs_and_problems::func_145p();
+        ch03_synchronous_processing01::some_func11_138p_2();
+        ch03_synchronous_processing01::some_func11_138p_3();
         ch04_bugs_and_problems::func_146p();
+        ch04_bugs_and_problems::func_149p();
         ch04_bugs_and_problems::func_152p();
         ch04_bugs_and_problems::func_167p();
+        ch04_bugs_and_problems::func_167p_3().unwrap();
         // ch04_bugs_and_problems::func_172p();
+        // ch04_bugs_and_problems::func_172p_2();
         // ch05_async_programming::func_178p();
         // ch05_async_programming::func_186p();
+        ch05_async_programming::func_189p();
+        ch05_async_programming::func_189p_2();
+        ch05_async_programming::func_189p_3();
+        ch05_async_programming::func_197_2();
+        ch05_async_programming::func_197_3().unwrap();
+        ch05_async_programming::func_213p_2();
+        ch05_async_programming::func_214p_2();
+        ch05_async_programming::func_218p_2();
         ch05_async_programming::func_213p();
         ch05_async_programming::func_214p();
+        ch06_multitask::func_224p();
+    }
+
+    // 조건 재확인 루프 없이 if로 한 번만 wait하는 잘못된 대기. 깨어난 시점의 ready 값을 반환.
+    fn wait_without_loop(p: &(Mutex<bool>, spurious::SpuriousCondvar)) -> bool {
+        let (lock, cvar) = p;
+        let mut ready = lock.lock().unwrap();
+        if !*ready {
+            ready = cvar.wait(ready).unwrap();
+        }
+        *ready
+    }
+
+    #[test]
+    fn spurious_wakeup_breaks_missing_loop() {
+        // 확률 1.0이면 알림 전에 반드시 깨어나므로 루프가 없는 대기는 결정적으로 잘못된 값을 본다.
+        let config = spurious::Config { probability: 1.0, seed: 0 };
+        let p = Arc::new((Mutex::new(false), spurious::SpuriousCondvar::with_config(config)));
+        let p0 = p.clone();
+        let waiter = thread::spawn(move || wait_without_loop(&p0));
+        assert!(!waiter.join().unwrap());
+        assert_eq!(p.1.spurious_count(), 1);
+
+        // 같은 seed면 같은 wait 순서에서 같은 위치에 의사 각성이 일어난다.
+        let config = spurious::Config { probability: 0.5, seed: 42 };
+        let a = spurious::SpuriousCondvar::with_config(config);
+        let b = spurious::SpuriousCondvar::with_config(config);
+        let m = Mutex::new(());
+        let mut g = m.lock().unwrap();
+        for _ in 0..32 {
+            g = a.wait_timeout(g, std::time::Duration::ZERO).unwrap().0;
+            g = b.wait_timeout(g, std::time::Duration::ZERO).unwrap().0;
+            assert_eq!(a.spurious_count(), b.spurious_count());
+        }
+        assert!(a.spurious_count() > 0);
+    }
+
+    #[test]
+    fn spurious_wakeup_ch03() {
+        // 이후 생성되는 ch03의 조건 변수(child/parent, Semaphore, channel)에 의사 각성 주입
+        let prev = spurious::default_config();
+        spurious::set_default(spurious::Config { probability: 0.5, seed: 7 });
+
+        ch03_synchronous_processing01::some_func6_125p();
+
+        let sem = Arc::new(ch03_synchronous_processing01::Semaphore::new(2));
+        let inside = Arc::new(AtomicUsize::new(0));
+        let mut v = Vec::new();
+        for _ in 0..4 {
+            let sem = sem.clone();
+            let inside = inside.clone();
+            v.push(thread::spawn(move || {
+                for _ in 0..1000 {
+                    sem.wait();
+                    assert!(inside.fetch_add(1, Ordering::SeqCst) < 2);
+                    inside.fetch_sub(1, Ordering::SeqCst);
+                    sem.post();
+                }
+            }));
+        }
+        for t in v {
+            t.join().unwrap();
+        }
+
+        let (tx, rx) = ch03_synchronous_processing01::channel(2);
+        let t = thread::spawn(move || (0..1000).map(|_| rx.recv()).collect::<Vec<usize>>());
+        for i in 0..1000 {
+            tx.send(i);
+        }
+        assert_eq!(t.join().unwrap(), (0..1000).collect::<Vec<usize>>());
+
+        spurious::set_default(prev);
+    }
+
+    // 데드락을 일으키는 데모. it_works에서 직접 호출하지 않고 watchdog으로 자식 프로세스에서 실행한다.
+    const DEMOS: &[(&str, fn())] = &[
+        ("func_144p_2", ch04_bugs_and_problems::func_144p_2),
+        ("func_145p", ch04_bugs_and_problems::func_145p),
+        ("func_147p_1", ch04_bugs_and_problems::func_147p_1),
+        ("func_161p", ch04_bugs_and_problems::func_161p),
+        ("func_146p", ch04_bugs_and_problems::func_146p),
+        ("func_147p_2", ch04_bugs_and_problems::func_147p_2),
+    ];
+
+    // watchdog의 자식 프로세스 진입점. WATCHDOG_DEMO가 없으면(일반 cargo test) 아무것도 하지 않는다.
+    #[test]
+    fn watchdog_child() {
+        watchdog::child_main(DEMOS);
+    }
+
+    #[test]
+    fn watchdog_bug_demos() {
+        use watchdog::Outcome;
+        let expected = [
+            ("func_144p_2", Outcome::TimedOut),
+            ("func_145p", Outcome::TimedOut),
+            ("func_147p_1", Outcome::TimedOut),
+            ("func_161p", Outcome::TimedOut),
+            // 데드락을 회피한 버전은 정상 종료해야 한다.
+            ("func_146p", Outcome::Completed),
+            ("func_147p_2", Outcome::Completed),
+        ];
+
+        // 데모마다 timeout을 기다리므로 병렬로 실행
+        let handles: Vec<_> = expected
+            .iter()
+            .map(|&(demo, outcome)| {
+                thread::spawn(move || {
+                    let report = watchdog::run(
+                        "tests::watchdog_child",
+                        demo,
+                        std::time::Duration::from_secs(3),
+                    );
+                    (report, outcome)
+                })
+            })
+            .collect();
+
+        for h in handles {
+            let (report, outcome) = h.join().unwrap();
+            println!("watchdog: {} -> {:?} ({:?})", report.demo, report.outcome, report.elapsed);
+            assert_eq!(report.outcome, outcome, "{}: stderr:\n{}", report.demo, report.stderr);
+            if outcome == Outcome::Completed {
+                assert!(report.stdout.contains("flag is true") || report.stdout.contains("not deadlock"));
+            }
+        }
+    }
+
+    // 두 스레드가 락을 잡고 임계 구역(cs)에 들어가는 모델. 임계 구역 안에 동시에 둘이 있으면 assert 실패.
+    fn mutual_exclusion<L>(
+        new_lock: fn() -> L,
+        with_lock: fn(&L, usize, &dyn Fn()),
+    ) -> Result<model_check::Stats, model_check::Failure>
+    where
+        L: Send + Sync + 'static,
+    {
+        model_check::check(move || {
+            use model_check::sync::atomic::{AtomicUsize, Ordering};
+            let shared = Arc::new((new_lock(), AtomicUsize::new(0)));
+            let handles: Vec<_> = (0..2)
+                .map(|i| {
+                    let shared = shared.clone();
+                    model_check::thread::spawn(move || {
+                        with_lock(&shared.0, i, &|| {
+                            assert_eq!(shared.1.fetch_add(1, Ordering::SeqCst), 0, "mutual exclusion violated");
+                            shared.1.fetch_sub(1, Ordering::SeqCst);
+                        });
+                    })
+                })
+                .collect();
+            for h in handles {
+                h.join().unwrap();
+            }
+        })
+    }
+
+    #[test]
+    fn model_check_bakery() {
+        use model_check::targets::BakeryLock;
+        let with_lock = |l: &BakeryLock, i, cs: &dyn Fn()| {
+            let _lock = l.lock(i);
+            cs();
+        };
+        let stats = mutual_exclusion(|| BakeryLock::new(2), with_lock).unwrap();
+        println!("{:?}", stats);
+        assert!(stats.complete);
+
+        let failure = mutual_exclusion(|| BakeryLock::without_entering(2), with_lock).unwrap_err();
+        println!("{}", failure);
+        assert!(matches!(failure.kind, model_check::FailureKind::Panic(_)));
+    }
+
+    #[test]
+    fn model_check_spinlock() {
+        use model_check::targets::SpinLock;
+        let with_lock = |l: &SpinLock<()>, _, cs: &dyn Fn()| {
+            let _lock = l.lock();
+            cs();
+        };
+        let stats = mutual_exclusion(|| SpinLock::new(()), with_lock).unwrap();
+        println!("{:?}", stats);
+        assert!(stats.complete);
+
+        let failure = mutual_exclusion(|| SpinLock::non_atomic(()), with_lock).unwrap_err();
+        println!("{}", failure);
+        assert!(matches!(failure.kind, model_check::FailureKind::Panic(_)));
+    }
+
+    #[test]
+    fn model_check_limits() {
+        // max_executions 안에 탐색을 끝내지 못하면 complete가 false
+        let stats = model_check::Checker::new().max_executions(1).check(channel_model(2)).unwrap();
+        assert_eq!(stats.executions, 1);
+        assert!(!stats.complete);
+
+        // 한 번의 실행에서 전환 지점이 max_steps를 넘으면 StepLimit
+        let failure = model_check::Checker::new()
+            .max_steps(10)
+            .check(|| {
+                use model_check::sync::atomic::{AtomicUsize, Ordering};
+                let n = AtomicUsize::new(0);
+                for _ in 0..100 {
+                    n.fetch_add(1, Ordering::SeqCst);
+                }
+            })
+            .unwrap_err();
+        assert_eq!(failure.kind, model_check::FailureKind::StepLimit);
+    }
+
+    // 용량 1인 채널로 송신 스레드 2개가 하나씩 보내고 수신 측이 n개 받는 모델
+    fn channel_model(n: usize) -> impl Fn() + Send + Sync + 'static {
+        move || {
+            let (tx, rx) = model_check::targets::channel(1);
+            let handles: Vec<_> = (1..=2)
+                .map(|i| {
+                    let tx = tx.clone();
+                    model_check::thread::spawn(move || tx.send(i))
+                })
+                .collect();
+            let sum: usize = (0..n).map(|_| rx.recv()).sum();
+            assert!(n != 2 || sum == 3);
+            for h in handles {
+                h.join().unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn model_check_channel() {
+        let stats = model_check::check(channel_model(2)).unwrap();
+        println!("{:?}", stats);
+        assert!(stats.complete);
+
+        // 보낸 것보다 많이 받으려 하면 데드락. 보고된 스케줄로 같은 데드락을 재현할 수 있다.
+        let failure = model_check::check(channel_model(3)).unwrap_err();
+        println!("{}", failure);
+        assert_eq!(failure.kind, model_check::FailureKind::Deadlock);
+        let replayed = model_check::Checker::new()
+            .replay(&failure.schedule, channel_model(3))
+            .unwrap_err();
+        assert_eq!(replayed.kind, model_check::FailureKind::Deadlock);
+        assert_eq!(replayed.schedule, failure.schedule);
+    }
+
+    // some_func9_129p를 줄인 모델. 스레드 4개가 SEM_NUM(2)인 세마포어로 임계 구역에 2번씩 들어간다.
+    fn semaphore_model() {
+        use model_check::sync::atomic::{AtomicUsize, Ordering};
+        const SEM_NUM: isize = 2;
+        let shared = Arc::new((model_check::targets::Semaphore::new(SEM_NUM), AtomicUsize::new(0)));
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let shared = shared.clone();
+                model_check::thread::spawn(move || {
+                    for _ in 0..2 {
+                        shared.0.wait();
+                        let n = shared.1.fetch_add(1, Ordering::SeqCst) + 1;
+                        assert!((n as isize) <= SEM_NUM);
+                        shared.1.fetch_sub(1, Ordering::SeqCst);
+                        shared.0.post();
+                    }
+                })
+            })
+            .collect();
+        for h in handles {
+            h.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn model_check_seed() {
+        // 모든 인터리빙을 탐색하기에는 너무 크므로 seed 1000개로 무작위 스케줄을 시도
+        let n = model_check::Checker::new().fuzz(0..1000, semaphore_model).unwrap();
+        assert_eq!(n, 1000);
+
+        // 깨진 Bakery 락은 몇 개의 seed 안에 실패하고, 그 seed로 같은 스케줄이 재현된다.
+        let model = || {
+            use model_check::targets::BakeryLock;
+            let lock = Arc::new(BakeryLock::without_entering(2));
+            let cnt = Arc::new(model_check::sync::atomic::AtomicUsize::new(0));
+            let handles: Vec<_> = (0..2)
+                .map(|i| {
+                    let (lock, cnt) = (lock.clone(), cnt.clone());
+                    model_check::thread::spawn(move || {
+                        use model_check::sync::atomic::Ordering;
+                        let _lock = lock.lock(i);
+                        assert_eq!(cnt.fetch_add(1, Ordering::SeqCst), 0, "mutual exclusion violated");
+                        cnt.fetch_sub(1, Ordering::SeqCst);
+                    })
+                })
+                .collect();
+            for h in handles {
+                h.join().unwrap();
+            }
+        };
+        let checker = model_check::Checker::new();
+        let failure = checker.fuzz(0..10_000, model).unwrap_err();
+        println!("{}", failure);
+        let seed = failure.seed.unwrap();
+        assert!(matches!(failure.kind, model_check::FailureKind::Panic(_)));
+        for _ in 0..3 {
+            let replayed = checker.run_seed(seed, model).unwrap_err();
+            assert_eq!(replayed.kind, failure.kind);
+            assert_eq!(replayed.schedule, failure.schedule);
+        }
+    }
+
+    #[test]
+    fn race_detector() {
+        use race::{AccessKind, Shared};
+        static X: Shared<u64> = Shared::new("race_detector::X", 0);
+        static Y: Shared<u64> = Shared::new("race_detector::Y", 0);
+        static Z: Shared<u64> = Shared::new("race_detector::Z", 0);
+
+        // 보호되지 않은 쓰기 두 개는 레이스. 두 스레드와 호출 위치가 보고된다.
+        let t0 = race::spawn(|| X.write(1));
+        let t1 = race::spawn(|| X.write(2));
+        t0.join().unwrap();
+        t1.join().unwrap();
+        let races = race::take_races("race_detector::X");
+        assert_eq!(races.len(), 1);
+        let r = &races[0];
+        println!("{}", r);
+        assert_eq!((r.first.kind, r.second.kind), (AccessKind::Write, AccessKind::Write));
+        assert_ne!(r.first.tid, r.second.tid);
+        assert_eq!(r.first.site.file(), file!());
+
+        // spawn 전의 쓰기와 join 후의 읽기, ch03 channel로 전달된 쓰기는 순서가 정해져 있으므로 레이스가 아니다.
+        Y.write(1);
+        let (tx, rx) = ch03_synchronous_processing01::channel(1);
+        let t = race::spawn(move || {
+            Y.write(Y.read() + 1);
+            tx.send(());
+        });
+        rx.recv();
+        assert_eq!(Y.read(), 2);
+        t.join().unwrap();
+        Y.write(3);
+        assert!(race::take_races("race_detector::Y").is_empty());
+
+        // 여러 스레드의 동시 읽기는 레이스가 아니지만, 그 뒤의 보호되지 않은 쓰기는 모든 읽기와 레이스
+        let readers: Vec<_> = (0..3).map(|_| race::spawn(|| Z.read())).collect();
+        let lock = Arc::new(race::Mutex::new(()));
+        let l = lock.clone();
+        let writer = race::spawn(move || {
+            let _guard = l.lock().unwrap();
+            Z.write(1)
+        });
+        for t in readers {
+            t.join().unwrap();
+        }
+        writer.join().unwrap();
+        let races = race::take_races("race_detector::Z");
+        assert!(!races.is_empty());
+        assert!(races.iter().all(|r| r.second.kind == AccessKind::Write || r.first.kind == AccessKind::Write));
+    }
+
+    #[test]
+    fn linearizability_specs() {
+        use linearizability::*;
+        fn op<O, R>(thread: usize, op: O, ret: R, invoke: u64, response: u64) -> Operation<O, R> {
+            Operation { thread, op, ret: Some(ret), invoke, response: Some(response) }
+        }
+
+        // 겹친 두 Enqueue는 어느 순서로든 선형화할 수 있다.
+        let queue = vec![
+            op(0, QueueOp::Enqueue(1), None, 0, 3),
+            op(1, QueueOp::Enqueue(2), None, 1, 2),
+            op(2, QueueOp::Dequeue, Some(1), 4, 5),
+            op(2, QueueOp::Dequeue, Some(2), 6, 7),
+        ];
+        assert!(check::<Queue<u32>>(&queue).is_ok());
+        // 같은 기록을 스택으로 보면 Pop이 1, 2 순서로 나올 수 없다(마지막 Pop이 2가 되려면 2를 먼저 Push해야 함).
+        let stack = vec![
+            op(0, StackOp::Push(1), None, 0, 1),
+            op(1, StackOp::Push(2), None, 2, 3),
+            op(2, StackOp::Pop, Some(1), 4, 5),
+            op(2, StackOp::Pop, Some(2), 6, 7),
+        ];
+        let e = check::<Stack<u32>>(&stack).unwrap_err();
+        println!("{}", e);
+        assert_eq!(e.history.len(), 3);
+
+        // 쓰기가 끝난 뒤의 읽기는 옛 값을 볼 수 없다. 무관한 연산은 최소 기록에서 빠진다.
+        let register = vec![
+            op(0, RegisterOp::Write(1), None, 0, 1),
+            op(1, RegisterOp::Read, Some(1), 2, 3),
+            op(2, RegisterOp::Read, None, 4, 5),
+        ];
+        let e = check::<Register<u32>>(&register).unwrap_err();
+        println!("{}", e);
+        assert_eq!(e.history, vec![register[0].clone(), register[2].clone()]);
+
+        // 집합은 원소별로 나눠 검사. 응답이 없는 Remove(1)은 일어났을 수도 있다.
+        let mut set = vec![
+            op(0, SetOp::Insert(1), true, 0, 1),
+            op(0, SetOp::Insert(2), true, 2, 3),
+            op(2, SetOp::Contains(1), false, 6, 7),
+            op(1, SetOp::Contains(2), true, 8, 9),
+        ];
+        set.push(Operation { thread: 3, op: SetOp::Remove(1), ret: None, invoke: 4, response: None });
+        assert!(check::<Set<u32>>(&set).is_ok());
+        set.pop();
+        let e = check::<Set<u32>>(&set).unwrap_err();
+        assert_eq!(e.history.len(), 2);
+    }
+
+    #[test]
+    fn linearizability_channel() {
+        use linearizability::{check, Queue, QueueOp, Recorder};
+        let (tx, rx) = ch03_synchronous_processing01::channel(1);
+        let rec = Arc::new(Recorder::new());
+        let senders: Vec<_> = (0..3)
+            .map(|i| {
+                let (tx, rec) = (tx.clone(), rec.clone());
+                thread::spawn(move || {
+                    for j in 0..4 {
+                        rec.call(i, QueueOp::Enqueue(i * 4 + j), || {
+                            tx.send(i * 4 + j);
+                            None
+                        });
+                    }
+                })
+            })
+            .collect();
+        for _ in 0..12 {
+            rec.call(3, QueueOp::Dequeue, || Some(rx.recv()));
+        }
+        for t in senders {
+            t.join().unwrap();
+        }
+        assert!(check::<Queue<usize>>(&rec.history()).is_ok());
+    }
+
+    #[test]
+    fn signal_hub_reload_and_stop() {
+        use nix::libc;
+
+        // signal의 현재 처리 방법이 SIG_DFL인가
+        fn is_default(signal: libc::c_int) -> bool {
+            unsafe {
+                let mut cur: libc::sigaction = std::mem::zeroed();
+                libc::sigaction(signal, std::ptr::null(), &mut cur);
+                cur.sa_sigaction == libc::SIG_DFL
+            }
+        }
+
+        // 작업 스레드가 꺼내기 전에 온 SIGHUP 2번은 1번의 재로드로 합쳐진다.
+        // SIGINT는 백그라운드로 실행하면 SIG_IGN일 수 있으므로 원래의 처리 방법으로 돌아오는지만 본다.
+        assert!(is_default(libc::SIGTERM));
+        let sigint = is_default(libc::SIGINT);
+        let (generation, coalesced) = ch04_bugs_and_problems::func_167p_2().unwrap();
+        assert_eq!((generation, coalesced), (1, 2));
+        // hub를 멈추면 SIGTERM은 다시 프로세스를 종료시킨다.
+        assert!(is_default(libc::SIGTERM));
+        assert_eq!(is_default(libc::SIGINT), sigint);
+
+        // 다음 hub는 다시 시그널을 받는다. SIGINT(Ctrl-C)도 종료 대신 구독자에게 전달된다.
+        let hub = signal_hub::SignalHub::new(&[signal_hub::SIGTERM, signal_hub::SIGINT]).unwrap();
+        let sub = hub.subscribe(&[signal_hub::SIGTERM, signal_hub::SIGINT]).unwrap();
+        assert!(!is_default(libc::SIGTERM) && !is_default(libc::SIGINT));
+        nix::sys::signal::raise(nix::sys::signal::Signal::SIGTERM).unwrap();
+        let n = sub.recv_timeout(std::time::Duration::from_secs(5)).unwrap();
+        assert_eq!((n.signal, n.count), (signal_hub::SIGTERM, 1));
+        nix::sys::signal::raise(nix::sys::signal::Signal::SIGINT).unwrap();
+        let n = sub.recv_timeout(std::time::Duration::from_secs(5)).unwrap();
+        assert_eq!((n.signal, n.count), (signal_hub::SIGINT, 1));
+        drop(hub);
+        assert!(is_default(libc::SIGTERM));
+        assert_eq!(is_default(libc::SIGINT), sigint);
+    }
+
+    #[test]
+    fn ltl_semantics() {
+        use ltl::*;
+        let ev = |task, kind| Event { task, kind };
+        let trace = Trace::new(vec![
+            ev(0, EventKind::Runnable),
+            ev(1, EventKind::Runnable),
+            ev(0, EventKind::Ran),
+            ev(0, EventKind::Blocked),
+            ev(1, EventKind::Ran),
+        ]);
+        let (r0, r1) = (atom(Pred::Ran(0)), atom(Pred::Ran(1)));
+        assert!(trace.holds(&finally(r1.clone())));
+        assert!(!trace.holds(&globally(atom(Pred::Runnable(0)))));
+        assert!(trace.holds(&until(not(r1.clone()), r0.clone())));
+        assert!(trace.holds(&next(atom(Pred::Runnable(1)))));
+        // 유한한 기록에서 마지막 위치의 X는 거짓
+        assert!(!trace.holds(&finally(and(r1.clone(), next(Formula::True)))));
+        // 0이 블록된 뒤에는 다시 실행되지 않음
+        let f = globally(implies(atom(Pred::Blocked(0)), globally(not(r0.clone()))));
+        assert_eq!(f.to_string(), "G (blocked(0) -> G !ran(0))");
+        assert!(trace.holds(&f));
+
+        // 반복 구간에서 1만 실행되면 계속 실행 가능한 0은 약한 공평성 위반
+        let trace = Trace::new(vec![
+            ev(0, EventKind::Runnable),
+            ev(1, EventKind::Runnable),
+            ev(1, EventKind::Ran),
+        ])
+        .lasso(2);
+        assert_eq!(trace.unfair_tasks(false), vec![0]);
+        assert_eq!(trace.unfair_tasks(true), vec![0]);
+
+        // 실행 가능과 블록을 반복하는데 한 번도 실행되지 않으면 강한 공평성만 위반
+        let trace = Trace::new(vec![
+            ev(1, EventKind::Runnable),
+            ev(0, EventKind::Runnable),
+            ev(1, EventKind::Ran),
+            ev(0, EventKind::Blocked),
+            ev(1, EventKind::Ran),
+            ev(0, EventKind::Runnable),
+        ])
+        .lasso(1);
+        assert!(trace.unfair_tasks(false).is_empty());
+        assert_eq!(trace.unfair_tasks(true), vec![0]);
+
+        // 반복 구간에서 0이 블록되기만 하면 두 번째로 지날 때 0의 상태가 달라지므로 반복 구간으로 지정할 수 없다.
+        let events = vec![
+            ev(0, EventKind::Runnable),
+            ev(1, EventKind::Runnable),
+            ev(1, EventKind::Ran),
+            ev(0, EventKind::Blocked),
+        ];
+        assert!(std::panic::catch_unwind(|| Trace::new(events.clone()).lasso(2)).is_err());
+        assert_eq!(Trace::new(events).lasso(3).loop_start, Some(3));
+    }
+
+    #[test]
+    fn ltl_lock_fairness() {
+        // 여러 스레드가 ch03의 Semaphore(1)을 락으로 잡는 기록. 모든 스레드가 끝까지 실행되었으므로 각 스레드는
+        // "실행 가능해지면 언젠가 실행된다"를 만족해야 한다.
+        use ltl::{atom, finally, globally, implies, Pred, Recorder};
+        let rec = Arc::new(Recorder::new());
+        let sem = Arc::new(ch03_synchronous_processing01::Semaphore::new(1));
+        let handles: Vec<_> = (0..3)
+            .map(|t| {
+                let (rec, sem) = (rec.clone(), sem.clone());
+                thread::spawn(move || {
+                    for _ in 0..10 {
+                        rec.runnable(t);
+                        sem.wait();
+                        rec.ran(t);
+                        rec.blocked(t);
+                        sem.post();
+                    }
+                })
+            })
+            .collect();
+        for h in handles {
+            h.join().unwrap();
+        }
+        let trace = rec.trace();
+        for t in trace.tasks() {
+            let f = globally(implies(atom(Pred::Runnable(t)), finally(atom(Pred::Ran(t)))));
+            assert!(trace.holds(&f), "{} does not hold", f);
+        }
+    }
+
+    #[test]
+    fn chaos_stress() {
+        // chaos feature를 켜면 ch03의 Semaphore와 channel에 지연이 주입된다. 주입과 상관없이 결과는 같아야 한다.
+        //   $ CHAOS=0.3:1 cargo test --features chaos chaos_stress
+        if std::env::var("CHAOS").is_err() {
+            chaos::set_config(chaos::Config { rate: 0.3, seed: 1 });
+        }
+        let before = chaos::injected();
+
+        let (tx, rx) = ch03_synchronous_processing01::channel(2);
+        let sem = Arc::new(ch03_synchronous_processing01::Semaphore::new(2));
+        let inside = Arc::new(AtomicUsize::new(0));
+        let handles: Vec<_> = (0..4)
+            .map(|i| {
+                let (tx, sem, inside) = (tx.clone(), sem.clone(), inside.clone());
+                thread::spawn(move || {
+                    for j in 0..200 {
+                        sem.wait();
+                        assert!(inside.fetch_add(1, Ordering::SeqCst) < 2);
+                        inside.fetch_sub(1, Ordering::SeqCst);
+                        sem.post();
+                        tx.send(i * 200 + j);
+                    }
+                })
+            })
+            .collect();
+        let mut got: Vec<usize> = (0..800).map(|_| rx.recv()).collect();
+        for h in handles {
+            h.join().unwrap();
+        }
+        got.sort_unstable();
+        assert_eq!(got, (0..800).collect::<Vec<_>>());
+        if chaos::enabled() {
+            println!("injected: {}", chaos::injected() - before);
+            assert!(chaos::injected() > before);
+        }
+    }
+
+    #[test]
+    fn lockstat_histogram() {
+        let h = lockstat::Histogram::new();
+        for v in 1..=10_000u64 {
+            h.record(v);
+        }
+        assert_eq!(h.count(), 10_000);
+        assert_eq!(h.max(), 10_000);
+        assert!((h.mean() - 5000.5).abs() < 1e-9);
+        // 버킷 단위로 반환하므로 상대 오차는 1/16 이내
+        for (p, want) in [(50.0, 5_000.0), (90.0, 9_000.0), (99.0, 9_900.0)] {
+            let got = h.percentile(p) as f64;
+            assert!((got - want).abs() <= want / 16.0, "p{} = {}", p, got);
+        }
+        assert_eq!(h.percentile(100.0), 10_000);
+        for v in 0..16 {
+            let h = lockstat::Histogram::new();
+            h.record(v);
+            assert_eq!(h.percentile(50.0), v); // 작은 값은 정확
+        }
+    }
+
+    #[test]
+    fn lockstat_registry() {
+        // lockstat feature를 끄면 통계는 모이지 않는다.
+        //   $ cargo test --features lockstat lockstat_registry -- --nocapture
+        let sem = Arc::new(ch03_synchronous_processing01::Semaphore::with_name(1, "test.sem"));
+        let handles: Vec<_> = (0..4)
+            .map(|_| {
+                let sem = sem.clone();
+                thread::spawn(move || {
+                    for _ in 0..100 {
+                        sem.wait();
+                        thread::sleep(std::time::Duration::from_micros(50));
+                        sem.post();
+                    }
+                })
+            })
+            .collect();
+        for h in handles {
+            h.join().unwrap();
+        }
+
+        let Some(stats) = lockstat::get("test.sem") else {
+            assert!(!lockstat::enabled());
+            assert_eq!(lockstat::dump(), "");
+            return;
+        };
+        println!("{}", lockstat::dump());
+        assert_eq!(stats.acquisitions.load(Ordering::Relaxed), 400);
+        assert!(stats.contended.load(Ordering::Relaxed) > 0);
+        assert_eq!(stats.wait.count(), 400);
+        assert_eq!(stats.hold.count(), 400);
+        assert!(stats.hold.percentile(50.0) >= 40_000); // sleep한 50us 정도는 보유
+        assert!(lockstat::dump().contains("test.sem: acquisitions = 400"));
+    }
+
+    #[test]
+    fn runtime_echo() {
+        use runtime::{AsyncListener, Runtime};
+        use std::io::{BufRead, BufReader, Write};
+
+        let rt = Runtime::new();
+        assert_eq!(rt.block_on(async { 1 + 1 }), 2);
+
+        // func_197과 같은 echo server에 클라이언트 2개가 동시에 접속
+        let listener = AsyncListener::listen("127.0.0.1:0", rt.selector());
+        let addr = listener.local_addr();
+        let clients: Vec<_> = (0..2)
+            .map(|i| {
+                thread::spawn(move || {
+                    let mut stream = std::net::TcpStream::connect(addr).unwrap();
+                    let mut reader = BufReader::new(stream.try_clone().unwrap());
+                    for j in 0..3 {
+                        let msg = format!("client {} line {}\n", i, j);
+                        stream.write_all(msg.as_bytes()).unwrap();
+                        let mut line = String::new();
+                        reader.read_line(&mut line).unwrap();
+                        assert_eq!(line, msg);
+                    }
+                })
+            })
+            .collect();
+
+        let spawner = rt.spawner();
+        let echoed = Arc::new(AtomicUsize::new(0));
+        let e = echoed.clone();
+        let total = rt.block_on(async move {
+            for _ in 0..2 {
+                let (mut reader, mut writer, _addr) = listener.accept().await;
+                let e = e.clone();
+                spawner.spawn(async move {
+                    while let Some(buf) = reader.read_line().await {
+                        writer.write_all(buf.as_bytes()).unwrap();
+                        writer.flush().unwrap();
+                        e.fetch_add(1, Ordering::SeqCst);
+                    }
+                });
+            }
+            // 접속별 Task가 끝날 때까지 이 Task는 양보하면서 기다린다.
+            futures::future::poll_fn(|cx| {
+                if e.load(Ordering::SeqCst) == 6 {
+                    std::task::Poll::Ready(e.load(Ordering::SeqCst))
+                } else {
+                    cx.waker().wake_by_ref();
+                    std::task::Poll::Pending
+                }
+            })
+            .await
+        });
+        for c in clients {
+            c.join().unwrap();
+        }
+        assert_eq!(total, 6);
+        assert_eq!(echoed.load(Ordering::SeqCst), 6);
+
+        // Runtime을 drop하면 IOSelector의 전용 스레드가 종료하고 IOSelector도 해제된다.
+        let selector = Arc::downgrade(&rt.selector());
+        drop(rt);
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+        while selector.strong_count() > 0 {
+            assert!(std::time::Instant::now() < deadline, "IOSelector leaked after Runtime drop");
+            thread::sleep(std::time::Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn runtime_join() {
+        use runtime::Runtime;
+
+        let rt = Runtime::new();
+        let spawner = rt.spawner();
+        let (sum, panicked) = rt.block_on(async move {
+            // 값을 반환하는 Task
+            let handles: Vec<_> = (1..=10u64).map(|i| spawner.spawn(async move { i * i })).collect();
+            let mut sum = 0;
+            for h in handles {
+                sum += h.await.unwrap();
+            }
+            // panic한 Task는 JoinError가 되고 Executor는 계속 실행된다.
+            let err = spawner
+                .spawn(async {
+                    panic!("boom");
+                })
+                .await
+                .unwrap_err();
+            (sum, err)
+        });
+        assert_eq!(sum, 385);
+        assert!(panicked.is_panic());
+        assert_eq!(panicked.to_string(), "task panicked: boom");
+        assert_eq!(rt.block_on(async { "still running" }), "still running");
+
+        // block_on에 전달한 future의 panic은 호출자에게 전달된다.
+        let r = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            rt.block_on(async { panic!("{}", 42) })
+        }));
+        let p = r.unwrap_err();
+        assert_eq!(p.downcast_ref::<String>().map(|s| s.as_str()), Some("42"));
+    }
+
+    #[test]
+    fn runtime_shutdown() {
+        use runtime::Executor;
+        use std::sync::atomic::AtomicBool;
+        use std::task::{Poll, Waker};
+
+        // 모든 Task가 완료하면 run이 반환한다.
+        let executor = Executor::new();
+        let spawner = executor.get_spawner();
+        let count = Arc::new(AtomicUsize::new(0));
+        for _ in 0..3 {
+            let (spawner, count) = (spawner.clone(), count.clone());
+            spawner.clone().spawn(async move {
+                let c = count.clone();
+                spawner.spawn(async move { c.fetch_add(1, Ordering::SeqCst) }).await.unwrap();
+                count.fetch_add(1, Ordering::SeqCst);
+            });
+        }
+        executor.run();
+        assert_eq!(count.load(Ordering::SeqCst), 6);
+        assert_eq!(executor.live_tasks(), 0);
+
+        // 아무도 wake할 수 없는 Task는 버려지고 Cancelled가 된다.
+        let orphan = spawner.spawn(futures::future::pending::<()>());
+        executor.run();
+        assert!(executor.block_on(orphan).unwrap_err().is_cancelled());
+
+        // Waker를 다른 곳에 보관한 채 기다리는 Task는 shutdown하면 버려진다.
+        struct Flag(Arc<AtomicBool>);
+        impl Drop for Flag {
+            fn drop(&mut self) {
+                self.0.store(true, Ordering::SeqCst);
+            }
+        }
+        let parked: Arc<Mutex<Option<Waker>>> = Arc::new(Mutex::new(None));
+        let dropped = Arc::new(AtomicBool::new(false));
+        let (p, flag) = (parked.clone(), Flag(dropped.clone()));
+        let waiting = spawner.spawn(futures::future::poll_fn(move |cx| {
+            let _ = &flag;
+            *p.lock().unwrap() = Some(cx.waker().clone());
+            Poll::<()>::Pending
+        }));
+        let s = spawner.clone();
+        spawner.spawn(async move { s.shutdown() }); // Task 안에서 shutdown
+        executor.run();
+        assert!(dropped.load(Ordering::SeqCst));
+        assert_eq!(executor.live_tasks(), 0);
+        assert!(waiting.is_finished());
+        parked.lock().unwrap().take().unwrap().wake(); // shutdown 후의 wake는 무시된다.
+        assert!(spawner.spawn(async {}).is_finished()); // shutdown 후의 spawn은 바로 Cancelled
+
+        // block_on의 panic 메시지로 shutdown과 버려진 future를 구별한다.
+        let block_on_panic = |executor: &Executor| {
+            let r = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                executor.block_on(futures::future::pending::<()>())
+            }));
+            *r.unwrap_err().downcast::<&str>().unwrap()
+        };
+        assert_eq!(block_on_panic(&executor), "block_on: executor was shut down");
+        assert_eq!(
+            block_on_panic(&Executor::new()),
+            "block_on: future was abandoned because nothing can wake it"
+        );
+    }
+
+    #[test]
+    fn runtime_waker() {
+        use runtime::Executor;
+        use std::task::{Poll, Waker};
+
+        // 같은 poll 안에서의 wake와 다른 스레드에서의 wake는 합쳐진다.
+        let executor = Executor::new();
+        let spawner = executor.get_spawner();
+        let polls = Arc::new(AtomicUsize::new(0));
+        let p = polls.clone();
+        let h = spawner.spawn(futures::future::poll_fn(move |cx| {
+            if p.fetch_add(1, Ordering::SeqCst) > 0 {
+                return Poll::Ready(());
+            }
+            for _ in 0..10 {
+                cx.waker().wake_by_ref();
+            }
+            let w = cx.waker().clone();
+            assert!(w.will_wake(cx.waker()));
+            thread::spawn(move || w.wake()).join().unwrap();
+            Poll::Pending
+        }));
+        executor.block_on(h).unwrap();
+        assert_eq!(polls.load(Ordering::SeqCst), 2);
+
+        // 완료한 Task의 Waker는 Slot을 잡고 있으므로 다른 Task를 wake하지 않는다.
+        let stale: Arc<Mutex<Option<Waker>>> = Arc::new(Mutex::new(None));
+        let s = stale.clone();
+        executor.block_on(futures::future::poll_fn(move |cx| {
+            *s.lock().unwrap() = Some(cx.waker().clone());
+            Poll::Ready(())
+        }));
+        let (polls, parked) = (Arc::new(AtomicUsize::new(0)), Arc::new(Mutex::new(None::<Waker>)));
+        let (p, w) = (polls.clone(), parked.clone());
+        let waiting = spawner.spawn(futures::future::poll_fn(move |cx| {
+            p.fetch_add(1, Ordering::SeqCst);
+            *w.lock().unwrap() = Some(cx.waker().clone());
+            Poll::<()>::Pending
+        }));
+        executor.block_on(async {});
+        stale.lock().unwrap().as_ref().unwrap().wake_by_ref();
+        executor.block_on(async {});
+        assert_eq!(polls.load(Ordering::SeqCst), 1);
+
+        // 다른 스레드에서 마지막 Waker를 버리면 Task가 버려지고, 이를 기다리는 block_on이 깨어난다.
+        let waker = parked.lock().unwrap().take().unwrap();
+        let dropper = thread::spawn(move || {
+            thread::sleep(std::time::Duration::from_millis(50));
+            drop(waker);
+        });
+        assert!(executor.block_on(waiting).unwrap_err().is_cancelled());
+        dropper.join().unwrap();
+        assert_eq!(executor.live_tasks(), 0);
+
+        // Executor보다 오래 사는 Waker
+        let w = parked.clone();
+        let h = spawner.spawn(futures::future::poll_fn(move |cx| {
+            *w.lock().unwrap() = Some(cx.waker().clone());
+            Poll::<()>::Pending
+        }));
+        executor.block_on(async {});
+        drop(executor);
+        drop(spawner);
+        assert!(h.is_finished());
+        parked.lock().unwrap().take().unwrap().wake();
+        stale.lock().unwrap().take().unwrap().wake();
+    }
+
+    #[test]
+    fn runtime_budget() {
+        use runtime::{consume_budget, yield_now, AsyncListener, Runtime};
+        use std::sync::atomic::AtomicBool;
+
+        // 예산을 다 쓰면 양보하므로 그 사이에 다른 Task가 실행된다.
+        let rt = Runtime::new();
+        let spawner = rt.spawner();
+        for (budget, forced) in [(u32::MAX, 0), (10, 99)] {
+            rt.set_budget(budget);
+            let (ticks, done) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicBool::new(false)));
+            let (s, t, d) = (spawner.clone(), ticks.clone(), done.clone());
+            let greedy = spawner.spawn(async move {
+                for _ in 0..1000 {
+                    consume_budget().await;
+                }
+                let m = s.metrics();
+                d.store(true, Ordering::SeqCst);
+                (t.load(Ordering::SeqCst), m)
+            });
+            spawner.spawn(async move {
+                while !done.load(Ordering::SeqCst) {
+                    ticks.fetch_add(1, Ordering::SeqCst);
+                    yield_now().await;
+                }
+            });
+            let (ticks, m) = rt.block_on(greedy).unwrap();
+            rt.run();
+            assert_eq!((m[0].ops, m[0].forced_yields, m[0].polls), (1000, forced, forced + 1));
+            assert_eq!(ticks, forced as usize);
+            assert_eq!(m[1].voluntary_yields, forced);
+        }
+
+        // 미리 버퍼에 들어온 행은 항상 읽을 수 있지만 ReadLine도 예산을 소비한다.
+        rt.set_budget(10);
+        let listener = AsyncListener::listen("127.0.0.1:0", rt.selector());
+        let addr = listener.local_addr();
+        thread::spawn(move || {
+            use std::io::Write;
+            let mut stream = std::net::TcpStream::connect(addr).unwrap();
+            let buf: String = (0..300).map(|i| format!("{}\n", i)).collect();
+            stream.write_all(buf.as_bytes()).unwrap();
+        })
+        .join()
+        .unwrap();
+        let (lines, m) = rt.block_on(async move {
+            let (mut reader, _writer, _addr) = listener.accept().await;
+            let mut lines = 0;
+            while reader.read_line().await.is_some() {
+                lines += 1;
+            }
+            (lines, spawner.metrics())
+        });
+        assert_eq!(lines, 300);
+        assert_eq!(m.len(), 1);
+        assert!(m[0].ops >= 301, "{:?}", m); // accept + 300행 + EOF
+        assert!(m[0].forced_yields >= 29, "{:?}", m);
+    }
+
+    #[test]
+    fn runtime_slow_poll() {
+        use runtime::Executor;
+        use std::time::Duration;
+
+        // 기준보다 오래 걸린 poll은 spawn한 위치와 함께 기록된다.
+        let executor = Executor::new();
+        let spawner = executor.get_spawner();
+        executor.set_slow_poll(Some(Duration::from_millis(20)), false);
+        let (tx, rx) = futures::channel::oneshot::channel::<()>();
+        let line = line!() + 1;
+        let h = spawner.spawn(async move {
+            thread::sleep(Duration::from_millis(50)); // async 안의 blocking 호출
+            rx.await.unwrap();
+        });
+        executor.block_on(async {});
+        let m = executor.metrics();
+        assert_eq!(m.len(), 1);
+        assert_eq!((m[0].polls, m[0].slow_polls), (1, 1));
+        assert_eq!((m[0].location.file(), m[0].location.line()), (file!(), line));
+        tx.send(()).unwrap();
+        executor.block_on(h).unwrap();
+
+        // strict면 poll이 끝난 뒤 run이 panic하고, Executor는 계속 이용할 수 있다.
+        executor.set_slow_poll(Some(Duration::from_millis(20)), true);
+        spawner.spawn(async { thread::sleep(Duration::from_millis(50)) });
+        let r = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| executor.run()));
+        let msg = r.unwrap_err().downcast::<String>().unwrap();
+        assert!(msg.starts_with("slow poll: task") && msg.contains(file!()), "{}", msg);
+        assert_eq!(executor.live_tasks(), 0);
+        assert_eq!(executor.block_on(async { 1 }), 1);
+
+        // MultiThreadExecutor에서는 Worker 대신 block_on과 run이 panic하고, Worker는 계속 실행한다.
+        let executor = runtime::MultiThreadExecutor::new(1);
+        let spawner = executor.get_spawner();
+        executor.set_slow_poll(Some(Duration::from_millis(20)), true);
+        let (tx, rx) = futures::channel::oneshot::channel::<()>();
+        let s = spawner.clone();
+        let h = spawner.spawn(async move {
+            // Worker 안에서 spawn한 Task는 lifo 슬롯에 들어간다.
+            let waiter = s.spawn(async move { rx.await.unwrap() });
+            tx.send(()).unwrap();
+            thread::sleep(Duration::from_millis(50));
+            waiter.await.unwrap();
+        });
+        let r = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| executor.block_on(async {})));
+        let msg = r.unwrap_err().downcast::<String>().unwrap();
+        assert!(msg.starts_with("slow poll: task") && msg.contains(file!()), "{}", msg);
+        executor.set_slow_poll(None, false);
+        executor.block_on(h).unwrap();
+        executor.run();
+        assert_eq!(executor.live_tasks(), 0);
+
+        executor.set_slow_poll(Some(Duration::from_millis(20)), true);
+        spawner.spawn(async { thread::sleep(Duration::from_millis(50)) });
+        let r = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| executor.run()));
+        assert!(r.unwrap_err().downcast::<String>().unwrap().starts_with("slow poll: task"));
+        assert_eq!(executor.block_on(async { 1 }), 1);
+    }
+
+    #[test]
+    fn runtime_introspection() {
+        use runtime::{sleep, AsyncReader, Executor, IOSelector, TaskState, WaitOn};
+        use std::io::Write;
+        use std::net::{TcpListener, TcpStream};
+        use std::os::unix::io::AsRawFd;
+        use std::time::Duration;
+
+        let executor = Executor::new();
+        let spawner = executor.get_spawner();
+        let selector = IOSelector::new();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let stream = listener.accept().unwrap().0;
+        let fd = stream.as_raw_fd();
+        let mut reader = AsyncReader::new(stream, selector.clone());
+
+        // fd, 타이머, 채널을 각각 기다리는 Task
+        let read = spawner.spawn(async move { reader.read_line().await });
+        let timer = spawner.spawn(sleep(Duration::from_millis(200), selector));
+        let (tx, rx) = futures::channel::oneshot::channel::<()>();
+        let chan = spawner.spawn(async move {
+            thread::sleep(Duration::from_millis(5));
+            rx.await.unwrap();
+        });
+        executor.block_on(async {});
+
+        let m = executor.metrics();
+        assert_eq!(m.len(), 3);
+        assert!(m.iter().all(|m| m.state == TaskState::Idle && m.polls == 1));
+        assert_eq!(m[0].waiting_on, Some(WaitOn::Fd(fd)));
+        assert!(matches!(m[1].waiting_on, Some(WaitOn::Timer(_))));
+        assert_eq!(m[2].waiting_on, None);
+        assert!(m[2].busy >= Duration::from_millis(5));
+        let dump = executor.dump();
+        assert!(dump.starts_with("runtime: 3 live tasks\n"), "{}", dump);
+        assert!(dump.contains(&format!("waiting on fd {}", fd)) && dump.contains("waiting on timer"), "{}", dump);
+        assert!(dump.contains(file!()), "{}", dump);
+
+        // wake되면 기다리는 대상을 지우고, 마지막 wake 이후 지난 시간을 다시 잰다.
+        client.write_all(b"hello\n").unwrap();
+        tx.send(()).unwrap();
+        assert_eq!(executor.block_on(read).unwrap().as_deref(), Some("hello\n"));
+        executor.block_on(chan).unwrap();
+        let m = executor.metrics();
+        assert_eq!(m.len(), 1);
+        assert!(m[0].since_wake >= Duration::from_millis(5));
+        executor.block_on(timer).unwrap();
+        assert_eq!(executor.live_tasks(), 0);
+        assert_eq!(executor.dump(), "runtime: 0 live tasks\n");
+    }
+
+    #[test]
+    fn runtime_blocking() {
+        use runtime::{spawn_blocking, Executor};
+        use std::sync::{Barrier, Condvar};
+        use std::time::Duration;
+
+        // open이 true가 될 때까지 블록하는 작업
+        let gate = Arc::new((Mutex::new(false), Condvar::new()));
+        let wait = |gate: &(Mutex<bool>, Condvar)| {
+            let _g = gate.1.wait_while(gate.0.lock().unwrap(), |open| !*open).unwrap();
+        };
+        let open = |gate: &(Mutex<bool>, Condvar)| {
+            *gate.0.lock().unwrap() = true;
+            gate.1.notify_all();
+        };
+
+        // 최대 스레드 수를 넘은 작업은 큐에서 기다린다.
+        let executor = Executor::new();
+        executor.set_blocking_limits(2, Duration::from_millis(50));
+        let spawner = executor.get_spawner();
+        let started = Arc::new(Barrier::new(3));
+        let handles: Vec<_> = (0..4)
+            .map(|i| {
+                let (gate, started) = (gate.clone(), started.clone());
+                spawner.spawn_blocking(move || {
+                    if i < 2 {
+                        started.wait();
+                    }
+                    wait(&gate);
+                    i
+                })
+            })
+            .collect();
+        started.wait();
+        let m = executor.blocking_metrics();
+        assert_eq!((m.threads, m.idle_threads, m.queue_depth, m.spawned_threads), (2, 0, 2, 2));
+
+        // 블록하는 작업이 있어도 Executor의 Task는 실행되고, 결과는 JoinHandle의 Waker로 전달된다.
+        assert_eq!(executor.block_on(async { 1 }), 1);
+        open(&gate);
+        let sum = executor.block_on(async move {
+            let mut sum = 0;
+            for h in handles {
+                sum += h.await.unwrap();
+            }
+            // Task 안에서는 runtime::spawn_blocking을 이용할 수 있고, panic은 JoinError가 된다.
+            sum += spawn_blocking(|| 10).await.unwrap();
+            assert!(spawn_blocking(|| panic!("blocking")).await.unwrap_err().is_panic());
+            sum
+        });
+        assert_eq!(sum, 16);
+
+        // 작업이 없는 스레드는 idle timeout이 지나면 종료한다.
+        thread::sleep(Duration::from_millis(200));
+        let m = executor.blocking_metrics();
+        assert_eq!((m.threads, m.queue_depth), (0, 0));
+        assert!(std::panic::catch_unwind(|| spawn_blocking(|| ())).is_err()); // Task 밖
+
+        // shutdown하면 큐에서 기다리는 작업은 Cancelled가 되고, 실행 중인 작업은 끝까지 실행한다.
+        let gate = Arc::new((Mutex::new(false), Condvar::new()));
+        executor.set_blocking_limits(1, Duration::from_secs(10));
+        let (g, started) = (gate.clone(), Arc::new(Barrier::new(2)));
+        let s = started.clone();
+        let running = spawner.spawn_blocking(move || {
+            s.wait();
+            wait(&g)
+        });
+        started.wait();
+        let queued = spawner.spawn_blocking(|| ());
+        executor.shutdown();
+        assert!(futures::executor::block_on(queued).unwrap_err().is_cancelled());
+        assert!(spawner.spawn_blocking(|| ()).is_finished());
+        open(&gate);
+        futures::executor::block_on(running).unwrap();
+    }
+
+    #[test]
+    fn runtime_local() {
+        use futures::channel::oneshot;
+        use runtime::{spawn_local, LocalExecutor};
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        let executor = LocalExecutor::new();
+        let log = Rc::new(RefCell::new(Vec::new()));
+        // Task 안에서 spawn_local한 Task와 Rc<RefCell>로 상태를 공유
+        let l = log.clone();
+        let sum = executor.block_on(async move {
+            let handles: Vec<_> = (0..10)
+                .map(|i| {
+                    let l = l.clone();
+                    spawn_local(async move {
+                        l.borrow_mut().push(i);
+                        i * 2
+                    })
+                })
+                .collect();
+            let mut sum = 0;
+            for h in handles {
+                sum += h.await.unwrap();
+            }
+            sum
+        });
+        assert_eq!(sum, 90);
+        assert_eq!(*log.borrow(), (0..10).collect::<Vec<_>>());
+
+        // 다른 스레드에서의 wake
+        let (tx, rx) = oneshot::channel();
+        let waiting = executor.spawn_local(async move { rx.await.unwrap() });
+        let sender = thread::spawn(move || {
+            thread::sleep(std::time::Duration::from_millis(50));
+            tx.send(7).unwrap();
+        });
+        assert_eq!(executor.block_on(waiting).unwrap(), 7);
+        sender.join().unwrap();
+
+        // panic은 JoinError로
+        let h = executor.spawn_local(async { panic!("local") });
+        let err = executor.block_on(h).unwrap_err();
+        assert!(err.is_panic());
+        executor.run();
+        assert_eq!(executor.live_tasks(), 0);
+
+        // 아무도 wake할 수 없는 Task는 Executor와 같이 버려지고 Cancelled가 된다.
+        let orphan = executor.spawn_local(futures::future::pending::<()>());
+        executor.run();
+        assert!(executor.block_on(orphan).unwrap_err().is_cancelled());
+
+        // 다른 스레드가 Waker를 버려도 run이 깨어나서 Task를 버린다.
+        let (tx, rx) = std::sync::mpsc::channel();
+        let h = executor.spawn_local(futures::future::poll_fn(move |cx| {
+            tx.send(cx.waker().clone()).unwrap();
+            std::task::Poll::<()>::Pending
+        }));
+        let dropper = thread::spawn(move || {
+            let waker = rx.recv().unwrap();
+            thread::sleep(std::time::Duration::from_millis(50));
+            drop(waker);
+        });
+        executor.run();
+        dropper.join().unwrap();
+        assert!(h.is_finished());
+        assert_eq!(executor.live_tasks(), 0);
+
+        // 끝나지 않은 Task는 Executor와 함께 버려진다.
+        let (_tx, rx) = oneshot::channel::<()>();
+        let h = executor.spawn_local(rx);
+        drop(executor);
+        assert!(h.is_finished());
+    }
+
+    #[test]
+    fn runtime_multi_thread() {
+        use futures::{channel::mpsc, SinkExt, StreamExt};
+        use runtime::{MultiThreadExecutor, Runtime};
+
+        let executor = MultiThreadExecutor::new(4);
+        let spawner = executor.get_spawner();
+        // Task 안에서 spawn한 Task(lifo 슬롯, 로컬 큐)와 밖에서 spawn한 Task(injector)를 섞어서 실행
+        let s = spawner.clone();
+        let total = executor.block_on(async move {
+            let handles: Vec<_> = (0..100u64)
+                .map(|i| {
+                    let s2 = s.clone();
+                    s.spawn(async move {
+                        let inner: Vec<_> = (0..10u64).map(|j| s2.spawn(async move { i * 10 + j })).collect();
+                        let mut sum = 0;
+                        for h in inner {
+                            sum += h.await.unwrap();
+                        }
+                        sum
+                    })
+                })
+                .collect();
+            let mut total = 0;
+            for h in handles {
+                total += h.await.unwrap();
+            }
+            total
+        });
+        assert_eq!(total, (0..1000u64).sum::<u64>());
+
+        // 서로 wake하는 ping-pong. wake가 합쳐지거나 잃어버리면 멈춘다.
+        let (mut ping_tx, mut ping_rx) = mpsc::channel::<u32>(1);
+        let (mut pong_tx, mut pong_rx) = mpsc::channel::<u32>(1);
+        spawner.spawn(async move {
+            while let Some(v) = ping_rx.next().await {
+                pong_tx.send(v + 1).await.unwrap();
+            }
+        });
+        let last = executor.block_on(async move {
+            let mut v = 0;
+            for _ in 0..1000 {
+                ping_tx.send(v).await.unwrap();
+                v = pong_rx.next().await.unwrap();
+            }
+            v
+        });
+        assert_eq!(last, 1000);
+        executor.run(); // ping_tx가 버려졌으므로 상대 Task도 끝난다.
+        assert_eq!(executor.live_tasks(), 0);
+
+        // 멀티스레드 런타임에서 echo
+        let rt = Runtime::multi_thread(2);
+        let listener = runtime::AsyncListener::listen("127.0.0.1:0", rt.selector());
+        let addr = listener.local_addr();
+        let client = thread::spawn(move || {
+            use std::io::{BufRead, BufReader, Write};
+            let mut stream = std::net::TcpStream::connect(addr).unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut line = String::new();
+            for i in 0..100 {
+                stream.write_all(format!("{}\n", i).as_bytes()).unwrap();
+                line.clear();
+                reader.read_line(&mut line).unwrap();
+                assert_eq!(line, format!("{}\n", i));
+            }
+        });
+        let lines = rt.block_on(async move {
+            use std::io::Write;
+            let (mut reader, mut writer, _) = listener.accept().await;
+            let mut n = 0;
+            while let Some(buf) = reader.read_line().await {
+                writer.write_all(buf.as_bytes()).unwrap();
+                writer.flush().unwrap();
+                n += 1;
+            }
+            n
+        });
+        client.join().unwrap();
+        assert_eq!(lines, 100);
+        rt.shutdown();
+    }
+
+    #[test]
+    fn timeline_overlap() {
+        use std::time::Duration;
+        use timeline::{State, Timeline};
+        let ms = Duration::from_millis;
+
+        // A: 10-50 실행, B: 20-30 실행, 30-40 대기, 40-60 실행, C: 70까지 실행 전
+        let t = Timeline::new();
+        let a = t.process("A");
+        let b = t.process("B");
+        let c = t.process("C");
+        t.record_at(a.pid(), State::Running, ms(10), "start");
+        t.record_at(b.pid(), State::Running, ms(20), "start");
+        t.record_at(b.pid(), State::Waiting, ms(30), "lock");
+        t.record_at(b.pid(), State::Running, ms(40), "lock");
+        t.record_at(a.pid(), State::Terminated, ms(50), "exit");
+        t.record_at(b.pid(), State::Terminated, ms(60), "exit");
+        t.record_at(c.pid(), State::PreRun, ms(70), "spawn");
+
+        let overlap = t.overlap();
+        assert_eq!(overlap.concurrent, ms(30)); // 20-50
+        assert_eq!(overlap.parallel, ms(20)); // 20-30, 40-50
+
+        // 한 칸이 10ms
+        let gantt = t.gantt(7);
+        println!("{}", gantt);
+        let rows: Vec<&str> = gantt.lines().collect();
+        assert_eq!(rows[0], "         A | ####  |");
+        assert_eq!(rows[1], "         B |  #-## |");
+        assert_eq!(rows[2], "         C |       |");
+        assert_eq!(rows[3], "concurrent |  ~~~  |");
+        assert_eq!(rows[4], "  parallel |  = =  |");
+
+        let json = t.chrome_trace();
+        assert!(json.starts_with('[') && json.ends_with(']'));
+        assert!(json.contains(r#""name": "Waiting", "cat": "state", "ph": "X", "ts": 30000, "dur": 10000, "pid": 1, "tid": 1"#));
+        assert_eq!(json.matches("\"ph\": \"X\"").count(), 7);
+    }
+
+    #[test]
+    fn timeline_primitives() {
+        // 기록 대상 스레드에서 ch03의 Semaphore와 channel이 블록되면 Waiting이 기록된다.
+        use timeline::{State, Timeline};
+        let t = Timeline::new();
+        let sem = Arc::new(ch03_synchronous_processing01::Semaphore::new(1));
+        let (tx, rx) = ch03_synchronous_processing01::channel(1);
+        sem.wait();
+        let s = sem.clone();
+        let h = t.spawn("waiter", move || {
+            s.wait();
+            s.post();
+            rx.recv()
+        });
+        // waiter가 대기 상태가 된 것을 확인한 뒤 깨운다.
+        let waiting = |n| {
+            while t.events().iter().filter(|e| e.state == State::Waiting).count() < n {
+                thread::yield_now();
+            }
+        };
+        waiting(1);
+        sem.post();
+        waiting(2);
+        tx.send(7);
+        assert_eq!(h.join().unwrap(), 7);
+
+        let reasons: Vec<_> = t
+            .events()
+            .iter()
+            .filter(|e| e.state == State::Waiting)
+            .map(|e| e.reason)
+            .collect();
+        assert_eq!(reasons, ["Semaphore::wait", "Receiver::recv"]);
+        assert_eq!(t.events().last().unwrap().state, State::Terminated);
+    }
+
+    #[test]
+    fn instrument_site() {
+        use instrument::Site;
+        use race::Shared;
+        use timeline::{State, Timeline};
+        static SITE: Site = Site::new("test.site", "test.site::lock");
+        static V: Shared<u64> = Shared::new("instrument_site::V", 0);
+
+        // 처음 스핀할 때만 Waiting, 기다렸으면 획득할 때 Running. 기다리지 않은 획득은 기록하지 않는다.
+        let t = Timeline::new();
+        t.spawn("worker", || {
+            let mut wait = SITE.on_wait_begin();
+            wait.on_spin();
+            wait.on_spin();
+            let held = wait.on_acquired();
+            V.write(1);
+            SITE.on_released(held);
+            let held = SITE.on_wait_begin().on_acquired();
+            SITE.on_released(held);
+        })
+        .join()
+        .unwrap();
+        let states: Vec<_> = t
+            .events()
+            .iter()
+            .filter(|e| e.reason == "test.site::lock")
+            .map(|e| e.state)
+            .collect();
+        assert_eq!(states, [State::Waiting, State::Running]);
+
+        // 해제와 획득이 벡터 클록에 반영된다. std의 채널은 동기화로 인식되지 않으므로 순서를 정하는 것은 Site뿐이다.
+        let (tx, rx) = std::sync::mpsc::channel();
+        let t0 = race::spawn(move || {
+            let held = SITE.on_wait_begin().on_acquired();
+            V.write(2);
+            SITE.on_released(held);
+            tx.send(()).unwrap();
+        });
+        let t1 = race::spawn(move || {
+            rx.recv().unwrap();
+            let held = SITE.on_wait_begin().on_acquired();
+            V.write(V.read() + 1);
+            SITE.on_released(held);
+        });
+        t0.join().unwrap();
+        t1.join().unwrap();
+        assert_eq!(V.read(), 3);
+        assert!(race::take_races("instrument_site::V").is_empty());
+
+        if let Some(stats) = lockstat::get("test.site") {
+            assert_eq!(stats.acquisitions.load(Ordering::Relaxed), 4);
+            assert_eq!(stats.contended.load(Ordering::Relaxed), 1);
+            assert_eq!(stats.hold.count(), 4);
+        }
+    }
+
+    #[test]
+    fn weak_memory_fences() {
+        use weak_memory::{bakery, dekker, fence_report, peterson};
+        // (제거한 fence, [SC, TSO, PSO]에서 안전한가)
+        let bakery = fence_report(|removed| bakery(2, removed));
+        let peterson = fence_report(peterson);
+        let dekker = fence_report(dekker);
+        for (name, safe) in bakery.iter().chain(peterson.iter()).chain(dekker.iter()) {
+            assert!(safe[0], "{} must be safe on SC", name);
+        }
+        let get = |table: &[(&str, [bool; 3])], f: &str| table.iter().find(|(n, _)| *n == f).unwrap().1;
+
+        assert_eq!(get(&bakery, "(none)"), [true, true, true]);
+        assert_eq!(get(&bakery, "F2"), [true, false, false]);
+        assert_eq!(get(&bakery, "F3"), [true, true, false]);
+        assert_eq!(get(&bakery, "F6"), [true, true, true]);
+
+        assert_eq!(get(&peterson, "(none)"), [true, true, true]);
+        assert_eq!(get(&peterson, "F1"), [true, true, false]);
+        assert_eq!(get(&peterson, "F2"), [true, false, false]);
+
+        assert_eq!(get(&dekker, "(none)"), [true, true, true]);
+        assert_eq!(get(&dekker, "F1"), [true, false, false]);
+    }
+
+    #[test]
+    fn litmus_forbidden_outcomes() {
+        use litmus::{run, Config, Test};
+        use std::sync::atomic::Ordering::{Acquire, Release, SeqCst};
+
+        // 하드웨어와 관계없이 언어 수준의 메모리 모델이 금지하는 결과는 관찰되지 않아야 한다.
+        let cases = [
+            (Test::Sb, SeqCst, SeqCst, false),
+            (Test::Sb, Release, Acquire, true), // fence(SeqCst)로도 막을 수 있다
+            (Test::Mp, Release, Acquire, false),
+            (Test::Lb, Release, Acquire, false),
+            (Test::Iriw, SeqCst, SeqCst, false),
+        ];
+        for (test, store, load, fence) in cases {
+            let report = run(Config {
+                store,
+                load,
+                fence,
+                iterations: 2_000,
+                ..Config::new(test)
+            });
+            assert_eq!(report.outcomes.values().sum::<u64>(), 2_000);
+            assert_eq!(report.weak_count(), 0, "{}", report);
+        }
+    }
+
+    #[test]
+    fn progress_monitor_alerts() {
+        use progress_monitor::{jain_index, Alert, ProgressMonitor};
+        use std::sync::atomic::AtomicBool;
+        use std::time::Duration;
+
+        assert_eq!(jain_index(&[5.0; 4]), 1.0);
+        assert_eq!(jain_index(&[1.0, 0.0, 0.0, 0.0]), 0.25);
+        assert_eq!(jain_index(&[]), 1.0);
+
+        // busy는 계속 진행하고 stuck은 한 번만 진행한 뒤 멈춘다. stuck만 굶주림으로 보고된다.
+        let window = Duration::from_millis(100);
+        let monitor = ProgressMonitor::new(window);
+        let (busy, stuck) = (monitor.register("busy"), monitor.register("stuck"));
+        let stop = Arc::new(AtomicBool::new(false));
+        let s = stop.clone();
+        let t = thread::spawn(move || {
+            while !s.load(Ordering::Relaxed) {
+                busy.progress();
+                thread::yield_now();
+            }
+        });
+        stuck.progress();
+        thread::sleep(window * 4);
+        stop.store(true, Ordering::Relaxed);
+        t.join().unwrap();
+
+        let alerts = monitor.alerts();
+        assert!(alerts.contains(&Alert::Starvation { name: "stuck".to_string(), window }));
+        assert!(!alerts.iter().any(|a| matches!(a, Alert::Starvation { name, .. } if name == "busy")));
+        assert!(!alerts.iter().any(|a| matches!(a, Alert::Livelock { .. })));
+
+        let summary = monitor.shutdown();
+        assert_eq!(summary.progress.len(), 2);
+        assert_eq!(summary.progress[0].0, "busy");
+        assert_eq!(summary.progress[1], ("stuck".to_string(), 1));
+        assert!(summary.progress[0].1 > 1);
+        assert!(summary.jain_index < 1.0);
+        assert!(summary.alerts.starts_with(&alerts)); // shutdown 전까지 stuck의 굶주림이 더 보고될 수 있다.
+
+        // 상태 전이만 반복하고 아무도 진행하지 못하면 굶주림 대신 라이브락으로 보고된다.
+        let monitor = ProgressMonitor::with_livelock_threshold(window, 10);
+        let spinner = monitor.register("spinner");
+        let stop = Arc::new(AtomicBool::new(false));
+        let s = stop.clone();
+        let t = thread::spawn(move || {
+            while !s.load(Ordering::Relaxed) {
+                spinner.state_change();
+                thread::yield_now();
+            }
+        });
+        thread::sleep(window * 4);
+        stop.store(true, Ordering::Relaxed);
+        t.join().unwrap();
+
+        let summary = monitor.shutdown();
+        assert!(summary.alerts.iter().any(|a| matches!(a, Alert::Livelock { .. })));
+        assert_eq!(summary.progress, vec![("spinner".to_string(), 0)]);
+    }
+
+    #[test]
+    fn thread_registry_snapshot() {
+        use std::sync::mpsc::channel;
+        use std::time::{Duration, Instant};
+        use thread_registry::{ThreadInfo, ThreadState, TrackedMutex};
+
+        fn find(name: &str) -> Option<ThreadInfo> {
+            thread_registry::threads().into_iter().find(|t| t.name == name)
+        }
+
+        // func_167p_3과 같은 상황. 철학자 1이 포크 1을 잡은 채 대기하고, 철학자 0은 포크 0을 잡고 포크 1을 기다린다.
+        let c0 = Arc::new(TrackedMutex::new("fork 0", ()));
+        let c1 = Arc::new(TrackedMutex::new("fork 1", ()));
+        let (tx, rx) = channel::<()>();
+        let (held_tx, held_rx) = channel::<()>();
+
+        let c1_p1 = c1.clone();
+        let p1 = thread_registry::spawn("philosopher 1", move || {
+            let _n1 = c1_p1.lock();
+            held_tx.send(()).unwrap();
+            thread_registry::waiting("release request", || rx.recv().unwrap());
+        });
+        held_rx.recv().unwrap();
+        let p0 = thread_registry::spawn("philosopher 0", move || {
+            let _n1 = c0.lock();
+            let _n2 = c1.lock();
+        });
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while find("philosopher 0").map(|t| t.state) != Some(ThreadState::Waiting("fork 1".to_string())) {
+            assert!(Instant::now() < deadline, "{}", thread_registry::snapshot());
+            thread::sleep(Duration::from_millis(1));
+        }
+        let t1 = find("philosopher 1").unwrap();
+        assert_eq!(t1.state, ThreadState::Waiting("release request".to_string()));
+        assert_eq!(t1.held, vec!["fork 1".to_string()]);
+        let t0 = find("philosopher 0").unwrap();
+        assert_eq!(t0.held, vec!["fork 0".to_string()]);
+        assert!(t1.id < t0.id);
+
+        let snapshot = thread_registry::snapshot();
+        println!("{}", snapshot);
+        let line = |name: &str| snapshot.lines().find(|l| l.contains(name)).unwrap().to_string();
+        assert!(line("philosopher 1").contains("waiting for release request"));
+        assert!(line("philosopher 1").ends_with("held: [fork 1]"));
+        assert!(line("philosopher 0").contains("waiting for fork 1"));
+        assert!(line("philosopher 0").ends_with("held: [fork 0]"));
+
+        // 종료한 스레드는 레지스트리에서 제거된다.
+        tx.send(()).unwrap();
+        p1.join().unwrap();
+        p0.join().unwrap();
+        assert!(find("philosopher 0").is_none() && find("philosopher 1").is_none());
     }
 }
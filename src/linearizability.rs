@@ -0,0 +1,371 @@
+// 3.8.5 채널 위에 만든 컨테이너의 선형화 가능성(linearizability) 검사
+// ch03의 channel이나 그 위에 만든 큐를 테스트할 때 "카운터 값이 맞았다"는 것만으로는 올바르다고 할 수 없다. 동시에
+// 실행된 연산들의 호출(invoke)과 응답(response) 시각을 기록해 두고, 그 기록(history)을 어떤 순차 실행으로 설명할 수
+// 있는지 검사하는 Wing–Gong 알고리즘을 구현해보자.
+//
+// - 각 연산은 호출 시각과 응답 시각 사이의 어느 한 시점에 원자적으로 일어난 것처럼 보여야 한다.
+// - 응답 전에 다음 연산이 호출된 경우(겹친 연산)에는 두 순서 모두 가능하고, 그렇지 않으면 실제 시간 순서를 지켜야 한다.
+// - 순차 명세(Spec)는 FIFO 큐, 스택, 레지스터, 집합을 제공한다.
+// - 이미 탐색한 (선형화한 연산의 집합, 명세의 상태)는 다시 탐색하지 않는다(Lowe의 개선).
+// - 집합처럼 원소별로 독립인 명세는 원소별로 기록을 나눠 따로 검사한다(P-compositionality).
+// - 선형화할 수 없는 경우에는 연산을 하나씩 제거해 보면서 여전히 선형화할 수 없는 가장 작은 기록을 보고한다.
+//   단, 실제 시간 순서를 무시하면 설명할 수 있는 기록이었다면 그 성질은 유지하며 줄인다. 그렇지 않으면 "넣은 적 없는
+//   값을 꺼낸 Dequeue" 하나만 남아버려 어떤 순서가 문제인지 알 수 없기 때문이다.
+//
+// 응답이 없는(블록된 채로 끝난) 연산은 효과가 있었을 수도, 없었을 수도 있는 것으로 취급한다.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// 연산 하나의 기록
+#[derive(Debug, Clone, PartialEq)]
+pub struct Operation<O, R> {
+    pub thread: usize,
+    pub op: O,
+    pub ret: Option<R>,       // 응답이 없으면 None
+    pub invoke: u64,
+    pub response: Option<u64>,
+}
+
+/// 여러 스레드에서 호출과 응답을 기록한다. 시각은 전체에서 하나뿐인 논리 시계다.
+pub struct Recorder<O, R> {
+    clock: AtomicU64,
+    ops: Mutex<Vec<Operation<O, R>>>,
+}
+
+impl<O, R> Default for Recorder<O, R> {
+    fn default() -> Self {
+        Recorder::new()
+    }
+}
+
+impl<O, R> Recorder<O, R> {
+    pub fn new() -> Self {
+        Recorder {
+            clock: AtomicU64::new(0),
+            ops: Mutex::new(Vec::new()),
+        }
+    }
+
+    // 연산 호출을 기록하고 응답을 기록할 때 쓸 번호를 반환
+    pub fn invoke(&self, thread: usize, op: O) -> usize {
+        let mut ops = self.ops.lock().unwrap();
+        ops.push(Operation {
+            thread,
+            op,
+            ret: None,
+            invoke: self.clock.fetch_add(1, Ordering::SeqCst),
+            response: None,
+        });
+        ops.len() - 1
+    }
+
+    pub fn respond(&self, id: usize, ret: R) {
+        let mut ops = self.ops.lock().unwrap();
+        ops[id].ret = Some(ret);
+        ops[id].response = Some(self.clock.fetch_add(1, Ordering::SeqCst));
+    }
+
+    // f의 실행 전후를 호출과 응답으로 기록
+    pub fn call(&self, thread: usize, op: O, f: impl FnOnce() -> R) -> R
+    where
+        R: Clone,
+    {
+        let id = self.invoke(thread, op);
+        let ret = f();
+        self.respond(id, ret.clone());
+        ret
+    }
+
+    pub fn history(&self) -> Vec<Operation<O, R>>
+    where
+        O: Clone,
+        R: Clone,
+    {
+        self.ops.lock().unwrap().clone()
+    }
+}
+
+/// 순차 명세
+pub trait Spec {
+    type Op: Clone + fmt::Debug;
+    type Ret: Clone + PartialEq + fmt::Debug;
+    type State: Clone + Eq + Hash;
+
+    fn init() -> Self::State;
+
+    // 상태 state에서 op를 실행한 뒤의 상태와 반환값
+    fn step(state: &Self::State, op: &Self::Op) -> (Self::State, Self::Ret);
+
+    // 서로 다른 키의 연산끼리 독립이면 키를 반환(P-compositionality). None이면 나누지 않는다.
+    fn partition(_op: &Self::Op) -> Option<u64> {
+        None
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum QueueOp<T> {
+    Enqueue(T),
+    Dequeue,
+}
+
+/// FIFO 큐. Dequeue는 비어 있으면 None, Enqueue는 항상 None을 반환한다.
+pub struct Queue<T>(PhantomData<T>);
+
+impl<T: Clone + Eq + Hash + fmt::Debug> Spec for Queue<T> {
+    type Op = QueueOp<T>;
+    type Ret = Option<T>;
+    type State = VecDeque<T>;
+
+    fn init() -> Self::State {
+        VecDeque::new()
+    }
+
+    fn step(state: &Self::State, op: &Self::Op) -> (Self::State, Self::Ret) {
+        let mut s = state.clone();
+        match op {
+            QueueOp::Enqueue(v) => {
+                s.push_back(v.clone());
+                (s, None)
+            }
+            QueueOp::Dequeue => {
+                let v = s.pop_front();
+                (s, v)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StackOp<T> {
+    Push(T),
+    Pop,
+}
+
+/// LIFO 스택. Pop은 비어 있으면 None, Push는 항상 None을 반환한다.
+pub struct Stack<T>(PhantomData<T>);
+
+impl<T: Clone + Eq + Hash + fmt::Debug> Spec for Stack<T> {
+    type Op = StackOp<T>;
+    type Ret = Option<T>;
+    type State = Vec<T>;
+
+    fn init() -> Self::State {
+        Vec::new()
+    }
+
+    fn step(state: &Self::State, op: &Self::Op) -> (Self::State, Self::Ret) {
+        let mut s = state.clone();
+        match op {
+            StackOp::Push(v) => {
+                s.push(v.clone());
+                (s, None)
+            }
+            StackOp::Pop => {
+                let v = s.pop();
+                (s, v)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RegisterOp<T> {
+    Write(T),
+    Read,
+}
+
+/// 읽기/쓰기 레지스터. 초기값은 None이며 Read는 마지막으로 쓴 값, Write는 항상 None을 반환한다.
+pub struct Register<T>(PhantomData<T>);
+
+impl<T: Clone + Eq + Hash + fmt::Debug> Spec for Register<T> {
+    type Op = RegisterOp<T>;
+    type Ret = Option<T>;
+    type State = Option<T>;
+
+    fn init() -> Self::State {
+        None
+    }
+
+    fn step(state: &Self::State, op: &Self::Op) -> (Self::State, Self::Ret) {
+        match op {
+            RegisterOp::Write(v) => (Some(v.clone()), None),
+            RegisterOp::Read => (state.clone(), state.clone()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SetOp<T> {
+    Insert(T),
+    Remove(T),
+    Contains(T),
+}
+
+/// 집합. Insert와 Remove는 집합이 바뀌었는지, Contains는 원소가 있는지를 반환한다. 원소별로 나눠 검사한다.
+pub struct Set<T>(PhantomData<T>);
+
+impl<T: Clone + Ord + Hash + fmt::Debug> Spec for Set<T> {
+    type Op = SetOp<T>;
+    type Ret = bool;
+    type State = BTreeSet<T>;
+
+    fn init() -> Self::State {
+        BTreeSet::new()
+    }
+
+    fn step(state: &Self::State, op: &Self::Op) -> (Self::State, Self::Ret) {
+        let mut s = state.clone();
+        let ret = match op {
+            SetOp::Insert(v) => s.insert(v.clone()),
+            SetOp::Remove(v) => s.remove(v),
+            SetOp::Contains(v) => s.contains(v),
+        };
+        (s, ret)
+    }
+
+    fn partition(op: &Self::Op) -> Option<u64> {
+        let v = match op {
+            SetOp::Insert(v) | SetOp::Remove(v) | SetOp::Contains(v) => v,
+        };
+        let mut h = DefaultHasher::new();
+        v.hash(&mut h);
+        Some(h.finish())
+    }
+}
+
+/// 선형화할 수 없는 기록. history는 더 이상 연산을 제거할 수 없는 최소 기록이다.
+#[derive(Debug, Clone)]
+pub struct Violation<O, R> {
+    pub history: Vec<Operation<O, R>>,
+    pub original: usize, // 원래 기록의 연산 수
+}
+
+impl<O: fmt::Debug, R: fmt::Debug> fmt::Display for Violation<O, R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "not linearizable: minimal history of {} operation(s) (out of {})",
+            self.history.len(),
+            self.original
+        )?;
+        // 시각을 작은 순서대로 0, 1, 2, ...로 다시 매겨 타임라인으로 출력
+        let mut times: Vec<u64> = self
+            .history
+            .iter()
+            .flat_map(|o| std::iter::once(o.invoke).chain(o.response))
+            .collect();
+        times.sort_unstable();
+        times.dedup();
+        let col = |t: u64| times.binary_search(&t).unwrap() * 3;
+        let width = times.len() * 3;
+        for o in self.history.iter() {
+            let start = col(o.invoke);
+            let (end, close) = match o.response {
+                Some(t) => (col(t), ']'),
+                None => (width, '>'),
+            };
+            let bar: String = (0..=width)
+                .map(|i| match i {
+                    _ if i == start => '[',
+                    _ if i == end => close,
+                    _ if i > start && i < end => '-',
+                    _ => ' ',
+                })
+                .collect();
+            let ret = match &o.ret {
+                Some(r) => format!("{:?}", r),
+                None => "(pending)".to_string(),
+            };
+            writeln!(f, "  thread {:>2}: {} {:?} -> {}", o.thread, bar, o.op, ret)?;
+        }
+        Ok(())
+    }
+}
+
+// 검사 대상 연산 목록
+type Ops<'a, S> = Vec<&'a Operation<<S as Spec>::Op, <S as Spec>::Ret>>;
+
+// Wing–Gong 탐색. done[i]는 i번째 연산을 이미 선형화했는지. real_time이 false면 호출/응답 시각을 무시한다.
+fn search<S: Spec>(
+    ops: &[&Operation<S::Op, S::Ret>],
+    real_time: bool,
+    done: &mut Vec<bool>,
+    state: &S::State,
+    cache: &mut HashSet<(Vec<bool>, S::State)>,
+) -> bool {
+    // 응답이 있는 연산을 모두 선형화했으면 성공. 응답이 없는 연산은 일어나지 않은 것으로 해도 된다.
+    let remaining = ops.iter().zip(done.iter()).filter(|(_, d)| !**d);
+    let limit = match remaining.filter_map(|(o, _)| o.response).min() {
+        Some(t) => t,
+        None => return true,
+    };
+    // 아직 응답하지 않은 연산 중 가장 먼저 끝난 연산보다 먼저 호출된 연산만 다음 순서가 될 수 있다.
+    for i in 0..ops.len() {
+        if done[i] || (real_time && ops[i].invoke > limit) {
+            continue;
+        }
+        let (next, ret) = S::step(state, &ops[i].op);
+        if ops[i].ret.as_ref().is_some_and(|r| *r != ret) {
+            continue;
+        }
+        done[i] = true;
+        if cache.insert((done.clone(), next.clone())) && search::<S>(ops, real_time, done, &next, cache) {
+            return true;
+        }
+        done[i] = false;
+    }
+    false
+}
+
+fn linearizable<S: Spec>(ops: &[&Operation<S::Op, S::Ret>]) -> bool {
+    let mut done = vec![false; ops.len()];
+    search::<S>(ops, true, &mut done, &S::init(), &mut HashSet::new())
+}
+
+// 실제 시간 순서를 무시하면 어떤 순차 실행으로 설명할 수 있는가
+fn explainable<S: Spec>(ops: &[&Operation<S::Op, S::Ret>]) -> bool {
+    let mut done = vec![false; ops.len()];
+    search::<S>(ops, false, &mut done, &S::init(), &mut HashSet::new())
+}
+
+// 선형화할 수 없는 상태를 유지하면서 연산을 하나씩 제거
+fn shrink<S: Spec>(mut ops: Ops<'_, S>) -> Ops<'_, S> {
+    let keep_explainable = explainable::<S>(&ops);
+    let mut i = 0;
+    while i < ops.len() {
+        let mut smaller = ops.clone();
+        smaller.remove(i);
+        if linearizable::<S>(&smaller) || (keep_explainable && !explainable::<S>(&smaller)) {
+            i += 1;
+        } else {
+            ops = smaller;
+        }
+    }
+    ops
+}
+
+/// history가 명세 S에 대해 선형화 가능한지 검사
+pub fn check<S: Spec>(history: &[Operation<S::Op, S::Ret>]) -> Result<(), Violation<S::Op, S::Ret>> {
+    // 키별로 나눔. 키가 없는 연산은 모두 같은 부분 기록에 들어간다.
+    let mut parts: HashMap<Option<u64>, Ops<'_, S>> = HashMap::new();
+    for o in history.iter() {
+        parts.entry(S::partition(&o.op)).or_default().push(o);
+    }
+    for ops in parts.into_values() {
+        if !linearizable::<S>(&ops) {
+            let mut minimal = shrink::<S>(ops);
+            minimal.sort_by_key(|o| o.invoke);
+            return Err(Violation {
+                history: minimal.into_iter().cloned().collect(),
+                original: history.len(),
+            });
+        }
+    }
+    Ok(())
+}
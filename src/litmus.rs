@@ -0,0 +1,265 @@
+// 4.7 리트머스 테스트로 리오더링 관찰하기
+// ch03의 read_mem!/write_mem! 매크로와 fence, 4.7절의 리오더링 패턴 표는 모두 "이런 리오더링이 일어날 수 있다"는
+// 설명뿐이고 실제로 일어나는 모습은 보여주지 않는다. 그래서 메모리 모델 연구에서 이용하는 고전적인 리트머스 테스트를
+// 실제 CPU 위에서 수십만~수백만 번 실행해 관찰된 결과를 세어보는 하니스를 구현해보자.
+//
+// 변수 x, y는 처음에 0이며 r0, r1, ...은 각 스레드가 읽은 값이다.
+// - SB(store buffering)  : T0: x = 1; r0 = y          T1: y = 1; r1 = x
+//                          r0 = r1 = 0이면 W->R 리오더링. x86-64(TSO)에서도 관찰된다.
+// - MP(message passing)  : T0: x = 1; y = 1           T1: r0 = y; r1 = x
+//                          r0 = 1, r1 = 0이면 W->W 또는 R->R 리오더링. x86-64에서는 일어나지 않는다.
+// - LB(load buffering)   : T0: r0 = x; y = 1          T1: r1 = y; x = 1
+//                          r0 = r1 = 1이면 R->W 리오더링. x86-64에서는 일어나지 않는다.
+// - IRIW(independent reads of independent writes)
+//                        : T0: x = 1   T1: y = 1   T2: r0 = x; r1 = y   T3: r2 = y; r3 = x
+//                          (1, 0, 1, 0)이면 두 관찰자가 두 쓰기의 순서에 동의하지 않은 것(multi-copy atomic이 아님).
+//
+// 각 스레드는 가능하면 서로 다른 CPU에 고정(pin)하고, 매 반복마다 스핀 배리어로 동시에 출발시킨다.
+// 읽기/쓰기의 Ordering과 두 명령 사이의 fence(SeqCst) 유무를 바꿔가며 결과 분포를 비교할 수 있다.
+// CPU가 하나뿐인 환경에서는 스레드가 시분할로 실행되므로 리오더링은 관찰되지 않는다.
+//
+// Config::new의 반복 횟수는 기본 2만 회로, 데모를 바로 돌려볼 수 있는 정도다. 드물게 일어나는 리오더링(IRIW 등)까지
+// 보려면 환경 변수 LITMUS_ITERATIONS로 백만 회 이상을 지정한다.
+//   $ LITMUS_ITERATIONS=1000000 cargo test --release
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::atomic::{fence, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Test {
+    Sb,
+    Mp,
+    Lb,
+    Iriw,
+}
+
+impl Test {
+    fn threads(self) -> usize {
+        match self {
+            Test::Iriw => 4,
+            _ => 2,
+        }
+    }
+
+    // 순차 일관성에서는 나올 수 없는 결과
+    pub fn weak_outcome(self) -> Vec<usize> {
+        match self {
+            Test::Sb => vec![0, 0],
+            Test::Mp => vec![1, 0],
+            Test::Lb => vec![1, 1],
+            Test::Iriw => vec![1, 0, 1, 0],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Config {
+    pub test: Test,
+    pub store: Ordering,
+    pub load: Ordering,
+    pub fence: bool, // 각 스레드의 두 명령 사이에 fence(SeqCst)
+    pub iterations: usize,
+    pub pin: bool,
+}
+
+// LITMUS_ITERATIONS가 없을 때의 Config::new의 반복 횟수
+pub const DEFAULT_ITERATIONS: usize = 20_000;
+
+// 환경 변수 LITMUS_ITERATIONS로 지정한 반복 횟수. 지정하지 않았거나 숫자가 아니면 DEFAULT_ITERATIONS
+pub fn iterations() -> usize {
+    std::env::var("LITMUS_ITERATIONS")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(DEFAULT_ITERATIONS)
+}
+
+impl Config {
+    // Relaxed, fence 없음, iterations()회
+    pub fn new(test: Test) -> Self {
+        Config {
+            test,
+            store: Ordering::Relaxed,
+            load: Ordering::Relaxed,
+            fence: false,
+            iterations: iterations(),
+            pin: true,
+        }
+    }
+}
+
+/// 결과 분포
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub config: Config,
+    pub outcomes: BTreeMap<Vec<usize>, u64>, // (r0, r1, ...) -> 관찰 횟수
+}
+
+impl Report {
+    // 순차 일관성에서 나올 수 없는 결과가 관찰된 횟수
+    pub fn weak_count(&self) -> u64 {
+        *self.outcomes.get(&self.config.test.weak_outcome()).unwrap_or(&0)
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let c = &self.config;
+        writeln!(
+            f,
+            "{:?}: store = {:?}, load = {:?}, fence = {}, iterations = {}",
+            c.test, c.store, c.load, c.fence, c.iterations
+        )?;
+        let weak = c.test.weak_outcome();
+        for (outcome, n) in self.outcomes.iter() {
+            let mark = if *outcome == weak { "  <- weak" } else { "" };
+            writeln!(f, "  {:?}: {:>10}{}", outcome, n, mark)?;
+        }
+        Ok(())
+    }
+}
+
+// 모든 스레드가 도착할 때까지 스핀하는 배리어. std::sync::Barrier는 futex로 잠들기 때문에 출발 시각이 흩어진다.
+struct SpinBarrier {
+    n: usize,
+    spin_limit: u32, // 이만큼 스핀한 뒤에는 yield. CPU보다 스레드가 많으면 0
+    count: AtomicUsize,
+    generation: AtomicUsize,
+}
+
+impl SpinBarrier {
+    fn wait(&self) {
+        let gen = self.generation.load(Ordering::Acquire);
+        if self.count.fetch_add(1, Ordering::AcqRel) + 1 == self.n {
+            self.count.store(0, Ordering::Relaxed);
+            self.generation.fetch_add(1, Ordering::Release);
+            return;
+        }
+        let mut spins = 0u32;
+        while self.generation.load(Ordering::Acquire) == gen {
+            spins += 1;
+            if spins < self.spin_limit {
+                std::hint::spin_loop();
+            } else {
+                thread::yield_now();
+            }
+        }
+    }
+}
+
+struct Shared {
+    x: AtomicUsize,
+    y: AtomicUsize,
+    results: Vec<[AtomicUsize; 2]>, // 스레드별로 읽은 값
+    barrier: SpinBarrier,
+}
+
+fn pin(cpu: usize) {
+    use nix::sched::{sched_setaffinity, CpuSet};
+    use nix::unistd::Pid;
+    let mut set = CpuSet::new();
+    if set.set(cpu).is_ok() {
+        let _ = sched_setaffinity(Pid::from_raw(0), &set); // 실패하면 고정하지 않고 진행
+    }
+}
+
+// 스레드 tid의 명령열을 한 번 실행. 읽은 값은 results[tid]에 기록한다.
+fn body(c: &Config, s: &Shared, tid: usize) {
+    let f = || {
+        if c.fence {
+            fence(Ordering::SeqCst);
+        }
+    };
+    let (a, b) = if tid.is_multiple_of(2) { (&s.x, &s.y) } else { (&s.y, &s.x) };
+    let r = &s.results[tid];
+    match (c.test, tid) {
+        (Test::Sb, _) => {
+            a.store(1, c.store);
+            f();
+            r[0].store(b.load(c.load), Ordering::Relaxed);
+        }
+        (Test::Mp, 0) => {
+            s.x.store(1, c.store);
+            f();
+            s.y.store(1, c.store);
+        }
+        (Test::Mp, _) => {
+            r[0].store(s.y.load(c.load), Ordering::Relaxed);
+            f();
+            r[1].store(s.x.load(c.load), Ordering::Relaxed);
+        }
+        (Test::Lb, _) => {
+            r[0].store(a.load(c.load), Ordering::Relaxed);
+            f();
+            b.store(1, c.store);
+        }
+        (Test::Iriw, 0) => s.x.store(1, c.store),
+        (Test::Iriw, 1) => s.y.store(1, c.store),
+        (Test::Iriw, _) => {
+            // T2는 x -> y, T3은 y -> x 순으로 읽는다.
+            r[0].store(a.load(c.load), Ordering::Relaxed);
+            f();
+            r[1].store(b.load(c.load), Ordering::Relaxed);
+        }
+    }
+}
+
+// 결과 벡터. 읽기를 하지 않는 스레드는 제외한다.
+fn collect(c: &Config, s: &Shared) -> Vec<usize> {
+    let get = |t: usize, i: usize| s.results[t][i].load(Ordering::Relaxed);
+    match c.test {
+        Test::Sb | Test::Lb => vec![get(0, 0), get(1, 0)],
+        Test::Mp => vec![get(1, 0), get(1, 1)],
+        Test::Iriw => vec![get(2, 0), get(2, 1), get(3, 0), get(3, 1)],
+    }
+}
+
+pub fn run(config: Config) -> Report {
+    let n = config.test.threads();
+    let cpus = thread::available_parallelism().map_or(1, |c| c.get());
+    let shared = Arc::new(Shared {
+        x: AtomicUsize::new(0),
+        y: AtomicUsize::new(0),
+        results: (0..n).map(|_| [AtomicUsize::new(0), AtomicUsize::new(0)]).collect(),
+        barrier: SpinBarrier {
+            n,
+            spin_limit: if cpus >= n { 1000 } else { 0 },
+            count: AtomicUsize::new(0),
+            generation: AtomicUsize::new(0),
+        },
+    });
+
+    let handles: Vec<_> = (0..n)
+        .map(|tid| {
+            let s = shared.clone();
+            thread::spawn(move || {
+                if config.pin {
+                    pin(tid % cpus);
+                }
+                let mut outcomes = BTreeMap::new();
+                for _ in 0..config.iterations {
+                    s.barrier.wait(); // 동시에 출발
+                    body(&config, &s, tid);
+                    s.barrier.wait(); // 모두 끝날 때까지 대기
+                    if tid == 0 {
+                        // 집계와 초기화는 스레드 0이 한다. 다음 반복의 배리어가 초기화를 다른 스레드에 보여준다.
+                        *outcomes.entry(collect(&config, &s)).or_insert(0) += 1;
+                        s.x.store(0, Ordering::Relaxed);
+                        s.y.store(0, Ordering::Relaxed);
+                    }
+                }
+                outcomes
+            })
+        })
+        .collect();
+
+    let mut outcomes = BTreeMap::new();
+    for h in handles {
+        for (k, v) in h.join().unwrap() {
+            *outcomes.entry(k).or_insert(0) += v;
+        }
+    }
+    Report { config, outcomes }
+}
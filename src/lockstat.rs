@@ -0,0 +1,360 @@
+// 3장 락의 경합 통계
+// 서비스에서 어떤 락이 병목인지 알 수 없다. 그래서 cargo feature lockstat를 켜면 SpinLock, BakeryLock, Semaphore,
+// channel이 락마다 다음을 기록하도록 해보자. feature를 끄면 Probe와 관련 타입은 크기가 0이고 모든 메서드가 아무것도
+// 하지 않으므로 비용이 없다.
+// - 획득 횟수, 경합한(바로 획득하지 못한) 획득 횟수
+// - 획득 1회당 스핀 횟수, 대기 시간, 보유 시간(획득부터 해제까지)의 히스토그램
+//
+// 히스토그램은 HDR 히스토그램과 같은 로그-선형 버킷을 이용한다. 2의 거듭제곱 구간마다 16개의 버킷으로 나누므로 값의
+// 크기와 상관없이 상대 오차가 약 6% 이내이고, 기록은 아토믹 덧셈 한 번이다.
+//
+// 통계는 이름별로 레지스트리에 모이며, 같은 이름의 락은 통계를 공유한다(Semaphore::new로 만든 세마포어는 모두
+// "Semaphore"). 세마포어와 채널처럼 획득한 스레드와 해제하는 스레드가 다를 수 있는 경우에는 먼저 획득한 것이 먼저
+// 해제된다고 보고 보유 시간을 잰다. 채널에서는 메시지가 큐에 머문 시간이 된다.
+//   $ cargo test --features lockstat
+
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+#[cfg(feature = "lockstat")]
+use std::sync::{Arc, Mutex, OnceLock};
+#[cfg(feature = "lockstat")]
+use std::collections::VecDeque;
+#[cfg(feature = "lockstat")]
+use std::time::Instant;
+use std::time::Duration;
+
+const SUB_BITS: u32 = 4;
+const SUB: u64 = 1 << SUB_BITS; // 2의 거듭제곱 구간당 버킷 수
+const BUCKETS: usize = ((64 - SUB_BITS + 1) as usize) * SUB as usize;
+
+/// HDR 스타일의 로그-선형 히스토그램
+pub struct Histogram {
+    buckets: Vec<AtomicU64>,
+    count: AtomicU64,
+    sum: AtomicU64,
+    max: AtomicU64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Histogram::new()
+    }
+}
+
+impl Histogram {
+    pub fn new() -> Self {
+        Histogram {
+            buckets: (0..BUCKETS).map(|_| AtomicU64::new(0)).collect(),
+            count: AtomicU64::new(0),
+            sum: AtomicU64::new(0),
+            max: AtomicU64::new(0),
+        }
+    }
+
+    fn index(v: u64) -> usize {
+        if v < SUB {
+            return v as usize;
+        }
+        let shift = 63 - v.leading_zeros() - SUB_BITS;
+        ((shift as u64 + 1) * SUB + ((v >> shift) - SUB)) as usize
+    }
+
+    // 버킷 idx에 들어가는 가장 작은 값
+    fn lower_bound(idx: usize) -> u64 {
+        let idx = idx as u64;
+        if idx < SUB {
+            return idx;
+        }
+        let shift = idx / SUB - 1;
+        (SUB + idx % SUB) << shift
+    }
+
+    pub fn record(&self, v: u64) {
+        self.buckets[Histogram::index(v)].fetch_add(1, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum.fetch_add(v, Ordering::Relaxed);
+        self.max.fetch_max(v, Ordering::Relaxed);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    pub fn max(&self) -> u64 {
+        self.max.load(Ordering::Relaxed)
+    }
+
+    pub fn mean(&self) -> f64 {
+        let n = self.count();
+        if n == 0 {
+            0.0
+        } else {
+            self.sum.load(Ordering::Relaxed) as f64 / n as f64
+        }
+    }
+
+    // 백분위수(0.0~100.0). 해당 버킷의 하한을 반환하며, 최대값이 들어 있는 버킷이면 최대값을 반환한다.
+    pub fn percentile(&self, p: f64) -> u64 {
+        let n = self.count();
+        let max = self.max();
+        if n == 0 {
+            return 0;
+        }
+        let rank = ((p / 100.0) * n as f64).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (i, b) in self.buckets.iter().enumerate() {
+            seen += b.load(Ordering::Relaxed);
+            if seen >= rank {
+                return if i == Histogram::index(max) { max } else { Histogram::lower_bound(i) };
+            }
+        }
+        max
+    }
+
+    // other의 기록을 더함
+    pub fn merge(&self, other: &Histogram) {
+        for (a, b) in self.buckets.iter().zip(other.buckets.iter()) {
+            a.fetch_add(b.load(Ordering::Relaxed), Ordering::Relaxed);
+        }
+        self.count.fetch_add(other.count(), Ordering::Relaxed);
+        self.sum.fetch_add(other.sum.load(Ordering::Relaxed), Ordering::Relaxed);
+        self.max.fetch_max(other.max(), Ordering::Relaxed);
+    }
+}
+
+/// 이름 하나의 락 통계
+pub struct LockStats {
+    pub name: &'static str,
+    pub acquisitions: AtomicU64,
+    pub contended: AtomicU64,
+    pub spins: Histogram, // 획득 1회당 스핀 횟수
+    pub wait: Histogram,  // ns
+    pub hold: Histogram,  // ns
+}
+
+impl LockStats {
+    pub fn new(name: &'static str) -> Self {
+        LockStats {
+            name,
+            acquisitions: AtomicU64::new(0),
+            contended: AtomicU64::new(0),
+            spins: Histogram::new(),
+            wait: Histogram::new(),
+            hold: Histogram::new(),
+        }
+    }
+}
+
+fn ns(v: u64) -> String {
+    format!("{:?}", Duration::from_nanos(v))
+}
+
+impl fmt::Display for LockStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let acq = self.acquisitions.load(Ordering::Relaxed);
+        let contended = self.contended.load(Ordering::Relaxed);
+        writeln!(
+            f,
+            "{}: acquisitions = {}, contended = {} ({:.1}%)",
+            self.name,
+            acq,
+            contended,
+            if acq == 0 { 0.0 } else { contended as f64 * 100.0 / acq as f64 }
+        )?;
+        let h = &self.spins;
+        writeln!(
+            f,
+            "  spins: mean = {:.1}, p50 = {}, p99 = {}, max = {}",
+            h.mean(),
+            h.percentile(50.0),
+            h.percentile(99.0),
+            h.max()
+        )?;
+        for (label, h) in [("wait", &self.wait), ("hold", &self.hold)] {
+            writeln!(
+                f,
+                "  {}: mean = {}, p50 = {}, p99 = {}, max = {}",
+                label,
+                ns(h.mean() as u64),
+                ns(h.percentile(50.0)),
+                ns(h.percentile(99.0)),
+                ns(h.max())
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "lockstat")]
+static REGISTRY: Mutex<Vec<Arc<LockStats>>> = Mutex::new(Vec::new());
+
+// 이름으로 통계를 찾거나 새로 등록
+#[cfg(feature = "lockstat")]
+fn register(name: &'static str) -> Arc<LockStats> {
+    let mut r = REGISTRY.lock().unwrap();
+    if let Some(s) = r.iter().find(|s| s.name == name) {
+        return s.clone();
+    }
+    let s = Arc::new(LockStats::new(name));
+    r.push(s.clone());
+    s
+}
+
+/// 이름으로 통계를 찾음. feature가 꺼져 있으면 항상 None
+pub fn get(name: &str) -> Option<std::sync::Arc<LockStats>> {
+    #[cfg(feature = "lockstat")]
+    {
+        REGISTRY.lock().unwrap().iter().find(|s| s.name == name).cloned()
+    }
+    #[cfg(not(feature = "lockstat"))]
+    {
+        let _ = name;
+        None
+    }
+}
+
+/// 등록된 모든 락의 통계를 이름순으로 출력
+pub fn dump() -> String {
+    #[cfg(feature = "lockstat")]
+    {
+        let mut stats = REGISTRY.lock().unwrap().clone();
+        stats.sort_by_key(|s| s.name);
+        stats.iter().map(|s| s.to_string()).collect()
+    }
+    #[cfg(not(feature = "lockstat"))]
+    {
+        String::new()
+    }
+}
+
+pub fn enabled() -> bool {
+    cfg!(feature = "lockstat")
+}
+
+/// 락에 넣는 계측 지점. 처음 기록할 때 레지스트리에 등록하므로 static 변수에도 넣을 수 있다.
+pub struct Probe {
+    #[cfg(feature = "lockstat")]
+    name: &'static str,
+    #[cfg(feature = "lockstat")]
+    stats: OnceLock<Arc<LockStats>>,
+    #[cfg(feature = "lockstat")]
+    held: Mutex<VecDeque<Instant>>, // 세마포어/채널용. 획득 순서대로의 획득 시각
+}
+
+/// 획득 시도 하나. 스핀 횟수와 블록 여부를 모은다.
+pub struct Attempt {
+    #[cfg(feature = "lockstat")]
+    start: Instant,
+    #[cfg(feature = "lockstat")]
+    spins: u64,
+    #[cfg(feature = "lockstat")]
+    blocked: bool,
+}
+
+/// 획득한 락. 해제할 때 Probe::released에 넘긴다.
+#[derive(Clone, Copy)]
+pub struct Held {
+    #[cfg(feature = "lockstat")]
+    since: Instant,
+}
+
+impl Attempt {
+    #[inline(always)]
+    pub fn spin(&mut self) {
+        #[cfg(feature = "lockstat")]
+        {
+            self.spins += 1;
+        }
+    }
+
+    // 조건 변수 등으로 잠들었음(경합)
+    #[inline(always)]
+    pub fn block(&mut self) {
+        #[cfg(feature = "lockstat")]
+        {
+            self.blocked = true;
+        }
+    }
+}
+
+impl Probe {
+    #[cfg(feature = "lockstat")]
+    pub const fn new(name: &'static str) -> Self {
+        Probe {
+            name,
+            stats: OnceLock::new(),
+            held: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    #[cfg(not(feature = "lockstat"))]
+    pub const fn new(_name: &'static str) -> Self {
+        Probe {}
+    }
+
+    #[cfg(feature = "lockstat")]
+    fn stats(&self) -> &LockStats {
+        self.stats.get_or_init(|| register(self.name))
+    }
+
+    #[inline(always)]
+    pub fn begin(&self) -> Attempt {
+        Attempt {
+            #[cfg(feature = "lockstat")]
+            start: Instant::now(),
+            #[cfg(feature = "lockstat")]
+            spins: 0,
+            #[cfg(feature = "lockstat")]
+            blocked: false,
+        }
+    }
+
+    #[inline(always)]
+    pub fn acquired(&self, attempt: Attempt) -> Held {
+        #[cfg(feature = "lockstat")]
+        {
+            let s = self.stats();
+            let now = Instant::now();
+            s.acquisitions.fetch_add(1, Ordering::Relaxed);
+            if attempt.blocked || attempt.spins > 0 {
+                s.contended.fetch_add(1, Ordering::Relaxed);
+            }
+            s.spins.record(attempt.spins);
+            s.wait.record((now - attempt.start).as_nanos() as u64);
+            Held { since: now }
+        }
+        #[cfg(not(feature = "lockstat"))]
+        {
+            let _ = attempt;
+            Held {}
+        }
+    }
+
+    #[inline(always)]
+    pub fn released(&self, held: Held) {
+        #[cfg(feature = "lockstat")]
+        self.stats().hold.record(held.since.elapsed().as_nanos() as u64);
+        #[cfg(not(feature = "lockstat"))]
+        let _ = held;
+    }
+
+    // 세마포어/채널용. 획득 시각을 Probe 안에 보관한다.
+    #[inline(always)]
+    pub fn acquired_shared(&self, attempt: Attempt) {
+        let _held = self.acquired(attempt);
+        #[cfg(feature = "lockstat")]
+        self.held.lock().unwrap().push_back(_held.since);
+    }
+
+    // 세마포어/채널용. 가장 먼저 획득한 것이 해제되었다고 본다.
+    #[inline(always)]
+    pub fn released_shared(&self) {
+        #[cfg(feature = "lockstat")]
+        {
+            let since = self.held.lock().unwrap().pop_front();
+            if let Some(since) = since {
+                self.released(Held { since });
+            }
+        }
+    }
+}
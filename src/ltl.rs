@@ -0,0 +1,385 @@
+// 6.1.1 공평성을 선형 시제 논리(LTL)로 검사하기
+// ch06에서는 약한 공평성과 강한 공평성을 LTL로 정식화할 수 있다고만 설명했다. 여기서는 스케줄러나 락이 남긴 실행
+// 기록(trace)에 대해 LTL 식을 평가하는 작은 검사기를 구현해서 공평성 위반을 CI에서 잡을 수 있게 해보자.
+//
+// trace는 이벤트의 열이며 각 이벤트는 태스크 하나의 상태 변화다.
+// - Runnable(t): 태스크 t가 실행 가능해짐
+// - Ran(t)     : 태스크 t가 실행됨(실행 후에도 Blocked가 기록될 때까지는 실행 가능한 상태로 본다)
+// - Blocked(t) : 태스크 t가 실행 불가능해짐(락이나 IO 대기 등)
+//
+// 식은 다음으로 구성된다. i번째 위치에서의 의미는 다음과 같다.
+// - 술어 runnable(t), blocked(t): i번째 이벤트까지 반영한 t의 상태, ran(t): i번째 이벤트가 Ran(t)
+// - X φ: 다음 위치에서 φ. 마지막 위치에서는 거짓
+// - G φ: 지금부터 항상 φ,  F φ: 지금부터 언젠가 φ
+// - φ U ψ: 언젠가 ψ이고 그때까지는 계속 φ
+//
+// 공평성은 무한한 실행에 대한 성질이므로 유한한 기록을 그대로 평가하면 G F 같은 식은 마지막 위치만 보게 된다.
+// 그래서 기록의 어느 위치 이후(loop_start..)를 무한히 반복하는 올가미(lasso) 모양 실행으로 해석할 수도 있다.
+// 스케줄러의 정상 상태를 한 주기 이상 기록하고 그 부분을 반복 구간으로 지정하면 된다. 기록의 끝에서의 태스크 상태로
+// 반복 구간을 다시 지날 수 없는 위치는 반복 구간의 시작으로 지정할 수 없다(Trace::lasso가 panic).
+//   약한 공평성: F G runnable(t) -> G F ran(t)
+//   강한 공평성: G F runnable(t) -> G F ran(t)
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EventKind {
+    Runnable,
+    Ran,
+    Blocked,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Event {
+    pub task: usize,
+    pub kind: EventKind,
+}
+
+/// 여러 스레드에서 이벤트를 기록한다.
+#[derive(Debug, Default)]
+pub struct Recorder {
+    events: Mutex<Vec<Event>>,
+}
+
+impl Recorder {
+    pub fn new() -> Self {
+        Recorder::default()
+    }
+
+    pub fn record(&self, task: usize, kind: EventKind) {
+        self.events.lock().unwrap().push(Event { task, kind });
+    }
+
+    pub fn runnable(&self, task: usize) {
+        self.record(task, EventKind::Runnable);
+    }
+
+    pub fn ran(&self, task: usize) {
+        self.record(task, EventKind::Ran);
+    }
+
+    pub fn blocked(&self, task: usize) {
+        self.record(task, EventKind::Blocked);
+    }
+
+    pub fn trace(&self) -> Trace {
+        Trace::new(self.events.lock().unwrap().clone())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pred {
+    Runnable(usize),
+    Ran(usize),
+    Blocked(usize),
+}
+
+impl fmt::Display for Pred {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Pred::Runnable(t) => write!(f, "runnable({})", t),
+            Pred::Ran(t) => write!(f, "ran({})", t),
+            Pred::Blocked(t) => write!(f, "blocked({})", t),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Formula {
+    True,
+    False,
+    Atom(Pred),
+    Not(Box<Formula>),
+    And(Box<Formula>, Box<Formula>),
+    Or(Box<Formula>, Box<Formula>),
+    Next(Box<Formula>),
+    Until(Box<Formula>, Box<Formula>),
+    Globally(Box<Formula>),
+    Finally(Box<Formula>),
+}
+
+// 식을 조립하기 위한 함수
+pub fn atom(p: Pred) -> Formula {
+    Formula::Atom(p)
+}
+
+pub fn not(f: Formula) -> Formula {
+    Formula::Not(Box::new(f))
+}
+
+pub fn and(a: Formula, b: Formula) -> Formula {
+    Formula::And(Box::new(a), Box::new(b))
+}
+
+pub fn or(a: Formula, b: Formula) -> Formula {
+    Formula::Or(Box::new(a), Box::new(b))
+}
+
+pub fn implies(a: Formula, b: Formula) -> Formula {
+    or(not(a), b)
+}
+
+pub fn next(f: Formula) -> Formula {
+    Formula::Next(Box::new(f))
+}
+
+pub fn until(a: Formula, b: Formula) -> Formula {
+    Formula::Until(Box::new(a), Box::new(b))
+}
+
+pub fn globally(f: Formula) -> Formula {
+    Formula::Globally(Box::new(f))
+}
+
+pub fn finally(f: Formula) -> Formula {
+    Formula::Finally(Box::new(f))
+}
+
+// 약한 공평성: F G runnable(t) -> G F ran(t)
+pub fn weak_fairness(task: usize) -> Formula {
+    implies(
+        finally(globally(atom(Pred::Runnable(task)))),
+        globally(finally(atom(Pred::Ran(task)))),
+    )
+}
+
+// 강한 공평성: G F runnable(t) -> G F ran(t)
+pub fn strong_fairness(task: usize) -> Formula {
+    implies(
+        globally(finally(atom(Pred::Runnable(task)))),
+        globally(finally(atom(Pred::Ran(task)))),
+    )
+}
+
+impl fmt::Display for Formula {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Formula::True => write!(f, "true"),
+            Formula::False => write!(f, "false"),
+            Formula::Atom(p) => write!(f, "{}", p),
+            Formula::Not(a) => match a.as_ref() {
+                Formula::Atom(_) => write!(f, "!{}", a),
+                _ => write!(f, "!({})", a),
+            },
+            Formula::And(a, b) => write!(f, "({} & {})", a, b),
+            // !a | b는 a -> b로 출력
+            Formula::Or(a, b) => match a.as_ref() {
+                Formula::Not(a) => write!(f, "({} -> {})", a, b),
+                _ => write!(f, "({} | {})", a, b),
+            },
+            Formula::Next(a) => write!(f, "X {}", a),
+            Formula::Until(a, b) => write!(f, "({} U {})", a, b),
+            Formula::Globally(a) => write!(f, "G {}", a),
+            Formula::Finally(a) => write!(f, "F {}", a),
+        }
+    }
+}
+
+/// 실행 기록
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trace {
+    pub events: Vec<Event>,
+    pub loop_start: Option<usize>, // Some이면 events[loop_start..]를 무한히 반복하는 실행으로 해석
+}
+
+impl Trace {
+    pub fn new(events: Vec<Event>) -> Self {
+        Trace {
+            events,
+            loop_start: None,
+        }
+    }
+
+    // events[start..]를 반복 구간으로 지정. 반복 구간을 두 번째로 지날 때도 각 위치에서 모든 태스크의 상태가 처음과
+    // 같아야 하므로, 기록의 끝에서의 상태로 반복 구간을 다시 지나서 확인하고 다르면 panic한다. 예를 들어 반복 구간
+    // 안에서 Blocked만 되고 다시 Runnable이 되지 않는 태스크가 있으면 그런 실행은 반복될 수 없다.
+    pub fn lasso(mut self, start: usize) -> Self {
+        assert!(start < self.events.len());
+        let mut status = HashMap::new();
+        let mut first = Vec::with_capacity(self.events.len() - start); // 처음 지날 때의 위치별 상태
+        for (i, e) in self.events.iter().enumerate() {
+            Trace::apply(&mut status, e);
+            if i >= start {
+                first.push(status.clone());
+            }
+        }
+        for (i, e) in self.events[start..].iter().enumerate() {
+            Trace::apply(&mut status, e);
+            assert!(
+                status == first[i],
+                "lasso: task states at position {} differ on the second pass of the loop starting at {}",
+                start + i,
+                start
+            );
+        }
+        self.loop_start = Some(start);
+        self
+    }
+
+    // 이벤트 하나를 태스크별 상태에 반영. Ran은 실행 가능한 상태로 본다.
+    fn apply(status: &mut HashMap<usize, EventKind>, e: &Event) {
+        let kind = match e.kind {
+            EventKind::Runnable | EventKind::Ran => EventKind::Runnable,
+            EventKind::Blocked => EventKind::Blocked,
+        };
+        status.insert(e.task, kind);
+    }
+
+    // 기록에 나타난 태스크(번호 순)
+    pub fn tasks(&self) -> Vec<usize> {
+        let mut tasks: Vec<usize> = self.events.iter().map(|e| e.task).collect();
+        tasks.sort_unstable();
+        tasks.dedup();
+        tasks
+    }
+
+    // i번째 위치의 다음 위치
+    fn succ(&self, i: usize) -> Option<usize> {
+        if i + 1 < self.events.len() {
+            Some(i + 1)
+        } else {
+            self.loop_start
+        }
+    }
+
+    // 술어의 위치별 참/거짓
+    fn atom(&self, p: Pred) -> Vec<bool> {
+        let task = match p {
+            Pred::Runnable(t) | Pred::Ran(t) | Pred::Blocked(t) => t,
+        };
+        let mut status = None; // 아직 이벤트가 없으면 어느 쪽도 아님
+        let mut v = Vec::with_capacity(self.events.len());
+        for e in self.events.iter() {
+            if e.task == task {
+                status = match e.kind {
+                    EventKind::Runnable | EventKind::Ran => Some(EventKind::Runnable),
+                    EventKind::Blocked => Some(EventKind::Blocked),
+                };
+            }
+            v.push(match p {
+                Pred::Runnable(_) => status == Some(EventKind::Runnable),
+                Pred::Blocked(_) => status == Some(EventKind::Blocked),
+                Pred::Ran(_) => e.task == task && e.kind == EventKind::Ran,
+            });
+        }
+        v
+    }
+
+    // 모든 위치에서의 식의 참/거짓. 반복 구간이 있으면 U는 최소 고정점이 될 때까지 반복해서 계산한다.
+    fn eval(&self, f: &Formula) -> Vec<bool> {
+        let n = self.events.len();
+        match f {
+            Formula::True => vec![true; n],
+            Formula::False => vec![false; n],
+            Formula::Atom(p) => self.atom(*p),
+            Formula::Not(a) => self.eval(a).into_iter().map(|x| !x).collect(),
+            Formula::And(a, b) => {
+                let (a, b) = (self.eval(a), self.eval(b));
+                a.iter().zip(b.iter()).map(|(x, y)| *x && *y).collect()
+            }
+            Formula::Or(a, b) => {
+                let (a, b) = (self.eval(a), self.eval(b));
+                a.iter().zip(b.iter()).map(|(x, y)| *x || *y).collect()
+            }
+            Formula::Next(a) => {
+                let a = self.eval(a);
+                (0..n).map(|i| self.succ(i).is_some_and(|j| a[j])).collect()
+            }
+            Formula::Until(a, b) => {
+                let (a, b) = (self.eval(a), self.eval(b));
+                let mut v = vec![false; n];
+                loop {
+                    let mut changed = false;
+                    for i in (0..n).rev() {
+                        let x = b[i] || (a[i] && self.succ(i).is_some_and(|j| v[j]));
+                        if x != v[i] {
+                            v[i] = x;
+                            changed = true;
+                        }
+                    }
+                    if !changed {
+                        break v;
+                    }
+                }
+            }
+            Formula::Finally(a) => self.eval(&until(Formula::True, (**a).clone())),
+            Formula::Globally(a) => self.eval(&not(finally(not((**a).clone())))),
+        }
+    }
+
+    // 처음 위치에서 식이 성립하는가. 빈 기록에서는 항상 성립한다.
+    pub fn holds(&self, f: &Formula) -> bool {
+        self.eval(f).first().copied().unwrap_or(true)
+    }
+
+    // 약한/강한 공평성을 만족하지 않는 태스크
+    pub fn unfair_tasks(&self, strong: bool) -> Vec<usize> {
+        self.tasks()
+            .into_iter()
+            .filter(|&t| {
+                let f = if strong { strong_fairness(t) } else { weak_fairness(t) };
+                !self.holds(&f)
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, e) in self.events.iter().enumerate() {
+            if Some(i) == self.loop_start {
+                write!(f, "| ")?;
+            }
+            let c = match e.kind {
+                EventKind::Runnable => '+',
+                EventKind::Ran => '*',
+                EventKind::Blocked => '-',
+            };
+            write!(f, "{}{} ", c, e.task)?;
+        }
+        if self.loop_start.is_some() {
+            write!(f, "|")?;
+        }
+        Ok(())
+    }
+}
+
+/// 스케줄러 시뮬레이션용. 태스크별로 실행 가능 여부를 관리하면서 이벤트를 기록한다.
+#[derive(Debug, Default)]
+pub struct Sim {
+    runnable: HashMap<usize, bool>,
+    events: Vec<Event>,
+}
+
+impl Sim {
+    pub fn new() -> Self {
+        Sim::default()
+    }
+
+    pub fn set_runnable(&mut self, task: usize, runnable: bool) {
+        if self.runnable.insert(task, runnable) != Some(runnable) {
+            let kind = if runnable { EventKind::Runnable } else { EventKind::Blocked };
+            self.events.push(Event { task, kind });
+        }
+    }
+
+    pub fn is_runnable(&self, task: usize) -> bool {
+        self.runnable.get(&task).copied().unwrap_or(false)
+    }
+
+    pub fn run(&mut self, task: usize) {
+        assert!(self.is_runnable(task), "task {} is not runnable", task);
+        self.events.push(Event { task, kind: EventKind::Ran });
+    }
+
+    // 지금까지의 이벤트 수. 반복 구간의 시작 위치로 이용한다.
+    pub fn position(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn trace(&self) -> Trace {
+        Trace::new(self.events.clone())
+    }
+}
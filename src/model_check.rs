@@ -0,0 +1,1166 @@
+// 모든 인터리빙을 탐색하는 모델 검사기
+// 1.4.2절에서 본 것처럼 프로세스가 n개면 계산 경로는 n!개가 되고, 드물게 일어나는 버그는 그중 몇 개의 경로에만 숨어 있다.
+// 보통의 테스트는 OS 스케줄러가 우연히 고른 경로 몇 개만 실행하므로 이런 버그를 거의 찾지 못한다. 그래서 loom처럼
+// 스레드 전환이 일어날 수 있는 지점(아토믹 변수 접근, 락, 조건 변수, 스레드 생성과 join)을 모두 스케줄러가 직접
+// 결정하게 하고, 가능한 모든 인터리빙을 깊이 우선으로 하나씩 실행해보는 검사기를 구현해보자.
+//
+// - 검사 대상 코드는 std 대신 이 모듈의 shim(thread, sync::Mutex, sync::Condvar, sync::atomic, hint)을 이용한다.
+// - 실제 OS 스레드를 이용하지만 한 번에 하나의 스레드만 실행되며, 전환 지점마다 다음에 실행할 스레드를 고른다.
+// - 한 번의 실행이 끝나면 마지막 선택 지점으로 되돌아가(backtrack) 아직 고르지 않은 스레드를 골라 다시 처음부터 실행한다.
+// - 서로 독립인 연산(다른 변수에 대한 접근, 같은 변수의 읽기끼리)은 순서를 바꿔도 결과가 같으므로 sleep set을 이용한
+//   partial-order reduction으로 같은 결과가 되는 인터리빙의 탐색을 생략한다.
+// - assert 실패(패닉), 데드락, 모든 스레드가 스핀 중인 라이브락을 찾으면 그때의 스케줄(선택한 스레드 번호의 나열)과
+//   실행 순서를 함께 보고한다. 보고된 스케줄은 replay로 다시 실행할 수 있다.
+//
+// 메모리 모델은 순차 일관성(sequential consistency)으로 가정하며 Ordering 인자는 무시한다. 즉, 여기서는 인터리빙에
+// 의한 버그만 찾는다. 스핀 루프는 hint::spin_loop을 호출해야 하며, 호출한 스레드는 다른 스레드가 무언가를 쓸
+// 때까지 스케줄 대상에서 제외된다(다시 읽어도 같은 값을 읽을 뿐이므로). 그렇지 않으면 탐색이 끝나지 않는다.
+//
+// 결정적 스케줄러(seed 모드)
+// some_func9_129p처럼 스레드와 반복 횟수가 많으면 모든 인터리빙을 탐색할 수 없다. 이런 경우에는 전환 지점마다
+// seed로 초기화한 난수로 다음 스레드를 고르는 run_seed를 이용한다. 같은 seed면 같은 스케줄이 되므로 실패한 seed를
+// 그대로 다시 실행해 버그를 재현할 수 있고, fuzz는 여러 seed를 차례로 시도해 처음 실패한 seed를 보고한다.
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::ops::Range;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar as StdCondvar, Mutex as StdMutex, MutexGuard as StdMutexGuard};
+
+type Tid = usize;
+
+// 아토믹 변수, 뮤텍스, 조건 변수를 구별하는 번호
+static NEXT_OBJECT: AtomicUsize = AtomicUsize::new(1);
+
+fn new_object() -> usize {
+    NEXT_OBJECT.fetch_add(1, Ordering::Relaxed)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Access {
+    Read,
+    Write, // store뿐만 아니라 swap, compare_exchange, fetch_add 등 읽고 쓰는 연산 포함
+}
+
+// 스레드가 다음에 실행하려는 연산. 전환 지점에서 스케줄러에게 알린다.
+#[derive(Debug, Clone, PartialEq)]
+enum Op {
+    Start, // 생성 직후. 첫 전환 지점까지는 스레드 로컬한 처리뿐이다.
+    Atomic(usize, Access),
+    Lock(usize),
+    Unlock(usize),
+    Wait(usize, usize), // (조건 변수, 뮤텍스)
+    Notify(usize),
+    Spawn,
+    Join(Tid),
+    Spin,
+}
+
+// 두 연산의 순서를 바꿔도 결과가 같은가
+fn independent(a: &Op, b: &Op) -> bool {
+    use Op::*;
+    match (a, b) {
+        (Start, _) | (_, Start) => true,
+        (Atomic(x, ax), Atomic(y, ay)) => x != y || (*ax == Access::Read && *ay == Access::Read),
+        (Atomic(..), Lock(_) | Unlock(_) | Wait(..) | Notify(_)) => true,
+        (Lock(_) | Unlock(_) | Wait(..) | Notify(_), Atomic(..)) => true,
+        (Lock(m) | Unlock(m), Lock(n) | Unlock(n)) => m != n,
+        (Lock(m) | Unlock(m), Wait(c, n)) | (Wait(c, n), Lock(m) | Unlock(m)) => m != n && m != c,
+        (Lock(m) | Unlock(m), Notify(c)) | (Notify(c), Lock(m) | Unlock(m)) => m != c,
+        (Wait(c, m), Wait(d, n)) => c != d && m != n,
+        (Wait(c, _), Notify(d)) | (Notify(d), Wait(c, _)) => c != d,
+        (Notify(c), Notify(d)) => c != d,
+        // 스레드 생성, join, 스핀은 다른 스레드의 실행 가능 여부를 바꾸므로 보수적으로 모두 의존으로 취급
+        _ => false,
+    }
+}
+
+// 깊이 우선 탐색의 선택 지점 하나
+#[derive(Clone)]
+struct Decision {
+    enabled: Vec<Tid>,
+    pending: Vec<Option<Op>>, // 선택 시점의 각 스레드의 다음 연산
+    chosen: Tid,
+    done: Vec<Tid>,  // 이미 탐색을 마친 선택
+    sleep: Vec<Tid>, // 탐색할 필요가 없는 선택(sleep set)
+}
+
+struct ThreadState {
+    pending: Option<Op>, // 종료하면 None
+    waiting_cv: Option<usize>,
+    spinning: bool,
+}
+
+/// 검사 실패의 종류
+#[derive(Debug, Clone, PartialEq)]
+pub enum FailureKind {
+    Panic(String),
+    Deadlock,
+    Livelock,  // 종료하지 않은 스레드가 모두 스핀 중
+    StepLimit, // 한 번의 실행에서 전환 지점이 max_steps를 넘음
+}
+
+/// 실패한 실행의 보고
+#[derive(Debug, Clone)]
+pub struct Failure {
+    pub kind: FailureKind,
+    pub schedule: Vec<usize>, // 선택 지점마다 고른 스레드 번호. replay에 넘길 수 있다.
+    pub trace: Vec<String>,   // 실행한 연산의 순서
+    pub executions: usize,    // 몇 번째 실행에서 찾았는가
+    pub seed: Option<u64>,    // seed 모드에서 찾은 경우 그 seed. run_seed에 넘기면 재현된다.
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "model check failed: {:?} (execution #{})", self.kind, self.executions)?;
+        if let Some(seed) = self.seed {
+            writeln!(f, "seed: {}", seed)?;
+        }
+        writeln!(f, "schedule: {:?}", self.schedule)?;
+        for (i, t) in self.trace.iter().enumerate() {
+            writeln!(f, "  {:>3}: {}", i, t)?;
+        }
+        Ok(())
+    }
+}
+
+/// 검사 결과 요약
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stats {
+    pub executions: usize,
+    pub pruned: usize,   // partial-order reduction으로 도중에 중단한 실행
+    pub complete: bool, // max_executions 안에 모든 인터리빙을 탐색했는가
+}
+
+struct State {
+    threads: Vec<ThreadState>,
+    active: Tid,
+    locks: HashMap<usize, Tid>,
+    cv_waiters: HashMap<usize, VecDeque<Tid>>,
+    path: Vec<Decision>,
+    rng: Option<StdRng>, // seed 모드이면 경로 탐색 대신 난수로 선택
+    depth: usize,
+    steps: usize,
+    max_steps: usize,
+    trace: Vec<String>,
+    failure: Option<FailureKind>,
+    pruned: bool,
+    aborted: bool,  // 실패 또는 가지치기로 실행을 중단
+    finished: bool, // 모든 스레드가 종료
+}
+
+struct Execution {
+    state: StdMutex<State>,
+    cond: StdCondvar,
+    handles: StdMutex<Vec<std::thread::JoinHandle<()>>>,
+}
+
+// 실행 중단 시 스레드를 풀어내기 위한 패닉 payload. resume_unwind로 던지므로 패닉 메시지는 출력되지 않는다.
+struct Aborted;
+
+thread_local! {
+    static CURRENT: RefCell<Option<(Arc<Execution>, Tid)>> = const { RefCell::new(None) };
+}
+
+fn current() -> Option<(Arc<Execution>, Tid)> {
+    CURRENT.with(|c| c.borrow().clone())
+}
+
+impl State {
+    fn enabled(&self, t: Tid) -> bool {
+        let th = &self.threads[t];
+        if th.waiting_cv.is_some() || th.spinning {
+            return false;
+        }
+        match &th.pending {
+            None => false,
+            Some(Op::Lock(m)) => !self.locks.contains_key(m),
+            Some(Op::Join(u)) => self.threads[*u].pending.is_none(),
+            Some(_) => true,
+        }
+    }
+
+    fn fail(&mut self, kind: FailureKind) {
+        if !self.aborted {
+            self.failure = Some(kind);
+            self.aborted = true;
+        }
+    }
+
+    // 다음에 실행할 스레드를 고른다. 실행이 끝났거나 중단했으면 None.
+    fn decide(&mut self, prefer: Tid) -> Option<Tid> {
+        let n = self.threads.len();
+        let enabled: Vec<Tid> = (0..n).filter(|&t| self.enabled(t)).collect();
+        if enabled.is_empty() {
+            if self.threads.iter().all(|t| t.pending.is_none()) {
+                self.finished = true;
+            } else if self.threads.iter().any(|t| t.spinning) {
+                self.fail(FailureKind::Livelock);
+            } else {
+                self.fail(FailureKind::Deadlock);
+            }
+            return None;
+        }
+
+        self.steps += 1;
+        if self.steps > self.max_steps {
+            self.fail(FailureKind::StepLimit);
+            return None;
+        }
+
+        let depth = self.depth;
+        self.depth += 1;
+        let chosen = if depth < self.path.len() {
+            // 이전 실행과 같은 경로를 재현
+            self.path[depth].chosen
+        } else if let Some(rng) = self.rng.as_mut() {
+            let chosen = enabled[rng.gen_range(0..enabled.len())];
+            self.path.push(Decision {
+                enabled: Vec::new(),
+                pending: Vec::new(),
+                chosen,
+                done: Vec::new(),
+                sleep: Vec::new(),
+            });
+            chosen
+        } else {
+            // 부모의 sleep set과 탐색을 마친 선택 중 부모가 고른 연산과 독립인 것은 여기서도 탐색할 필요가 없다.
+            // (replay로 만든 경로에는 pending이 기록되어 있지 않으므로 빈 sleep set으로 시작)
+            let parent = depth.checked_sub(1).map(|i| &self.path[i]);
+            let sleep: Vec<Tid> = match parent.and_then(|p| Some((p, p.pending.get(p.chosen)?.clone()?))) {
+                Some((p, op)) => p
+                    .sleep
+                    .iter()
+                    .chain(p.done.iter())
+                    .copied()
+                    .filter(|&t| {
+                        t != p.chosen && p.pending[t].as_ref().is_some_and(|o| independent(o, &op))
+                    })
+                    .collect(),
+                None => Vec::new(),
+            };
+            let candidates: Vec<Tid> =
+                enabled.iter().copied().filter(|t| !sleep.contains(t)).collect();
+            if candidates.is_empty() {
+                // 이 앞의 인터리빙은 모두 이미 탐색한 것과 같은 결과가 된다.
+                self.pruned = true;
+                self.aborted = true;
+                return None;
+            }
+            // 문맥 교환이 적은 경로부터 탐색하도록 현재 스레드를 우선
+            let chosen = if candidates.contains(&prefer) {
+                prefer
+            } else {
+                candidates[0]
+            };
+            self.path.push(Decision {
+                enabled,
+                pending: self.threads.iter().map(|t| t.pending.clone()).collect(),
+                chosen,
+                done: Vec::new(),
+                sleep,
+            });
+            chosen
+        };
+
+        if !self.enabled(chosen) {
+            // replay에 넘긴 스케줄이 이 프로그램의 것이 아님
+            self.fail(FailureKind::Panic(format!(
+                "schedule diverged at step {}: thread {} is not runnable",
+                depth, chosen
+            )));
+            return None;
+        }
+        let op = self.threads[chosen].pending.clone().unwrap();
+        self.trace.push(format!("thread {}: {:?}", chosen, op));
+        Some(chosen)
+    }
+
+    // 연산의 효과 중 스케줄러가 관리하는 부분을 반영
+    fn apply(&mut self, me: Tid, op: &Op) {
+        match op {
+            Op::Lock(m) => {
+                self.locks.insert(*m, me);
+            }
+            Op::Unlock(m) => {
+                self.locks.remove(m);
+                self.wake_spinners();
+            }
+            Op::Atomic(_, Access::Write) => self.wake_spinners(),
+            Op::Wait(c, m) => {
+                self.locks.remove(m);
+                self.wake_spinners();
+                self.cv_waiters.entry(*c).or_default().push_back(me);
+                self.threads[me].waiting_cv = Some(*c);
+                self.threads[me].pending = Some(Op::Lock(*m)); // 알림을 받으면 락을 다시 획득
+            }
+            _ => {}
+        }
+    }
+
+    fn wake_spinners(&mut self) {
+        for t in self.threads.iter_mut() {
+            t.spinning = false;
+        }
+    }
+
+    fn notify(&mut self, cv: usize, all: bool) {
+        let waiters = self.cv_waiters.entry(cv).or_default();
+        let n = if all { waiters.len() } else { waiters.len().min(1) };
+        let woken: Vec<Tid> = waiters.drain(..n).collect();
+        for t in woken {
+            self.threads[t].waiting_cv = None;
+        }
+    }
+}
+
+impl Execution {
+    // 다음 스레드를 골라 실행시키고, 자신(me)이 다시 선택될 때까지 대기
+    fn switch<'a>(&'a self, mut st: StdMutexGuard<'a, State>, me: Tid) -> StdMutexGuard<'a, State> {
+        if let Some(next) = st.decide(me) {
+            st.active = next;
+        }
+        self.cond.notify_all();
+        while st.active != me && !st.aborted {
+            st = self.cond.wait(st).unwrap();
+        }
+        if st.aborted && !std::thread::panicking() {
+            drop(st);
+            panic::resume_unwind(Box::new(Aborted));
+        }
+        st
+    }
+}
+
+// 전환 지점. op를 실행하기 직전에 호출한다. 검사기 밖(일반 테스트)에서 호출되면 아무것도 하지 않는다.
+fn point(op: Op) {
+    let (exec, me) = match current() {
+        Some(c) => c,
+        None => return,
+    };
+    if std::thread::panicking() {
+        // 풀어내는 중(unwinding)의 drop에서 호출된 경우는 스케줄하지 않는다.
+        return;
+    }
+    let mut st = exec.state.lock().unwrap();
+    if st.aborted {
+        drop(st);
+        panic::resume_unwind(Box::new(Aborted));
+    }
+    st.threads[me].pending = Some(op.clone());
+    if op == Op::Spin {
+        // 다른 스레드가 쓸 때까지 스케줄 대상에서 제외
+        st.threads[me].spinning = true;
+    }
+    st = exec.switch(st, me);
+    st.apply(me, &op);
+    if let Op::Wait(_, m) = op {
+        st = exec.switch(st, me);
+        st.locks.insert(m, me);
+    }
+}
+
+fn notify_point(cv: usize, all: bool) {
+    point(Op::Notify(cv));
+    if let Some((exec, _)) = current() {
+        exec.state.lock().unwrap().notify(cv, all);
+    }
+}
+
+// 검사기가 관리하는 스레드 본체
+fn run_thread<F: FnOnce()>(exec: Arc<Execution>, me: Tid, f: F) {
+    CURRENT.with(|c| *c.borrow_mut() = Some((exec.clone(), me)));
+
+    let start = {
+        let mut st = exec.state.lock().unwrap();
+        while st.active != me && !st.aborted {
+            st = exec.cond.wait(st).unwrap();
+        }
+        !st.aborted
+    };
+    let result = if start {
+        panic::catch_unwind(AssertUnwindSafe(f))
+    } else {
+        Ok(())
+    };
+
+    let mut st = exec.state.lock().unwrap();
+    if let Err(e) = result {
+        if !e.is::<Aborted>() {
+            let msg = if let Some(s) = e.downcast_ref::<&str>() {
+                s.to_string()
+            } else if let Some(s) = e.downcast_ref::<String>() {
+                s.clone()
+            } else {
+                "panic".to_string()
+            };
+            st.fail(FailureKind::Panic(msg));
+        }
+    }
+    st.threads[me].pending = None;
+    if !st.aborted {
+        if let Some(next) = st.decide(me) {
+            st.active = next;
+        }
+    }
+    exec.cond.notify_all();
+    drop(st);
+    CURRENT.with(|c| *c.borrow_mut() = None);
+}
+
+/// 모델 검사기
+pub struct Checker {
+    max_executions: usize,
+    max_steps: usize,
+}
+
+impl Default for Checker {
+    fn default() -> Self {
+        Checker::new()
+    }
+}
+
+impl Checker {
+    // 기본값은 실행 10만 회, 실행당 전환 지점 1만 개
+    pub fn new() -> Self {
+        Checker {
+            max_executions: 100_000,
+            max_steps: 10_000,
+        }
+    }
+
+    pub fn max_executions(mut self, n: usize) -> Self {
+        self.max_executions = n;
+        self
+    }
+
+    pub fn max_steps(mut self, n: usize) -> Self {
+        self.max_steps = n;
+        self
+    }
+
+    // f의 모든 인터리빙을 탐색. 처음 찾은 실패를 반환한다.
+    pub fn check<F>(&self, f: F) -> Result<Stats, Failure>
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        let f: Arc<dyn Fn() + Send + Sync> = Arc::new(f);
+        let mut path = Vec::new();
+        let mut stats = Stats {
+            executions: 0,
+            pruned: 0,
+            complete: false,
+        };
+
+        while stats.executions < self.max_executions {
+            stats.executions += 1;
+            let (p, pruned, failure) = self.execute(path, None, f.clone());
+            if let Some(mut failure) = failure {
+                failure.executions = stats.executions;
+                return Err(failure);
+            }
+            if pruned {
+                stats.pruned += 1;
+            }
+            match backtrack(p) {
+                Some(p) => path = p,
+                None => {
+                    stats.complete = true;
+                    break;
+                }
+            }
+        }
+        Ok(stats)
+    }
+
+    // Failure::schedule로 보고된 실행 하나를 재현
+    pub fn replay<F>(&self, schedule: &[usize], f: F) -> Result<(), Failure>
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        let path = schedule
+            .iter()
+            .map(|&t| Decision {
+                enabled: Vec::new(),
+                pending: Vec::new(),
+                chosen: t,
+                done: Vec::new(),
+                sleep: Vec::new(),
+            })
+            .collect();
+        match self.execute(path, None, Arc::new(f)).2 {
+            Some(mut failure) => {
+                failure.executions = 1;
+                Err(failure)
+            }
+            None => Ok(()),
+        }
+    }
+
+    // seed로 정해지는 스케줄로 한 번 실행
+    pub fn run_seed<F>(&self, seed: u64, f: F) -> Result<(), Failure>
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        self.run_seed_arc(seed, Arc::new(f))
+    }
+
+    fn run_seed_arc(&self, seed: u64, f: Arc<dyn Fn() + Send + Sync>) -> Result<(), Failure> {
+        match self.execute(Vec::new(), Some(seed), f).2 {
+            Some(mut failure) => {
+                failure.executions = 1;
+                failure.seed = Some(seed);
+                Err(failure)
+            }
+            None => Ok(()),
+        }
+    }
+
+    // seeds의 seed를 차례로 시도. 모두 통과하면 실행 횟수를, 실패하면 처음 실패한 실행을 반환한다.
+    pub fn fuzz<F>(&self, seeds: Range<u64>, f: F) -> Result<usize, Failure>
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        let f: Arc<dyn Fn() + Send + Sync> = Arc::new(f);
+        let mut executions = 0;
+        for seed in seeds {
+            executions += 1;
+            if let Err(mut failure) = self.run_seed_arc(seed, f.clone()) {
+                failure.executions = executions;
+                return Err(failure);
+            }
+        }
+        Ok(executions)
+    }
+
+    // 경로 path를 따라 한 번 실행. 경로 뒤쪽은 새로 선택하며, 선택 결과를 포함한 경로를 반환한다.
+    fn execute(
+        &self,
+        path: Vec<Decision>,
+        seed: Option<u64>,
+        f: Arc<dyn Fn() + Send + Sync>,
+    ) -> (Vec<Decision>, bool, Option<Failure>) {
+        let exec = Arc::new(Execution {
+            state: StdMutex::new(State {
+                threads: vec![ThreadState {
+                    pending: Some(Op::Start),
+                    waiting_cv: None,
+                    spinning: false,
+                }],
+                active: 0,
+                locks: HashMap::new(),
+                cv_waiters: HashMap::new(),
+                path,
+                rng: seed.map(StdRng::seed_from_u64),
+                depth: 0,
+                steps: 0,
+                max_steps: self.max_steps,
+                trace: Vec::new(),
+                failure: None,
+                pruned: false,
+                aborted: false,
+                finished: false,
+            }),
+            cond: StdCondvar::new(),
+            handles: StdMutex::new(Vec::new()),
+        });
+
+        let exec0 = exec.clone();
+        let main = std::thread::spawn(move || run_thread(exec0, 0, move || f()));
+        main.join().unwrap();
+
+        // 실행 중(또는 중단 후 풀어내는 중)인 스레드가 모두 끝날 때까지 대기
+        loop {
+            let h = exec.handles.lock().unwrap().pop();
+            match h {
+                Some(h) => h.join().unwrap(),
+                None => break,
+            }
+        }
+
+        let mut st = exec.state.lock().unwrap();
+        debug_assert!(st.finished || st.aborted);
+        let path = std::mem::take(&mut st.path);
+        let failure = st.failure.take().map(|kind| Failure {
+            kind,
+            schedule: path[..st.depth.min(path.len())].iter().map(|d| d.chosen).collect(),
+            trace: std::mem::take(&mut st.trace),
+            executions: 0,
+            seed: None,
+        });
+        (path, st.pruned, failure)
+    }
+}
+
+// 마지막 선택 지점부터 아직 탐색하지 않은 선택을 찾는다. 없으면 탐색 종료.
+fn backtrack(mut path: Vec<Decision>) -> Option<Vec<Decision>> {
+    while let Some(mut d) = path.pop() {
+        d.done.push(d.chosen);
+        let next = d
+            .enabled
+            .iter()
+            .copied()
+            .find(|t| !d.done.contains(t) && !d.sleep.contains(t));
+        if let Some(t) = next {
+            d.chosen = t;
+            path.push(d);
+            return Some(path);
+        }
+    }
+    None
+}
+
+/// 기본 설정으로 검사
+pub fn check<F>(f: F) -> Result<Stats, Failure>
+where
+    F: Fn() + Send + Sync + 'static,
+{
+    Checker::new().check(f)
+}
+
+/// std::thread 대신 이용하는 shim
+pub mod thread {
+    use super::{current, point, run_thread, Op, ThreadState, Tid};
+    use std::sync::{Arc, Mutex};
+
+    pub struct JoinHandle<T> {
+        tid: Tid,
+        result: Arc<Mutex<Option<T>>>,
+        std: Option<std::thread::JoinHandle<()>>, // 검사기 밖에서 생성된 경우
+    }
+
+    pub fn spawn<F, T>(f: F) -> JoinHandle<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let result = Arc::new(Mutex::new(None));
+        let result0 = result.clone();
+        let body = move || {
+            let v = f();
+            *result0.lock().unwrap() = Some(v);
+        };
+
+        let (exec, _) = match current() {
+            Some(c) => c,
+            None => {
+                return JoinHandle {
+                    tid: 0,
+                    result,
+                    std: Some(std::thread::spawn(body)),
+                }
+            }
+        };
+        point(Op::Spawn);
+        let tid = {
+            let mut st = exec.state.lock().unwrap();
+            st.threads.push(ThreadState {
+                pending: Some(Op::Start),
+                waiting_cv: None,
+                spinning: false,
+            });
+            st.threads.len() - 1
+        };
+        let exec0 = exec.clone();
+        let h = std::thread::spawn(move || run_thread(exec0, tid, body));
+        exec.handles.lock().unwrap().push(h);
+        JoinHandle {
+            tid,
+            result,
+            std: None,
+        }
+    }
+
+    impl<T> JoinHandle<T> {
+        pub fn join(self) -> std::thread::Result<T> {
+            match self.std {
+                Some(h) => h.join()?,
+                None => point(Op::Join(self.tid)),
+            }
+            Ok(self.result.lock().unwrap().take().unwrap())
+        }
+    }
+}
+
+/// std::hint 대신 이용하는 shim
+pub mod hint {
+    // 스핀 루프 안에서 반드시 호출. 다른 스레드가 쓰기를 할 때까지 이 스레드는 선택되지 않는다.
+    pub fn spin_loop() {
+        if super::current().is_some() {
+            super::point(super::Op::Spin);
+        } else {
+            std::hint::spin_loop();
+        }
+    }
+}
+
+/// std::sync 대신 이용하는 shim. std와 같은 API를 갖추므로 검사 대상이 쓰지 않는 메서드도 있다.
+#[allow(dead_code)]
+pub mod sync {
+    use super::{current, new_object, notify_point, point, Op};
+    use std::ops::{Deref, DerefMut};
+    use std::sync::LockResult;
+
+    pub struct Mutex<T> {
+        id: usize,
+        inner: std::sync::Mutex<T>,
+    }
+
+    pub struct MutexGuard<'a, T> {
+        lock: &'a Mutex<T>,
+        guard: Option<std::sync::MutexGuard<'a, T>>,
+    }
+
+    impl<T> Mutex<T> {
+        pub fn new(v: T) -> Self {
+            Mutex {
+                id: new_object(),
+                inner: std::sync::Mutex::new(v),
+            }
+        }
+
+        // 검사 대상 코드가 std와 같은 모양이 되도록 LockResult를 반환(poison은 일어나지 않는다)
+        pub fn lock(&self) -> LockResult<MutexGuard<'_, T>> {
+            point(Op::Lock(self.id));
+            Ok(MutexGuard {
+                lock: self,
+                guard: Some(self.inner.lock().unwrap_or_else(|e| e.into_inner())),
+            })
+        }
+    }
+
+    impl<'a, T> Drop for MutexGuard<'a, T> {
+        fn drop(&mut self) {
+            if self.guard.take().is_some() {
+                point(Op::Unlock(self.lock.id));
+            }
+        }
+    }
+
+    impl<'a, T> Deref for MutexGuard<'a, T> {
+        type Target = T;
+
+        fn deref(&self) -> &Self::Target {
+            self.guard.as_ref().unwrap()
+        }
+    }
+
+    impl<'a, T> DerefMut for MutexGuard<'a, T> {
+        fn deref_mut(&mut self) -> &mut Self::Target {
+            self.guard.as_mut().unwrap()
+        }
+    }
+
+    pub struct Condvar {
+        id: usize,
+        inner: std::sync::Condvar, // 검사기 밖에서 이용하는 경우
+    }
+
+    impl Default for Condvar {
+        fn default() -> Self {
+            Condvar::new()
+        }
+    }
+
+    impl Condvar {
+        pub fn new() -> Self {
+            Condvar {
+                id: new_object(),
+                inner: std::sync::Condvar::new(),
+            }
+        }
+
+        pub fn wait<'a, T>(&self, mut guard: MutexGuard<'a, T>) -> LockResult<MutexGuard<'a, T>> {
+            let g = guard.guard.take().unwrap();
+            if current().is_none() {
+                guard.guard = Some(self.inner.wait(g).unwrap_or_else(|e| e.into_inner()));
+                return Ok(guard);
+            }
+            // 모델 위의 락을 해제하고 대기하는 동안에는 실제 락도 해제해둔다.
+            drop(g);
+            point(Op::Wait(self.id, guard.lock.id));
+            guard.guard = Some(guard.lock.inner.lock().unwrap_or_else(|e| e.into_inner()));
+            Ok(guard)
+        }
+
+        pub fn wait_while<'a, T, F>(
+            &self,
+            mut guard: MutexGuard<'a, T>,
+            mut condition: F,
+        ) -> LockResult<MutexGuard<'a, T>>
+        where
+            F: FnMut(&mut T) -> bool,
+        {
+            while condition(&mut *guard) {
+                guard = self.wait(guard)?;
+            }
+            Ok(guard)
+        }
+
+        pub fn notify_one(&self) {
+            notify_point(self.id, false);
+            self.inner.notify_one();
+        }
+
+        pub fn notify_all(&self) {
+            notify_point(self.id, true);
+            self.inner.notify_all();
+        }
+    }
+
+    /// std::sync::atomic 대신 이용하는 shim. Ordering은 받기만 하고 순차 일관성으로 실행한다.
+    pub mod atomic {
+        use crate::model_check::{new_object, point, Access, Op};
+        pub use std::sync::atomic::Ordering;
+
+        pub struct AtomicBool {
+            id: usize,
+            v: std::sync::atomic::AtomicBool,
+        }
+
+        impl AtomicBool {
+            pub fn new(v: bool) -> Self {
+                AtomicBool {
+                    id: new_object(),
+                    v: std::sync::atomic::AtomicBool::new(v),
+                }
+            }
+
+            pub fn load(&self, _: Ordering) -> bool {
+                point(Op::Atomic(self.id, Access::Read));
+                self.v.load(Ordering::SeqCst)
+            }
+
+            pub fn store(&self, v: bool, _: Ordering) {
+                point(Op::Atomic(self.id, Access::Write));
+                self.v.store(v, Ordering::SeqCst)
+            }
+
+            pub fn swap(&self, v: bool, _: Ordering) -> bool {
+                point(Op::Atomic(self.id, Access::Write));
+                self.v.swap(v, Ordering::SeqCst)
+            }
+
+            pub fn compare_exchange(
+                &self,
+                current: bool,
+                new: bool,
+                _: Ordering,
+                _: Ordering,
+            ) -> Result<bool, bool> {
+                point(Op::Atomic(self.id, Access::Write));
+                self.v
+                    .compare_exchange(current, new, Ordering::SeqCst, Ordering::SeqCst)
+            }
+
+            // 모델에서는 거짓 실패(spurious failure)를 일으키지 않는다.
+            pub fn compare_exchange_weak(
+                &self,
+                current: bool,
+                new: bool,
+                success: Ordering,
+                failure: Ordering,
+            ) -> Result<bool, bool> {
+                self.compare_exchange(current, new, success, failure)
+            }
+        }
+
+        macro_rules! atomic_int {
+            ($name: ident, $int: ty) => {
+                pub struct $name {
+                    id: usize,
+                    v: std::sync::atomic::$name,
+                }
+
+                impl $name {
+                    pub fn new(v: $int) -> Self {
+                        $name {
+                            id: new_object(),
+                            v: std::sync::atomic::$name::new(v),
+                        }
+                    }
+
+                    pub fn load(&self, _: Ordering) -> $int {
+                        point(Op::Atomic(self.id, Access::Read));
+                        self.v.load(Ordering::SeqCst)
+                    }
+
+                    pub fn store(&self, v: $int, _: Ordering) {
+                        point(Op::Atomic(self.id, Access::Write));
+                        self.v.store(v, Ordering::SeqCst)
+                    }
+
+                    pub fn swap(&self, v: $int, _: Ordering) -> $int {
+                        point(Op::Atomic(self.id, Access::Write));
+                        self.v.swap(v, Ordering::SeqCst)
+                    }
+
+                    pub fn fetch_add(&self, v: $int, _: Ordering) -> $int {
+                        point(Op::Atomic(self.id, Access::Write));
+                        self.v.fetch_add(v, Ordering::SeqCst)
+                    }
+
+                    pub fn fetch_sub(&self, v: $int, _: Ordering) -> $int {
+                        point(Op::Atomic(self.id, Access::Write));
+                        self.v.fetch_sub(v, Ordering::SeqCst)
+                    }
+
+                    pub fn compare_exchange(
+                        &self,
+                        current: $int,
+                        new: $int,
+                        _: Ordering,
+                        _: Ordering,
+                    ) -> Result<$int, $int> {
+                        point(Op::Atomic(self.id, Access::Write));
+                        self.v
+                            .compare_exchange(current, new, Ordering::SeqCst, Ordering::SeqCst)
+                    }
+                }
+            };
+        }
+
+        atomic_int!(AtomicUsize, usize);
+        atomic_int!(AtomicU64, u64);
+    }
+}
+
+/// 검사 대상. ch03의 BakeryLock과 channel, ch04의 SpinLock을 shim 위로 옮긴 것이다.
+/// 알고리즘은 원래 코드와 같으며 std 대신 shim을 이용하는 점, 스핀 루프에서 hint::spin_loop을 호출하는 점만 다르다.
+pub mod targets {
+    use super::hint::spin_loop;
+    use super::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+    use super::sync::{Condvar, Mutex};
+    use std::cell::UnsafeCell;
+    use std::collections::LinkedList;
+    use std::ops::{Deref, DerefMut};
+    use std::sync::Arc;
+
+    // 3.9 베이커리 알고리즘. tickets의 0은 원래 코드의 None에 해당한다.
+    pub struct BakeryLock {
+        entering: Vec<AtomicBool>,
+        tickets: Vec<AtomicU64>,
+        use_entering: bool, // false면 entering 처리를 생략한 잘못된 버전
+    }
+
+    pub struct BakeryGuard<'a> {
+        lock: &'a BakeryLock,
+        idx: usize,
+    }
+
+    impl BakeryLock {
+        pub fn new(n: usize) -> Self {
+            BakeryLock {
+                entering: (0..n).map(|_| AtomicBool::new(false)).collect(),
+                tickets: (0..n).map(|_| AtomicU64::new(0)).collect(),
+                use_entering: true,
+            }
+        }
+
+        // 티켓 취득 중 표시(entering)를 하지 않는 잘못된 버전. 같은 티켓 번호를 뽑는 인터리빙에서 상호 배제가 깨진다.
+        pub fn without_entering(n: usize) -> Self {
+            BakeryLock {
+                use_entering: false,
+                ..BakeryLock::new(n)
+            }
+        }
+
+        pub fn lock(&self, idx: usize) -> BakeryGuard<'_> {
+            let n = self.tickets.len();
+            if self.use_entering {
+                self.entering[idx].store(true, Ordering::SeqCst);
+            }
+            let max = (0..n)
+                .map(|i| self.tickets[i].load(Ordering::SeqCst))
+                .max()
+                .unwrap();
+            let ticket = max + 1;
+            self.tickets[idx].store(ticket, Ordering::SeqCst);
+            if self.use_entering {
+                self.entering[idx].store(false, Ordering::SeqCst);
+            }
+
+            for i in 0..n {
+                if i == idx {
+                    continue;
+                }
+                while self.entering[i].load(Ordering::SeqCst) {
+                    spin_loop();
+                }
+                loop {
+                    match self.tickets[i].load(Ordering::SeqCst) {
+                        0 => break,
+                        t if ticket < t || (ticket == t && idx < i) => break,
+                        _ => spin_loop(),
+                    }
+                }
+            }
+            BakeryGuard { lock: self, idx }
+        }
+    }
+
+    impl<'a> Drop for BakeryGuard<'a> {
+        fn drop(&mut self) {
+            self.lock.tickets[self.idx].store(0, Ordering::SeqCst);
+        }
+    }
+
+    // 4.7.3 스핀락
+    pub struct SpinLock<T> {
+        lock: AtomicBool,
+        data: UnsafeCell<T>,
+        atomic_tas: bool, // false면 test와 set을 따로 수행하는 잘못된 버전
+    }
+
+    pub struct SpinLockGuard<'a, T> {
+        spin_lock: &'a SpinLock<T>,
+    }
+
+    unsafe impl<T> Sync for SpinLock<T> {}
+    unsafe impl<T> Send for SpinLock<T> {}
+
+    impl<T> SpinLock<T> {
+        pub fn new(v: T) -> Self {
+            SpinLock {
+                lock: AtomicBool::new(false),
+                data: UnsafeCell::new(v),
+                atomic_tas: true,
+            }
+        }
+
+        // compare_exchange 대신 load 후 store하는 잘못된 버전
+        pub fn non_atomic(v: T) -> Self {
+            SpinLock {
+                atomic_tas: false,
+                ..SpinLock::new(v)
+            }
+        }
+
+        pub fn lock(&self) -> SpinLockGuard<'_, T> {
+            loop {
+                while self.lock.load(Ordering::Relaxed) {
+                    spin_loop();
+                }
+                if self.atomic_tas {
+                    if self
+                        .lock
+                        .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+                        .is_ok()
+                    {
+                        break;
+                    }
+                } else if !self.lock.load(Ordering::Relaxed) {
+                    self.lock.store(true, Ordering::Relaxed);
+                    break;
+                }
+            }
+            SpinLockGuard { spin_lock: self }
+        }
+    }
+
+    impl<'a, T> Drop for SpinLockGuard<'a, T> {
+        fn drop(&mut self) {
+            self.spin_lock.lock.store(false, Ordering::Release);
+        }
+    }
+
+    impl<'a, T> Deref for SpinLockGuard<'a, T> {
+        type Target = T;
+
+        fn deref(&self) -> &Self::Target {
+            unsafe { &*self.spin_lock.data.get() }
+        }
+    }
+
+    impl<'a, T> DerefMut for SpinLockGuard<'a, T> {
+        fn deref_mut(&mut self) -> &mut Self::Target {
+            unsafe { &mut *self.spin_lock.data.get() }
+        }
+    }
+
+    // 3.8.5 세마포어와 채널
+    pub struct Semaphore {
+        mutex: Mutex<isize>,
+        cond: Condvar,
+        max: isize,
+    }
+
+    impl Semaphore {
+        pub fn new(max: isize) -> Self {
+            Semaphore {
+                mutex: Mutex::new(0),
+                cond: Condvar::new(),
+                max,
+            }
+        }
+
+        pub fn wait(&self) {
+            let mut cnt = self.mutex.lock().unwrap();
+            while *cnt >= self.max {
+                cnt = self.cond.wait(cnt).unwrap();
+            }
+            *cnt += 1;
+        }
+
+        pub fn post(&self) {
+            let mut cnt = self.mutex.lock().unwrap();
+            *cnt -= 1;
+            if *cnt <= self.max {
+                self.cond.notify_one();
+            }
+        }
+    }
+
+    #[derive(Clone)]
+    pub struct Sender<T> {
+        sem: Arc<Semaphore>,
+        buf: Arc<Mutex<LinkedList<T>>>,
+        cond: Arc<Condvar>,
+    }
+
+    impl<T: Send> Sender<T> {
+        pub fn send(&self, data: T) {
+            self.sem.wait();
+            let mut buf = self.buf.lock().unwrap();
+            buf.push_back(data);
+            self.cond.notify_one();
+        }
+    }
+
+    pub struct Receiver<T> {
+        sem: Arc<Semaphore>,
+        buf: Arc<Mutex<LinkedList<T>>>,
+        cond: Arc<Condvar>,
+    }
+
+    impl<T> Receiver<T> {
+        pub fn recv(&self) -> T {
+            let mut buf = self.buf.lock().unwrap();
+            loop {
+                if let Some(data) = buf.pop_front() {
+                    self.sem.post();
+                    return data;
+                }
+                buf = self.cond.wait(buf).unwrap();
+            }
+        }
+    }
+
+    pub fn channel<T>(max: isize) -> (Sender<T>, Receiver<T>) {
+        assert!(max > 0);
+        let sem = Arc::new(Semaphore::new(max));
+        let buf = Arc::new(Mutex::new(LinkedList::new()));
+        let cond = Arc::new(Condvar::new());
+        let tx = Sender {
+            sem: sem.clone(),
+            buf: buf.clone(),
+            cond: cond.clone(),
+        };
+        let rx = Receiver { sem, buf, cond };
+        (tx, rx)
+    }
+}
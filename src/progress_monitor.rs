@@ -0,0 +1,232 @@
+// 4.2 livelock & starvation 측정
+// ch04에서는 라이브락과 굶주림을 스테이트 머신으로 정의만 했고 실제로 측정하는 수단은 없었음. 여기서는 각 스레드가
+// 진행(progress) 지점을 표시하게 하고, 감시용 스레드가 일정 시간(window)마다 이를 검사하는 간단한 모니터를 구현해보자.
+//
+// - 굶주림(starvation): 특정 스레드만 window 동안 한 번도 진행하지 못한 상태
+// - 라이브락(livelock): 상태 전이(포크를 들고 내려놓기 등)는 계속 일어나지만 시스템 전체에서 아무도 진행하지 못한 상태
+//
+// 종료 시에는 스레드별 진행 횟수로 공평성을 요약한다. 공평성 지표로는 Jain's fairness index를 이용한다.
+//   J(x1, ..., xn) = (Σxi)^2 / (n * Σxi^2)
+// 모든 스레드가 같은 횟수만큼 진행했으면 1, 한 스레드만 진행했으면 1/n이 된다.
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+// 스레드 하나의 카운터. 진행 횟수와 상태 전이 횟수만 아토믹하게 센다.
+struct Slot {
+    name: String,
+    progress: AtomicU64, // 진행 지점을 통과한 횟수
+    changes: AtomicU64,  // 상태 전이 횟수(진행과 관계없는 전이 포함)
+    done: AtomicBool,    // 처리를 마친 스레드는 굶주림 검사에서 제외
+}
+
+/// 스레드가 등록 후 받는 핸들. 진행 지점에서 progress, 상태 전이 지점에서 state_change를 호출한다.
+#[derive(Clone)]
+pub struct ProgressHandle {
+    slot: Arc<Slot>,
+}
+
+impl ProgressHandle {
+    // 진행 지점 통과. 진행도 상태 전이의 일종이므로 두 카운터 모두 증가.
+    pub fn progress(&self) {
+        self.slot.progress.fetch_add(1, Ordering::Relaxed);
+        self.slot.changes.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // 진행 없이 상태만 바뀐 경우(락 획득 시도, 포크 내려놓기 등)
+    pub fn state_change(&self) {
+        self.slot.changes.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // 처리를 마치고 종료하는 스레드가 호출. 이후로는 진행이 없어도 굶주림으로 보지 않는다.
+    pub fn done(&self) {
+        self.slot.done.store(true, Ordering::Relaxed);
+    }
+}
+
+/// 모니터가 감지한 이상
+#[derive(Debug, Clone, PartialEq)]
+pub enum Alert {
+    // window 동안 진행하지 못한 스레드
+    Starvation { name: String, window: Duration },
+    // window 동안 상태 전이는 changes번 있었지만 누구도 진행하지 못함
+    Livelock { changes: u64, window: Duration },
+}
+
+/// 종료 시 반환하는 공평성 요약
+#[derive(Debug, Clone)]
+pub struct Summary {
+    pub progress: Vec<(String, u64)>, // (스레드 이름, 진행 횟수)
+    pub jain_index: f64,
+    pub alerts: Vec<Alert>,
+}
+
+struct Inner {
+    slots: Mutex<Vec<Arc<Slot>>>,
+    alerts: Mutex<Vec<Alert>>,
+    stop: (Mutex<bool>, Condvar), // 감시 스레드 종료 알림용
+    window: Duration,
+    livelock_threshold: u64, // 진행 없이 이만큼 상태 전이가 일어나면 라이브락으로 간주
+}
+
+pub struct ProgressMonitor {
+    inner: Arc<Inner>,
+    monitor: Option<JoinHandle<()>>,
+}
+
+impl ProgressMonitor {
+    // window마다 검사하는 감시 스레드를 생성. livelock_threshold의 기본값은 window당 상태 전이 1000회.
+    pub fn new(window: Duration) -> Self {
+        ProgressMonitor::with_livelock_threshold(window, 1000)
+    }
+
+    pub fn with_livelock_threshold(window: Duration, livelock_threshold: u64) -> Self {
+        let inner = Arc::new(Inner {
+            slots: Mutex::new(Vec::new()),
+            alerts: Mutex::new(Vec::new()),
+            stop: (Mutex::new(false), Condvar::new()),
+            window,
+            livelock_threshold,
+        });
+        let inner0 = inner.clone();
+        let monitor = thread::spawn(move || inner0.monitor());
+        ProgressMonitor {
+            inner,
+            monitor: Some(monitor),
+        }
+    }
+
+    // 감시 대상 스레드 등록
+    pub fn register(&self, name: &str) -> ProgressHandle {
+        let slot = Arc::new(Slot {
+            name: name.to_string(),
+            progress: AtomicU64::new(0),
+            changes: AtomicU64::new(0),
+            done: AtomicBool::new(false),
+        });
+        self.inner.slots.lock().unwrap().push(slot.clone());
+        ProgressHandle { slot }
+    }
+
+    // 지금까지 감지된 이상 목록
+    pub fn alerts(&self) -> Vec<Alert> {
+        self.inner.alerts.lock().unwrap().clone()
+    }
+
+    // 감시 스레드를 정지하고 공평성 요약을 출력한 뒤 반환
+    pub fn shutdown(mut self) -> Summary {
+        self.stop();
+
+        let progress: Vec<(String, u64)> = self
+            .inner
+            .slots
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|s| (s.name.clone(), s.progress.load(Ordering::Relaxed)))
+            .collect();
+        let xs: Vec<f64> = progress.iter().map(|(_, n)| *n as f64).collect();
+        let summary = Summary {
+            jain_index: jain_index(&xs),
+            progress,
+            alerts: self.alerts(),
+        };
+
+        println!("fairness summary:");
+        for (name, n) in summary.progress.iter() {
+            println!("  {}: progress = {}", name, n);
+        }
+        println!("  jain index = {:.3}, alerts = {}", summary.jain_index, summary.alerts.len());
+        summary
+    }
+
+    fn stop(&mut self) {
+        let (lock, cvar) = &self.inner.stop;
+        *lock.lock().unwrap() = true;
+        cvar.notify_all();
+        if let Some(t) = self.monitor.take() {
+            t.join().unwrap();
+        }
+    }
+}
+
+impl Drop for ProgressMonitor {
+    // shutdown을 호출하지 않고 버려진 경우에도 감시 스레드는 종료시킨다.
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+impl Inner {
+    // 감시 스레드 본체. window마다 이전 스냅샷과 비교해 증가량(delta)을 본다.
+    fn monitor(&self) {
+        let mut last: Vec<(u64, u64)> = Vec::new();
+        let (lock, cvar) = &self.stop;
+        let mut stop = lock.lock().unwrap();
+        loop {
+            // 종료 알림이 오거나 window가 지날 때까지 대기. 의사 각성에 대비해 wait_timeout_while 사용.
+            stop = cvar.wait_timeout_while(stop, self.window, |s| !*s).unwrap().0;
+            if *stop {
+                return;
+            }
+            self.check(&mut last);
+        }
+    }
+
+    fn check(&self, last: &mut Vec<(u64, u64)>) {
+        let slots = self.slots.lock().unwrap();
+        let mut alerts = Vec::new();
+        let mut total_progress = 0;
+        let mut total_changes = 0;
+
+        for (i, slot) in slots.iter().enumerate() {
+            let now = (
+                slot.progress.load(Ordering::Relaxed),
+                slot.changes.load(Ordering::Relaxed),
+            );
+            if i >= last.len() {
+                // 이번 window에 새로 등록된 스레드는 다음 window부터 검사
+                last.push(now);
+                continue;
+            }
+            let dp = now.0 - last[i].0;
+            let dc = now.1 - last[i].1;
+            last[i] = now;
+
+            if dp == 0 && !slot.done.load(Ordering::Relaxed) {
+                alerts.push(Alert::Starvation {
+                    name: slot.name.clone(),
+                    window: self.window,
+                });
+            }
+            total_progress += dp;
+            total_changes += dc;
+        }
+
+        // 전체가 진행하지 못했는데 상태 전이만 많으면 개별 굶주림이 아니라 라이브락
+        if total_progress == 0 && total_changes >= self.livelock_threshold {
+            alerts.retain(|a| !matches!(a, Alert::Starvation { .. }));
+            alerts.push(Alert::Livelock {
+                changes: total_changes,
+                window: self.window,
+            });
+        }
+
+        for a in alerts.iter() {
+            println!("progress monitor: {:?}", a);
+        }
+        self.alerts.lock().unwrap().extend(alerts);
+    }
+}
+
+/// Jain's fairness index. 빈 입력이나 모두 0인 경우는 공평하다고 보고 1을 반환.
+pub fn jain_index(xs: &[f64]) -> f64 {
+    let sum: f64 = xs.iter().sum();
+    let sum_sq: f64 = xs.iter().map(|x| x * x).sum();
+    if xs.is_empty() || sum_sq == 0.0 {
+        return 1.0;
+    }
+    (sum * sum) / (xs.len() as f64 * sum_sq)
+}
@@ -0,0 +1,405 @@
+// 4.3 벡터 클록을 이용한 데이터 레이스 검출
+// safe Rust에서는 데이터 레이스가 컴파일 시에 막히지만, 이 크레이트는 시연을 위해 static mut COUNT, static mut LOCK과
+// read_volatile/write_volatile을 직접 이용하고 있다. 이런 코드에서 레이스를 실행 중에 찾아내는 FastTrack 방식의
+// 동적 레이스 검출기를 구현해보자.
+//
+// - 각 스레드는 벡터 클록 C_t를 가진다. C_t[u]는 "스레드 t가 알고 있는 스레드 u의 시각"이다.
+// - 락과 채널 같은 동기 객체는 SyncClock을 가진다. 해제(post, send, unlock) 시 C_t를 동기 객체에 합치고
+//   (release), 획득(wait, recv, lock) 시 동기 객체의 클록을 C_t에 합친다(acquire).
+// - race::spawn은 부모의 클록을 자식에게 넘기고, join은 자식의 마지막 클록을 부모에게 합친다.
+// - 검사 대상 변수는 Shared<T>로 감싼다. 마지막 쓰기는 epoch(스레드, 시각, 호출 위치) 하나로, 읽기는 보통 epoch
+//   하나로 기록하고 여러 스레드가 동시에 읽고 있을 때만 스레드별 epoch 목록으로 확장한다(FastTrack).
+// - 두 접근 중 하나 이상이 쓰기이고 happens-before로 순서가 정해지지 않으면 레이스로 보고한다.
+//   보고에는 두 스레드와 각각의 호출 위치(#[track_caller])가 포함된다.
+//
+// Shared<T>의 값 자체는 내부의 Mutex 안에 있으므로 실제로 정의되지 않은 동작이 일어나지는 않는다. 이 Mutex는 벡터
+// 클록에 반영되지 않으므로 검출기 입장에서는 보호되지 않은 변수로 보인다.
+//
+// 클록을 갱신하는 동기 객체는 race::Mutex, ch03의 Semaphore·channel·BakeryLock, ch04의 SpinLock이다. std의 Mutex와
+// 그 밖의 직접 만든 락은 검출기가 동기화로 인식하지 못한다. 검출은 race feature(또는 테스트 빌드)에서만 동작하며,
+// 꺼져 있으면 SyncClock은 크기가 0이고 Shared는 검사 없이 값을 읽고 쓴다.
+// 검출기 본체(Epoch, VarState의 기록 등)는 꺼진 빌드에서는 쓰이지 않는다.
+#![cfg_attr(not(any(test, feature = "race")), allow(dead_code))]
+
+use std::cell::RefCell;
+use std::fmt;
+use std::panic::Location;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{LockResult, Mutex as StdMutex, MutexGuard as StdMutexGuard, PoisonError};
+use std::thread as std_thread;
+
+// 벡터 클록. 인덱스는 race 모듈이 붙인 스레드 번호
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VectorClock(Vec<u32>);
+
+impl VectorClock {
+    pub fn get(&self, tid: usize) -> u32 {
+        self.0.get(tid).copied().unwrap_or(0)
+    }
+
+    fn set(&mut self, tid: usize, c: u32) {
+        if self.0.len() <= tid {
+            self.0.resize(tid + 1, 0);
+        }
+        self.0[tid] = c;
+    }
+
+    fn join(&mut self, other: &VectorClock) {
+        if self.0.len() < other.0.len() {
+            self.0.resize(other.0.len(), 0);
+        }
+        for (a, b) in self.0.iter_mut().zip(other.0.iter()) {
+            *a = (*a).max(*b);
+        }
+    }
+}
+
+// 스레드 번호는 처음 race 모듈의 기능을 이용할 때 붙인다.
+static NEXT_TID: AtomicUsize = AtomicUsize::new(0);
+
+struct ThreadClock {
+    tid: usize,
+    vc: VectorClock,
+}
+
+impl ThreadClock {
+    fn new(mut vc: VectorClock) -> Self {
+        let tid = NEXT_TID.fetch_add(1, Ordering::Relaxed);
+        vc.set(tid, 1);
+        ThreadClock { tid, vc }
+    }
+
+    fn tick(&mut self) {
+        let c = self.vc.get(self.tid);
+        self.vc.set(self.tid, c + 1);
+    }
+}
+
+thread_local! {
+    static CLOCK: RefCell<Option<ThreadClock>> = const { RefCell::new(None) };
+}
+
+fn with_clock<R>(f: impl FnOnce(&mut ThreadClock) -> R) -> R {
+    CLOCK.with(|c| {
+        let mut c = c.borrow_mut();
+        f(c.get_or_insert_with(|| ThreadClock::new(VectorClock::default())))
+    })
+}
+
+// 현재 스레드의 번호
+pub fn current_tid() -> usize {
+    with_clock(|c| c.tid)
+}
+
+pub fn enabled() -> bool {
+    cfg!(any(test, feature = "race"))
+}
+
+/// 동기 객체(락, 세마포어, 채널)가 가지는 클록. static 변수에도 넣을 수 있다.
+#[derive(Debug, Default)]
+pub struct SyncClock {
+    #[cfg(any(test, feature = "race"))]
+    vc: StdMutex<VectorClock>,
+}
+
+impl SyncClock {
+    pub const fn new() -> Self {
+        SyncClock {
+            #[cfg(any(test, feature = "race"))]
+            vc: StdMutex::new(VectorClock(Vec::new())),
+        }
+    }
+
+    // 동기 객체를 획득. 마지막으로 해제한 스레드들의 클록을 현재 스레드에 합친다.
+    pub fn acquire(&self) {
+        #[cfg(any(test, feature = "race"))]
+        {
+            let l = self.vc.lock().unwrap();
+            with_clock(|c| c.vc.join(&l));
+        }
+    }
+
+    // 동기 객체를 해제. 현재 스레드의 클록을 동기 객체에 합치고 자신의 시각을 진행시킨다.
+    pub fn release(&self) {
+        #[cfg(any(test, feature = "race"))]
+        {
+            let mut l = self.vc.lock().unwrap();
+            with_clock(|c| {
+                l.join(&c.vc);
+                c.tick();
+            });
+        }
+    }
+}
+
+/// 벡터 클록을 전달하는 스레드 생성
+pub fn spawn<F, T>(f: F) -> JoinHandle<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let vc = with_clock(|c| {
+        let vc = c.vc.clone();
+        c.tick();
+        vc
+    });
+    let handle = std_thread::spawn(move || {
+        CLOCK.with(|c| *c.borrow_mut() = Some(ThreadClock::new(vc)));
+        let v = f();
+        (v, with_clock(|c| c.vc.clone()))
+    });
+    JoinHandle(handle)
+}
+
+pub struct JoinHandle<T>(std_thread::JoinHandle<(T, VectorClock)>);
+
+impl<T> JoinHandle<T> {
+    pub fn join(self) -> std_thread::Result<T> {
+        let (v, vc) = self.0.join()?;
+        with_clock(|c| c.vc.join(&vc));
+        Ok(v)
+    }
+}
+
+/// 벡터 클록을 가지는 Mutex. std::sync::Mutex와 같은 인터페이스를 가진다.
+#[derive(Debug, Default)]
+pub struct Mutex<T> {
+    inner: StdMutex<T>,
+    clock: SyncClock,
+}
+
+pub struct MutexGuard<'a, T> {
+    guard: StdMutexGuard<'a, T>,
+    clock: &'a SyncClock,
+}
+
+impl<T> Mutex<T> {
+    pub fn new(v: T) -> Self {
+        Mutex {
+            inner: StdMutex::new(v),
+            clock: SyncClock::new(),
+        }
+    }
+
+    pub fn lock(&self) -> LockResult<MutexGuard<'_, T>> {
+        let (guard, poisoned) = match self.inner.lock() {
+            Ok(g) => (g, false),
+            Err(e) => (e.into_inner(), true),
+        };
+        self.clock.acquire();
+        let guard = MutexGuard {
+            guard,
+            clock: &self.clock,
+        };
+        if poisoned {
+            Err(PoisonError::new(guard))
+        } else {
+            Ok(guard)
+        }
+    }
+}
+
+impl<T> std::ops::Deref for MutexGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<T> std::ops::DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+impl<T> Drop for MutexGuard<'_, T> {
+    fn drop(&mut self) {
+        // 실제 락을 해제하기 전에 release
+        self.clock.release();
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AccessKind {
+    Read,
+    Write,
+}
+
+/// 레이스를 일으킨 접근 하나
+#[derive(Debug, Clone, Copy)]
+pub struct Access {
+    pub tid: usize,
+    pub kind: AccessKind,
+    pub site: &'static Location<'static>,
+}
+
+// 스레드 tid의 clock 시점의 접근
+#[derive(Debug, Clone, Copy)]
+struct Epoch {
+    clock: u32,
+    access: Access,
+}
+
+impl Epoch {
+    // 현재 스레드(클록 vc)보다 먼저 일어났는가
+    fn happens_before(&self, vc: &VectorClock) -> bool {
+        self.clock <= vc.get(self.access.tid)
+    }
+}
+
+/// 보고된 레이스. first가 먼저 기록된 접근, second가 레이스를 발견한 접근이다.
+#[derive(Debug, Clone)]
+pub struct Race {
+    pub var: &'static str,
+    pub first: Access,
+    pub second: Access,
+}
+
+impl fmt::Display for Race {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "data race on {}", self.var)?;
+        for (label, a) in [("first", &self.first), ("second", &self.second)] {
+            writeln!(f, "  {:<6}: {:?} by thread {} at {}", label, a.kind, a.tid, a.site)?;
+        }
+        Ok(())
+    }
+}
+
+// 보고된 레이스. 같은 변수에서 같은 호출 위치 쌍의 레이스는 한 번만 기록한다.
+static RACES: StdMutex<Vec<Race>> = StdMutex::new(Vec::new());
+
+fn report(race: Race) {
+    let mut races = RACES.lock().unwrap();
+    let dup = races.iter().any(|r| {
+        r.var == race.var && r.first.site == race.first.site && r.second.site == race.second.site
+    });
+    if !dup {
+        eprint!("{}", race);
+        races.push(race);
+    }
+}
+
+// 지금까지 보고된 레이스 중 변수 이름이 var인 것을 꺼낸다. 다른 변수의 레이스는 남겨둔다.
+pub fn take_races(var: &str) -> Vec<Race> {
+    let mut races = RACES.lock().unwrap();
+    let (taken, rest) = races.drain(..).partition(|r| r.var == var);
+    *races = rest;
+    taken
+}
+
+// 읽기 기록. 보통은 마지막 읽기 하나, 순서가 정해지지 않은 읽기가 여럿이면 스레드별 목록
+#[derive(Debug)]
+enum Reads {
+    Epoch(Option<Epoch>),
+    Shared(Vec<Epoch>),
+}
+
+#[derive(Debug)]
+struct VarState<T> {
+    value: T,
+    write: Option<Epoch>,
+    reads: Reads,
+}
+
+/// 레이스 검사 대상 변수
+#[derive(Debug)]
+pub struct Shared<T> {
+    name: &'static str,
+    state: StdMutex<VarState<T>>,
+}
+
+impl<T: Copy> Shared<T> {
+    pub const fn new(name: &'static str, value: T) -> Self {
+        Shared {
+            name,
+            state: StdMutex::new(VarState {
+                value,
+                write: None,
+                reads: Reads::Epoch(None),
+            }),
+        }
+    }
+
+    #[track_caller]
+    pub fn read(&self) -> T {
+        #[cfg(any(test, feature = "race"))]
+        let site = Location::caller();
+        #[allow(unused_mut)]
+        let mut st = self.state.lock().unwrap();
+        #[cfg(any(test, feature = "race"))]
+        with_clock(|c| {
+            let now = Epoch {
+                clock: c.vc.get(c.tid),
+                access: Access {
+                    tid: c.tid,
+                    kind: AccessKind::Read,
+                    site,
+                },
+            };
+            // write-read 레이스
+            if let Some(w) = st.write {
+                if w.access.tid != c.tid && !w.happens_before(&c.vc) {
+                    self.race(w.access, now.access);
+                }
+            }
+            st.reads = match std::mem::replace(&mut st.reads, Reads::Epoch(None)) {
+                Reads::Epoch(None) => Reads::Epoch(Some(now)),
+                // 이전 읽기가 현재 읽기보다 먼저 일어났으면 epoch 하나로 충분
+                Reads::Epoch(Some(r)) if r.access.tid == c.tid || r.happens_before(&c.vc) => {
+                    Reads::Epoch(Some(now))
+                }
+                Reads::Epoch(Some(r)) => Reads::Shared(vec![r, now]),
+                Reads::Shared(mut rs) => {
+                    match rs.iter_mut().find(|r| r.access.tid == c.tid) {
+                        Some(r) => *r = now,
+                        None => rs.push(now),
+                    }
+                    Reads::Shared(rs)
+                }
+            };
+        });
+        st.value
+    }
+
+    #[track_caller]
+    pub fn write(&self, value: T) {
+        #[cfg(any(test, feature = "race"))]
+        let site = Location::caller();
+        let mut st = self.state.lock().unwrap();
+        #[cfg(any(test, feature = "race"))]
+        with_clock(|c| {
+            let now = Epoch {
+                clock: c.vc.get(c.tid),
+                access: Access {
+                    tid: c.tid,
+                    kind: AccessKind::Write,
+                    site,
+                },
+            };
+            let unordered = |e: &Epoch| e.access.tid != c.tid && !e.happens_before(&c.vc);
+            // write-write 레이스
+            if let Some(w) = st.write.filter(unordered) {
+                self.race(w.access, now.access);
+            }
+            // read-write 레이스
+            let reads = match &st.reads {
+                Reads::Epoch(r) => r.iter().copied().collect(),
+                Reads::Shared(rs) => rs.clone(),
+            };
+            for r in reads.iter().filter(|r| unordered(r)) {
+                self.race(r.access, now.access);
+            }
+            st.write = Some(now);
+            st.reads = Reads::Epoch(None);
+        });
+        st.value = value;
+    }
+
+    fn race(&self, first: Access, second: Access) {
+        report(Race {
+            var: self.name,
+            first,
+            second,
+        });
+    }
+}
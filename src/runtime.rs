@@ -0,0 +1,2275 @@
+// 5.2.2/5.3.2 직접 구현한 비동기 런타임
+// ch05의 func_189p와 func_197은 Executor, Spawner, Task, IOSelector, AsyncListener, AsyncReader를 함수 안에서 각각
+// 정의하고 있어서 다른 곳에서 재사용할 수 없었다. 이들을 이 모듈에 한 번만 정의하고, 이를 조합한 Runtime을 진입점으로
+// 제공한다. 구조는 ch05의 그림 5-3과 같다.
+//
+//                                       ┌---IO Selector------┐
+//                                       |      [epoll]       |
+//                                 wake  |         ↑          |
+//  Executor <----- [실행 Queue] <--------┼---[Task 정보, ...] |
+//      |                                |         ↑          |
+// poll |                                └---------┼----------┘
+//      |           [IO Queue]---------------------┘
+//      ↓               ↑
+// Task/Waker[Future[Future, Future, ...], ...]
+//
+// Spawner::spawn은 JoinHandle을 반환하며, 이를 await하면 Task의 결과를 Result<T, JoinError>로 얻는다. Task 안의
+// poll에서 발생한 panic은 잡아서 JoinError로 전달하므로 Executor의 스레드는 계속 실행된다.
+//
+// Runtime::multi_thread는 MultiThreadExecutor를 이용해 여러 Worker 스레드에서 Task를 실행한다(work-stealing).
+// Send가 아닌 future는 LocalExecutor와 spawn_local로 실행한다.
+//
+// 협조적 스케줄링: Task는 poll마다 준비된 연산의 예산(기본 DEFAULT_BUDGET)을 받는다. ReadLine, Accept, JoinHandle은
+// 준비되어 있어도 예산을 다 썼으면 Task를 다시 스케줄링하고 Pending을 반환하므로, 항상 읽을 수 있는 connection을
+// 처리하는 Task도 다른 Task에 실행을 양보한다. yield_now로 직접 양보할 수도 있으며, 양보한 Task는 실행 Queue의
+// 뒤로 간다. Task별 poll 횟수와 양보 횟수는 Executor::metrics로 얻는다.
+//
+// Executor는 poll마다 시간을 재서 set_slow_poll로 정한 기준보다 오래 걸리면 Task 번호, spawn한 위치(#[track_caller]),
+// 걸린 시간을 출력한다. async 안에서 thread::sleep이나 경합하는 std::sync::Mutex처럼 스레드를 블록하는 호출을 찾는 데
+// 쓴다.
+//
+// thread::sleep이나 파일 IO처럼 블록하는 호출은 spawn_blocking으로 블로킹 전용 스레드 풀에서 실행하고, 반환한
+// JoinHandle을 await해서 결과를 얻는다. 풀은 작업이 밀리면 최대 스레드 수까지 스레드를 늘리고, 일정 시간 작업이 없는
+// 스레드는 종료한다.
+//
+// Task가 멈춘 이유를 알 수 있도록 Task마다 상태(scheduled, idle, polling), poll 횟수, poll에 걸린 시간의 합, 마지막
+// wake 이후 지난 시간, 기다리는 IOSelector의 fd 또는 타이머를 기록한다. Executor::dump로 완료하지 않은 Task의 목록을
+// 얻고, install_sigusr1_dump를 호출해 두면 SIGUSR1을 받을 때마다 표준 에러에 출력한다(tokio-console의 오프라인 판).
+//   $ kill -USR1 <pid>
+//
+// Task는 Slab에 두고 실행 Queue에는 Slab의 번호만 넣는다. Waker는 Task의 주소와 손으로 작성한 RawWakerVTable로
+// 만들므로 wake할 때 Arc를 복제해서 채널로 보내지 않으며, Task 헤더의 상태 워드로 중복된 wake를 합친다.
+// run은 완료하지 않은 Task를 세어서 0이 되면 반환한다. 아무도 wake할 수 없게 되어 버려진 Task도 완료한 것으로 센다.
+// shutdown은 완료하지 않은 Task를 모두 버리며, block_on은 전달한 future가 완료할 때까지만 실행한다.
+//
+// 사용 예
+//   let rt = Runtime::new();
+//   let listener = AsyncListener::listen("127.0.0.1:10000", rt.selector());
+//   rt.block_on(async move { let (reader, writer, addr) = listener.accept().await; ... });
+
+use futures::{
+    future::{BoxFuture, FutureExt, LocalBoxFuture},
+    task::{waker, ArcWake},
+};
+use std::cell::{Cell, RefCell, UnsafeCell};
+use std::any::Any;
+use std::error::Error;
+use std::fmt::{self, Write as _};
+use std::mem::ManuallyDrop;
+use std::panic::{AssertUnwindSafe, Location};
+use std::rc::{self, Rc};
+use nix::{
+    errno::Errno,
+    sys::{
+        epoll::{epoll_create1, epoll_ctl, epoll_wait, EpollCreateFlags, EpollEvent, EpollFlags, EpollOp},
+        eventfd::{eventfd, EfdFlags},
+        time::TimeSpec,
+        timerfd::{ClockId, Expiration, TimerFd, TimerFlags, TimerSetTimeFlags},
+    },
+    unistd::{close, read, write},
+};
+pub use crate::signal_hub::DumpHandle;
+use crate::signal_hub::{on_signal, SIGUSR1};
+use std::{
+    collections::{HashMap, VecDeque},
+    future::Future,
+    io::{BufRead, BufReader, BufWriter},
+    net::{SocketAddr, TcpListener, TcpStream},
+    os::unix::io::{AsRawFd, RawFd},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, AtomicPtr, AtomicU32, AtomicU64, AtomicU8, AtomicUsize, Ordering},
+        Arc, Condvar, Mutex, OnceLock,
+    },
+    thread,
+    time::{Duration, Instant},
+    task::{ready, Context, Poll, RawWaker, RawWakerVTable, Waker},
+};
+
+// Task를 넣을 실행 Queue. Task는 Slab의 번호로 주고받는다.
+enum Queue {
+    // Executor의 실행 Queue
+    Single(RunQueue),
+    // MultiThreadExecutor의 작업 큐
+    Pool(Arc<Pool>),
+}
+
+// Executor의 실행 Queue. run은 Task가 들어오거나 상태가 바뀔 때까지 cond에서 잠든다. notify는 잠든 경우에만
+// 호출한다(futex 시스템 콜을 피함). parked는 queue의 락을 획득한 상태에서만 읽고 쓴다.
+struct RunQueue {
+    queue: Mutex<VecDeque<usize>>,
+    parked: AtomicBool,
+    cond: Condvar,
+}
+
+impl RunQueue {
+    fn push(&self, idx: usize) {
+        let mut queue = self.queue.lock().unwrap();
+        queue.push_back(idx);
+        if self.parked.load(Ordering::Relaxed) {
+            self.cond.notify_one();
+        }
+    }
+
+    fn notify(&self) {
+        let _g = self.queue.lock().unwrap();
+        if self.parked.load(Ordering::Relaxed) {
+            self.cond.notify_all();
+        }
+    }
+}
+
+// Executor, Spawner, Task가 공유하는 상태. Task의 Waker가 가리키므로 항상 Arc 안에 둔다.
+struct Shared {
+    queue: Queue,
+    slab: Slab,
+    // 완료하지 않은 Task 수. 이 수가 0이 되면 run이 반환한다.
+    live: AtomicUsize,
+    shutdown: AtomicBool,
+    next_id: AtomicUsize,
+    budget: AtomicU32, // poll 1회에 실행할 수 있는 준비된 연산 수
+    // 이보다 오래 걸린 poll을 기록한다(ns). u64::MAX면 기록하지 않는다.
+    slow_poll: AtomicU64,
+    strict: AtomicBool, // 오래 걸린 poll을 기록하는 대신 panic
+    blocking: Arc<BlockingPool>, // spawn_blocking의 스레드 풀
+}
+
+impl Shared {
+    fn new(queue: Queue) -> Arc<Self> {
+        Arc::new(Shared {
+            queue,
+            slab: Slab::new(),
+            live: AtomicUsize::new(0),
+            shutdown: AtomicBool::new(false),
+            next_id: AtomicUsize::new(0),
+            budget: AtomicU32::new(DEFAULT_BUDGET),
+            slow_poll: AtomicU64::new(u64::MAX),
+            strict: AtomicBool::new(false),
+            blocking: BlockingPool::new(),
+        })
+    }
+
+    // 새 Task를 Slab에 넣고 실행 Queue에 넣음
+    fn spawn(&self, future: BoxFuture<'static, ()>, location: &'static Location<'static>) {
+        let idx = self.slab.alloc(self);
+        let task = self.slab.get(idx);
+        // 빈 Slot은 아무도 참조하지 않으므로 future를 바로 쓸 수 있다.
+        unsafe { *task.future.get() = Some(future) };
+        task.stats.reset(self.next_id.fetch_add(1, Ordering::Relaxed), location);
+        task.header.refs.store(2, Ordering::Relaxed); // 완료하지 않은 Task의 참조 + 실행 Queue의 참조
+        self.live.fetch_add(1, Ordering::SeqCst);
+        task.header.state.store(SCHEDULED, Ordering::Release);
+        self.schedule(idx);
+    }
+
+    fn schedule(&self, idx: usize) {
+        match &self.queue {
+            Queue::Single(q) => q.push(idx),
+            Queue::Pool(pool) => pool.schedule(idx, false),
+        }
+    }
+
+    // poll 중에 자신을 wake한 Task를 다시 넣음. 양보한 Task가 곧바로 다시 실행되지 않도록 lifo 슬롯을 쓰지 않는다.
+    fn schedule_yield(&self, idx: usize) {
+        match &self.queue {
+            Queue::Single(q) => q.push(idx),
+            Queue::Pool(pool) => pool.schedule(idx, true),
+        }
+    }
+
+    // 블록 중인 run을 깨워서 상태를 다시 확인하게 한다.
+    fn notify(&self) {
+        match &self.queue {
+            Queue::Single(q) => q.notify(),
+            Queue::Pool(pool) => pool.notify_all(),
+        }
+    }
+
+    fn live_tasks(&self) -> usize {
+        self.live.load(Ordering::SeqCst)
+    }
+
+    fn is_shutdown(&self) -> bool {
+        self.shutdown.load(Ordering::SeqCst)
+    }
+
+    fn wake(&self, task: &Task) {
+        // shutdown 후에는 아무것도 하지 않는다.
+        if self.is_shutdown() {
+            return;
+        }
+        let state = &task.header.state;
+        let mut s = state.load(Ordering::Acquire);
+        loop {
+            let next = match s {
+                IDLE => SCHEDULED,
+                RUNNING => NOTIFIED,
+                _ => return, // 이미 실행 Queue에 있거나 완료
+            };
+            match state.compare_exchange(s, next, Ordering::SeqCst, Ordering::Acquire) {
+                Ok(_) => {
+                    task.stats.last_wake.store(now_ns(), Ordering::Relaxed);
+                    if next == SCHEDULED {
+                        // wake한 Waker가 참조를 가지고 있으므로 그 사이에 Slot이 재사용되지 않는다.
+                        task.header.refs.fetch_add(1, Ordering::Relaxed);
+                        self.schedule(task.header.idx);
+                    }
+                    return;
+                }
+                Err(cur) => s = cur,
+            }
+        }
+    }
+
+    // 실행 Queue에서 꺼낸 Task의 Future를 poll하고 실행 Queue의 참조를 놓는다. 완료하면 Future를 버려서 이후에
+    // wake되어도 다시 poll하지 않는다. shutdown 후에는 poll하지 않고 버린다.
+    fn run(&self, idx: usize) {
+        let task = self.slab.get(idx);
+        let state = &task.header.state;
+        let mut slow = None;
+        if self.is_shutdown() {
+            if state.compare_exchange(SCHEDULED, COMPLETE, Ordering::SeqCst, Ordering::Relaxed).is_ok() {
+                self.complete(task);
+            }
+        } else if state.compare_exchange(SCHEDULED, RUNNING, Ordering::SeqCst, Ordering::Relaxed).is_ok() {
+            // RUNNING인 동안 future는 이 스레드만 만진다.
+            let future = unsafe { (*task.future.get()).as_mut().unwrap() };
+            // 참조를 세지 않는 Waker. future가 clone하면 그때 참조를 센다.
+            let waker = ManuallyDrop::new(unsafe { Waker::from_raw(task.raw_waker()) });
+            let mut ctx = Context::from_waker(&waker);
+            task.stats.polls.fetch_add(1, Ordering::Relaxed);
+            task.stats.wait.store(0, Ordering::Relaxed); // Pending을 반환하는 Future가 다시 기록한다.
+            let coop = Coop::enter(self.budget.load(Ordering::Relaxed), &task.stats, self);
+            let start = Instant::now();
+            let ready = future.as_mut().poll(&mut ctx).is_ready();
+            let elapsed = start.elapsed();
+            drop(coop);
+            task.stats.busy.fetch_add(elapsed.as_nanos() as u64, Ordering::Relaxed);
+            slow = self.check_slow_poll(task, elapsed);
+            if let Queue::Pool(pool) = &self.queue {
+                // Worker에서 panic하면 Worker가 lifo 슬롯의 Task와 함께 사라지므로, panic하는 대신 run(또는 block_on)에
+                // 전달한다. Task를 완료하기 전에 전달해야 마지막 Task를 기다리던 run이 놓치지 않는다.
+                if let Some(msg) = slow.take() {
+                    pool.report(Box::new(msg));
+                }
+            }
+            if ready || self.is_shutdown() {
+                // 완료, 또는 poll 중에 shutdown되었음
+                state.store(COMPLETE, Ordering::SeqCst);
+                self.complete(task);
+            } else if state.compare_exchange(RUNNING, IDLE, Ordering::SeqCst, Ordering::Relaxed).is_err() {
+                // poll 중에 wake되었음
+                state.store(SCHEDULED, Ordering::SeqCst);
+                task.header.refs.fetch_add(1, Ordering::Relaxed);
+                self.schedule_yield(idx);
+            } else if self.is_shutdown()
+                && state.compare_exchange(IDLE, COMPLETE, Ordering::SeqCst, Ordering::Relaxed).is_ok()
+            {
+                // IDLE로 바꾸는 사이에 shutdown되었음
+                self.complete(task);
+            }
+        }
+        self.release(task);
+        // Task의 상태를 정리한 뒤에 panic하므로 Executor는 계속 이용할 수 있다.
+        if let Some(msg) = slow {
+            panic!("{}", msg);
+        }
+    }
+
+    // poll이 기준보다 오래 걸렸으면 Task 번호, spawn한 위치와 함께 기록한다. strict면 panic할 메시지를 반환한다.
+    fn check_slow_poll(&self, task: &Task, elapsed: Duration) -> Option<String> {
+        let threshold = self.slow_poll.load(Ordering::Relaxed);
+        if elapsed.as_nanos() <= threshold as u128 {
+            return None;
+        }
+        task.stats.slow_polls.fetch_add(1, Ordering::Relaxed);
+        let m = task.metrics();
+        let msg = format!(
+            "slow poll: task {} spawned at {} took {:?} (threshold {:?})",
+            m.id,
+            m.location,
+            elapsed,
+            Duration::from_nanos(threshold)
+        );
+        if self.strict.load(Ordering::Relaxed) {
+            return Some(msg);
+        }
+        eprintln!("{}", msg);
+        None
+    }
+
+    fn set_slow_poll(&self, threshold: Option<Duration>, strict: bool) {
+        let ns = threshold.map_or(u64::MAX, |t| t.as_nanos().min(u64::MAX as u128 - 1) as u64);
+        self.slow_poll.store(ns, Ordering::Relaxed);
+        self.strict.store(strict, Ordering::Relaxed);
+    }
+
+    // state를 COMPLETE로 바꾼 쪽이 호출한다. Future를 버리고 완료하지 않은 Task의 참조를 놓는다.
+    fn complete(&self, task: &Task) {
+        drop(unsafe { (*task.future.get()).take() });
+        let last = self.live.fetch_sub(1, Ordering::SeqCst) == 1;
+        self.release(task);
+        match &self.queue {
+            // 취소된 Task를 block_on이 기다리고 있을 수 있다.
+            Queue::Single(q) => q.notify(),
+            Queue::Pool(pool) if last => pool.notify_all(),
+            Queue::Pool(_) => (),
+        }
+    }
+
+    // 참조를 하나 놓는다. 남은 참조가 완료하지 않은 Task의 참조뿐이면 아무도 wake할 수 없으므로 Task를 버리고,
+    // 참조가 모두 없어지면 Slot을 재사용한다.
+    fn release(&self, task: &Task) {
+        let state = &task.header.state;
+        match task.header.refs.fetch_sub(1, Ordering::SeqCst) {
+            1 => self.slab.free(task.header.idx),
+            2 if state.compare_exchange(IDLE, COMPLETE, Ordering::SeqCst, Ordering::Relaxed).is_ok() => {
+                self.complete(task)
+            }
+            _ => (),
+        }
+    }
+
+    // 완료하지 않은 Task의 지표. Slot이 재사용되는 중이면 섞인 값을 읽을 수 있다.
+    fn metrics(&self) -> Vec<TaskMetrics> {
+        let mut v: Vec<_> = self
+            .slab
+            .iter()
+            .filter(|t| t.header.state.load(Ordering::Acquire) != COMPLETE)
+            .map(|t| t.metrics())
+            .collect();
+        v.sort_by_key(|m| m.id);
+        v
+    }
+
+    // 완료하지 않은 Task의 목록을 한 줄에 하나씩
+    fn dump(&self) -> String {
+        let tasks = self.metrics();
+        let mut s = format!("runtime: {} live tasks\n", tasks.len());
+        for m in tasks {
+            writeln!(s, "  {}", m).unwrap();
+        }
+        s
+    }
+
+    // 이후의 스케줄링을 멈추고 완료하지 않은 Task의 Future를 모두 버린다. poll 중인 Task는 poll이 끝난 뒤에 버려진다.
+    // 아직 시작하지 않은 spawn_blocking의 작업도 버린다.
+    fn shutdown(&self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+        self.blocking.shutdown();
+        for task in self.slab.iter() {
+            let state = &task.header.state;
+            for s in [IDLE, SCHEDULED] {
+                if state.compare_exchange(s, COMPLETE, Ordering::SeqCst, Ordering::Relaxed).is_ok() {
+                    self.complete(task);
+                    break;
+                }
+            }
+        }
+        self.notify();
+    }
+}
+
+// Task의 상태. wake는 IDLE일 때만 실행 Queue에 넣고, poll 중(RUNNING)이면 NOTIFIED로 바꿔서 poll이 끝난 뒤에
+// 다시 넣는다. 그 밖의 상태에서의 wake는 무시하므로 중복된 wake가 합쳐지고, 여러 Worker가 같은 Task를 동시에
+// poll하지 않는다. 비어 있는 Slot은 COMPLETE다.
+const IDLE: u8 = 0;
+const SCHEDULED: u8 = 1;
+const RUNNING: u8 = 2;
+const NOTIFIED: u8 = 3;
+const COMPLETE: u8 = 4;
+
+// Task의 헤더. refs는 Waker, 실행 Queue의 항목, 그리고 완료하지 않은 Task 자신이 가지는 참조의 수로, 0이 되어야
+// Slot을 재사용하므로 오래된 Waker가 다른 Task를 wake하는 일은 없다.
+struct Header {
+    state: AtomicU8,
+    refs: AtomicUsize,
+    idx: usize,            // Slab의 번호
+    shared: *const Shared, // Arc<Shared>의 내용
+}
+
+/// 스케줄링 단위. Slab의 Slot에 있으며, Waker는 Task의 주소와 손으로 작성한 RawWakerVTable로 만든다.
+struct Task {
+    header: Header,
+    stats: TaskStats,
+    // 실행할 코루틴. 완료 또는 취소하면 None. state를 RUNNING이나 COMPLETE로 바꾼 스레드만 만진다.
+    future: UnsafeCell<Option<BoxFuture<'static, ()>>>,
+}
+
+// future는 state로 배타적으로 접근하고, shared는 Waker가 Arc의 참조를 세어서 살려 둔다.
+unsafe impl Send for Task {}
+unsafe impl Sync for Task {}
+
+impl Task {
+    fn raw_waker(&self) -> RawWaker {
+        RawWaker::new(self as *const Task as *const (), &WAKER_VTABLE)
+    }
+
+    // 다른 스레드에서 읽을 수 있다. Slot이 재사용되는 중이면 섞인 값을 읽을 수 있다.
+    fn metrics(&self) -> TaskMetrics {
+        let s = &self.stats;
+        let location = s.location.load(Ordering::Relaxed);
+        TaskMetrics {
+            id: s.id.load(Ordering::Relaxed),
+            // spawn 전에 읽은 Slot이면 이 함수의 위치
+            location: if location.is_null() { Location::caller() } else { unsafe { &*location } },
+            state: match self.header.state.load(Ordering::Acquire) {
+                SCHEDULED => TaskState::Scheduled,
+                RUNNING | NOTIFIED => TaskState::Polling,
+                _ => TaskState::Idle,
+            },
+            polls: s.polls.load(Ordering::Relaxed),
+            slow_polls: s.slow_polls.load(Ordering::Relaxed),
+            busy: Duration::from_nanos(s.busy.load(Ordering::Relaxed)),
+            since_wake: Duration::from_nanos(now_ns().saturating_sub(s.last_wake.load(Ordering::Relaxed))),
+            waiting_on: WaitOn::decode(s.wait.load(Ordering::Relaxed)),
+            ops: s.ops.load(Ordering::Relaxed),
+            forced_yields: s.forced_yields.load(Ordering::Relaxed),
+            voluntary_yields: s.voluntary_yields.load(Ordering::Relaxed),
+        }
+    }
+}
+
+// Waker를 clone하면 Task와 Shared의 참조를 하나씩 세고, drop하면 놓는다. 할당은 하지 않는다.
+static WAKER_VTABLE: RawWakerVTable = RawWakerVTable::new(clone_waker, wake, wake_by_ref, drop_waker);
+
+unsafe fn clone_waker(ptr: *const ()) -> RawWaker {
+    let task = &*(ptr as *const Task);
+    task.header.refs.fetch_add(1, Ordering::Relaxed);
+    Arc::increment_strong_count(task.header.shared);
+    RawWaker::new(ptr, &WAKER_VTABLE)
+}
+
+unsafe fn wake(ptr: *const ()) {
+    wake_by_ref(ptr);
+    drop_waker(ptr);
+}
+
+unsafe fn wake_by_ref(ptr: *const ()) {
+    let task = &*(ptr as *const Task);
+    (*task.header.shared).wake(task);
+}
+
+unsafe fn drop_waker(ptr: *const ()) {
+    let task = &*(ptr as *const Task);
+    let shared = task.header.shared;
+    (*shared).release(task);
+    Arc::decrement_strong_count(shared);
+}
+
+// Task별 공평성 통계와 진단 정보. 다른 스레드에서 읽을 수 있도록 아토믹으로 둔다.
+#[derive(Default)]
+struct TaskStats {
+    id: AtomicUsize,
+    location: AtomicPtr<Location<'static>>, // spawn한 위치
+    polls: AtomicU64,
+    slow_polls: AtomicU64,
+    busy: AtomicU64,      // poll에 걸린 시간의 합(ns)
+    last_wake: AtomicU64, // 마지막으로 wake(또는 spawn)된 시각. now_ns의 값
+    wait: AtomicU64,      // 기다리는 대상. WaitOn::encode의 값이며 0이면 알 수 없음
+    ops: AtomicU64,
+    forced_yields: AtomicU64,
+    voluntary_yields: AtomicU64,
+}
+
+impl TaskStats {
+    fn reset(&self, id: usize, location: &'static Location<'static>) {
+        self.id.store(id, Ordering::Relaxed);
+        self.location.store(location as *const _ as *mut _, Ordering::Relaxed);
+        let counters = [
+            &self.polls,
+            &self.slow_polls,
+            &self.busy,
+            &self.wait,
+            &self.ops,
+            &self.forced_yields,
+            &self.voluntary_yields,
+        ];
+        for c in counters {
+            c.store(0, Ordering::Relaxed);
+        }
+        self.last_wake.store(now_ns(), Ordering::Relaxed);
+    }
+}
+
+/// Task의 공평성 지표와 진단 정보. Executor::metrics 등으로 완료하지 않은 Task의 것을 얻는다.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaskMetrics {
+    pub id: usize,              // spawn한 순서의 번호
+    pub location: &'static Location<'static>, // spawn(또는 block_on)을 호출한 위치
+    pub state: TaskState,
+    pub polls: u64,             // poll된 횟수
+    pub slow_polls: u64,        // 기준보다 오래 걸린 poll 횟수
+    pub busy: Duration,         // poll에 걸린 시간의 합
+    pub since_wake: Duration,   // 마지막으로 wake(또는 spawn)된 뒤 지난 시간
+    pub waiting_on: Option<WaitOn>, // 마지막 poll에서 Pending을 반환할 때 기다리기 시작한 대상
+    pub ops: u64,               // 예산을 소비한 준비된 연산 수
+    pub forced_yields: u64,     // 예산을 다 써서 양보한 횟수
+    pub voluntary_yields: u64,  // yield_now로 양보한 횟수
+}
+
+impl fmt::Display for TaskMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = match self.state {
+            TaskState::Scheduled => "scheduled",
+            TaskState::Idle => "idle",
+            TaskState::Polling => "polling",
+        };
+        let waiting_on = match self.waiting_on {
+            Some(WaitOn::Fd(fd)) => format!("fd {}", fd),
+            Some(WaitOn::Timer(deadline)) => match deadline.checked_duration_since(Instant::now()) {
+                Some(left) => format!("timer (in {:?})", left),
+                None => "timer (expired)".to_string(),
+            },
+            None => "-".to_string(),
+        };
+        write!(
+            f,
+            "task {:<4} {:<9} polls = {:<6} busy = {:>12}  woken {:>12} ago  waiting on {:<20} spawned at {}",
+            self.id,
+            state,
+            self.polls,
+            format!("{:?}", self.busy),
+            format!("{:?}", self.since_wake),
+            waiting_on,
+            self.location
+        )
+    }
+}
+
+/// Task의 상태
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaskState {
+    Scheduled, // 실행 Queue에 있음
+    Idle,      // wake를 기다림
+    Polling,   // poll 중
+}
+
+/// Task가 기다리는 대상. IOSelector에 등록한 Future가 기록한다.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WaitOn {
+    Fd(RawFd),        // IOSelector::register로 등록한 fd
+    Timer(Instant),   // sleep의 만료 시각
+}
+
+// 상위 2비트가 종류이고 나머지가 fd 또는 만료 시각(now_ns의 값)
+const WAIT_FD: u64 = 1 << 62;
+const WAIT_TIMER: u64 = 2 << 62;
+const WAIT_MASK: u64 = (1 << 62) - 1;
+
+impl WaitOn {
+    fn encode(self) -> u64 {
+        match self {
+            WaitOn::Fd(fd) => WAIT_FD | fd as u32 as u64,
+            WaitOn::Timer(deadline) => {
+                WAIT_TIMER | deadline.saturating_duration_since(epoch()).as_nanos().min(WAIT_MASK as u128) as u64
+            }
+        }
+    }
+
+    fn decode(v: u64) -> Option<WaitOn> {
+        match v & !WAIT_MASK {
+            WAIT_FD => Some(WaitOn::Fd((v & WAIT_MASK) as u32 as RawFd)),
+            WAIT_TIMER => Some(WaitOn::Timer(epoch() + Duration::from_nanos(v & WAIT_MASK))),
+            _ => None,
+        }
+    }
+}
+
+// poll 중인 Task가 what을 기다린다고 기록한다. Task 밖에서는 아무것도 하지 않는다.
+fn wait_on(what: WaitOn) {
+    with_current(|s| s.wait.store(what.encode(), Ordering::Relaxed));
+}
+
+// 시각을 AtomicU64에 넣기 위한 기준
+fn epoch() -> Instant {
+    static EPOCH: OnceLock<Instant> = OnceLock::new();
+    *EPOCH.get_or_init(Instant::now)
+}
+
+fn now_ns() -> u64 {
+    epoch().elapsed().as_nanos() as u64
+}
+
+// poll 1회의 기본 예산
+pub const DEFAULT_BUDGET: u32 = 128;
+
+thread_local! {
+    // poll 중인 Task의 남은 예산과 통계. Executor의 Task 밖에서는 None과 null이다.
+    static BUDGET: Cell<Option<u32>> = const { Cell::new(None) };
+    static CURRENT: Cell<*const TaskStats> = const { Cell::new(std::ptr::null()) };
+    // poll 중인 Task를 실행하는 Executor. spawn_blocking이 이용한다.
+    static EXECUTOR: Cell<*const Shared> = const { Cell::new(std::ptr::null()) };
+}
+
+// Task를 poll하는 동안 예산과 현재 Task, Executor를 설정하고, 끝나면 이전 값으로 되돌린다.
+struct Coop {
+    budget: Option<u32>,
+    stats: *const TaskStats,
+    shared: *const Shared,
+}
+
+impl Coop {
+    fn enter(budget: u32, stats: &TaskStats, shared: &Shared) -> Coop {
+        Coop {
+            budget: BUDGET.replace(Some(budget)),
+            stats: CURRENT.replace(stats),
+            shared: EXECUTOR.replace(shared),
+        }
+    }
+}
+
+impl Drop for Coop {
+    fn drop(&mut self) {
+        BUDGET.set(self.budget);
+        CURRENT.set(self.stats);
+        EXECUTOR.set(self.shared);
+    }
+}
+
+fn with_current(f: impl FnOnce(&TaskStats)) {
+    let stats = CURRENT.get();
+    if !stats.is_null() {
+        f(unsafe { &*stats }); // poll이 끝날 때까지 Slot은 재사용되지 않는다.
+    }
+}
+
+/// 남은 예산이 없으면 Task를 다시 스케줄링하고 Pending을 반환한다. 준비된 연산을 실행하기 전에 호출하고, 실행했으면
+/// spend_budget을 호출한다. 항상 준비된 연산만 반복하는 Task가 다른 Task를 굶기지 않도록 한다.
+pub fn poll_budget(cx: &mut Context<'_>) -> Poll<()> {
+    if BUDGET.get() == Some(0) {
+        with_current(|s| {
+            s.forced_yields.fetch_add(1, Ordering::Relaxed);
+        });
+        cx.waker().wake_by_ref();
+        return Poll::Pending;
+    }
+    Poll::Ready(())
+}
+
+/// 준비된 연산 하나만큼 예산을 소비한다.
+pub fn spend_budget() {
+    if let Some(n) = BUDGET.get() {
+        BUDGET.set(Some(n.saturating_sub(1)));
+    }
+    with_current(|s| {
+        s.ops.fetch_add(1, Ordering::Relaxed);
+    });
+}
+
+/// 예산을 하나 소비한다. 예산을 다 썼으면 한 번 양보한다.
+pub async fn consume_budget() {
+    futures::future::poll_fn(|cx| {
+        ready!(poll_budget(cx));
+        spend_budget();
+        Poll::Ready(())
+    })
+    .await
+}
+
+/// 한 번 Pending을 반환해서 실행 Queue의 다른 Task에 실행을 양보한다.
+pub fn yield_now() -> YieldNow {
+    YieldNow { yielded: false }
+}
+
+pub struct YieldNow {
+    yielded: bool,
+}
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.yielded {
+            return Poll::Ready(());
+        }
+        self.yielded = true;
+        with_current(|s| {
+            s.voluntary_yields.fetch_add(1, Ordering::Relaxed);
+        });
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+// 첫 페이지의 Slot 수는 2^PAGE_BITS이고, 페이지마다 2배가 된다. 페이지는 한 번 만들면 옮기지 않으므로 Waker가
+// Task의 주소를 가질 수 있다.
+const PAGE_BITS: u32 = 5;
+const PAGES: usize = 32;
+
+// Task를 넣는 Slab. 완료한 Task의 Slot은 free에 넣었다가 재사용한다.
+struct Slab {
+    pages: [OnceLock<Box<[Task]>>; PAGES],
+    alloc: Mutex<SlabAlloc>,
+}
+
+struct SlabAlloc {
+    next: usize,     // 아직 한 번도 쓰지 않은 첫 번호
+    free: Vec<usize>,
+}
+
+impl Slab {
+    fn new() -> Self {
+        Slab {
+            pages: std::array::from_fn(|_| OnceLock::new()),
+            alloc: Mutex::new(SlabAlloc {
+                next: 0,
+                free: Vec::new(),
+            }),
+        }
+    }
+
+    // 번호 -> (페이지, 페이지 안의 위치)
+    fn locate(idx: usize) -> (usize, usize) {
+        let n = idx + (1 << PAGE_BITS);
+        let page = (usize::BITS - 1 - n.leading_zeros() - PAGE_BITS) as usize;
+        (page, n - (1 << (page as u32 + PAGE_BITS)))
+    }
+
+    fn get(&self, idx: usize) -> &Task {
+        let (page, i) = Slab::locate(idx);
+        &self.pages[page].get().unwrap()[i]
+    }
+
+    fn alloc(&self, shared: &Shared) -> usize {
+        let mut a = self.alloc.lock().unwrap();
+        if let Some(idx) = a.free.pop() {
+            return idx;
+        }
+        let idx = a.next;
+        a.next += 1;
+        let (page, _) = Slab::locate(idx);
+        self.pages[page].get_or_init(|| {
+            let first = (1 << (page as u32 + PAGE_BITS)) - (1 << PAGE_BITS);
+            (0..1 << (page as u32 + PAGE_BITS))
+                .map(|i| Task {
+                    header: Header {
+                        state: AtomicU8::new(COMPLETE),
+                        refs: AtomicUsize::new(0),
+                        idx: first + i,
+                        shared,
+                    },
+                    stats: TaskStats::default(),
+                    future: UnsafeCell::new(None),
+                })
+                .collect()
+        });
+        idx
+    }
+
+    fn free(&self, idx: usize) {
+        self.alloc.lock().unwrap().free.push(idx);
+    }
+
+    // 만들어진 모든 Slot
+    fn iter(&self) -> impl Iterator<Item = &Task> {
+        self.pages.iter().filter_map(|p| p.get()).flat_map(|p| p.iter())
+    }
+}
+
+/// 실행 Queue에서 Task를 꺼내 순서대로 poll하는 Executor. drop하면 shutdown한다.
+pub struct Executor {
+    shared: Arc<Shared>,
+}
+
+impl Default for Executor {
+    fn default() -> Self {
+        Executor::new()
+    }
+}
+
+impl Executor {
+    pub fn new() -> Self {
+        Executor {
+            shared: Shared::new(Queue::Single(RunQueue {
+                queue: Mutex::new(VecDeque::new()),
+                parked: AtomicBool::new(false),
+                cond: Condvar::new(),
+            })),
+        }
+    }
+
+    // 새롭게 Task를 생성하고 실행 Queue에 넣기 위한 객체
+    pub fn get_spawner(&self) -> Spawner {
+        Spawner {
+            shared: self.shared.clone(),
+        }
+    }
+
+    // 완료하지 않은 Task 수
+    pub fn live_tasks(&self) -> usize {
+        self.shared.live_tasks()
+    }
+
+    /// poll 1회에 실행할 수 있는 준비된 연산 수. u32::MAX면 사실상 제한이 없다.
+    pub fn set_budget(&self, budget: u32) {
+        self.shared.budget.store(budget, Ordering::Relaxed);
+    }
+
+    /// threshold보다 오래 걸린 poll을 Task 번호, spawn한 위치, 걸린 시간과 함께 표준 에러에 출력한다. None이면
+    /// 출력하지 않는다. strict면 출력하는 대신 poll이 끝난 뒤 run(또는 block_on)을 호출한 스레드에서 panic하므로,
+    /// 개발 중에 async 안의 blocking 호출을 찾을 수 있다.
+    pub fn set_slow_poll(&self, threshold: Option<Duration>, strict: bool) {
+        self.shared.set_slow_poll(threshold, strict);
+    }
+
+    /// 완료하지 않은 Task의 공평성 지표
+    pub fn metrics(&self) -> Vec<TaskMetrics> {
+        self.shared.metrics()
+    }
+
+    /// spawn_blocking의 최대 스레드 수와, 작업이 없는 스레드를 종료할 때까지의 시간. 기본값은
+    /// DEFAULT_MAX_BLOCKING_THREADS와 DEFAULT_BLOCKING_IDLE_TIMEOUT이다.
+    pub fn set_blocking_limits(&self, max_threads: usize, idle_timeout: Duration) {
+        self.shared.blocking.set_limits(max_threads, idle_timeout);
+    }
+
+    /// spawn_blocking 스레드 풀의 스레드 수와 큐에서 기다리는 작업 수
+    pub fn blocking_metrics(&self) -> BlockingMetrics {
+        self.shared.blocking.metrics()
+    }
+
+    /// 완료하지 않은 Task의 상태, poll 횟수, poll에 걸린 시간, 마지막 wake 이후 지난 시간, 기다리는 fd 또는 타이머,
+    /// spawn한 위치를 한 줄에 하나씩 나열한다. 다른 스레드에서 호출할 수 있도록 Spawner::dump도 있다.
+    pub fn dump(&self) -> String {
+        self.shared.dump()
+    }
+
+    fn run_queue(&self) -> &RunQueue {
+        match &self.shared.queue {
+            Queue::Single(q) => q,
+            Queue::Pool(_) => unreachable!(),
+        }
+    }
+
+    // 실행 Queue에서 Task를 꺼냄. done()이 true가 되거나, 모든 Task가 완료하거나, shutdown되면 None
+    fn next(&self, done: impl Fn() -> bool) -> Option<usize> {
+        let q = self.run_queue();
+        let mut queue = q.queue.lock().unwrap();
+        loop {
+            if done() {
+                return None;
+            }
+            if let Some(idx) = queue.pop_front() {
+                return Some(idx);
+            }
+            if self.shared.is_shutdown() || self.live_tasks() == 0 {
+                return None;
+            }
+            q.parked.store(true, Ordering::Relaxed);
+            queue = q.cond.wait(queue).unwrap();
+            q.parked.store(false, Ordering::Relaxed);
+        }
+    }
+
+    /// 실행 Queue에서 Task를 꺼내서 순서대로 실행한다. 모든 Task가 완료하거나 shutdown되면 반환한다.
+    pub fn run(&self) {
+        while let Some(idx) = self.next(|| false) {
+            self.shared.run(idx);
+        }
+    }
+
+    /// future를 Task로 실행하고 그 결과가 나올 때까지만 실행 Queue의 Task를 실행한다.
+    #[track_caller]
+    pub fn block_on<F>(&self, future: F) -> F::Output
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        let handle = self.get_spawner().spawn(future);
+        loop {
+            if let Some(r) = handle.try_take() {
+                return unwrap_block_on(r, self.shared.is_shutdown());
+            }
+            if let Some(idx) = self.next(|| handle.is_finished()) {
+                self.shared.run(idx);
+            }
+        }
+    }
+
+    /// 완료하지 않은 Task를 모두 버리고 이후의 spawn과 wake를 무시한다. 버린 Task의 JoinHandle은 Cancelled가 된다.
+    pub fn shutdown(&self) {
+        self.shared.shutdown();
+        self.drain();
+    }
+
+    // 실행 Queue에 남은 Task를 버림
+    fn drain(&self) {
+        loop {
+            let idx = self.run_queue().queue.lock().unwrap().pop_front();
+            match idx {
+                Some(idx) => self.shared.run(idx),
+                None => break,
+            }
+        }
+    }
+}
+
+impl Drop for Executor {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+// block_on의 결과. future의 panic은 호출자에게 전달한다. Cancelled는 shutdown으로 버려진 경우와 아무도 wake할 수
+// 없게 되어 버려진 경우가 있으므로 shutdown으로 구별해서 알린다.
+fn unwrap_block_on<T>(r: Result<T, JoinError>, shutdown: bool) -> T {
+    match r {
+        Ok(v) => v,
+        Err(JoinError::Panic(p)) => std::panic::resume_unwind(p),
+        Err(JoinError::Cancelled) if shutdown => panic!("block_on: executor was shut down"),
+        Err(JoinError::Cancelled) => panic!("block_on: future was abandoned because nothing can wake it"),
+    }
+}
+
+// Worker마다의 큐. lifo는 Worker 자신이 방금 wake한 Task를 넣는 슬롯으로, 다음에 바로 실행한다. 메시지를 보내고
+// 상대의 응답을 기다리는 것처럼 wake한 Task가 곧바로 진행할 수 있는 경우 캐시가 따뜻할 때 실행할 수 있다.
+struct Local {
+    lifo: Mutex<Option<usize>>,
+    deque: Mutex<VecDeque<usize>>,
+}
+
+// MultiThreadExecutor의 작업 큐
+struct Pool {
+    injector: Mutex<VecDeque<usize>>, // Worker 밖에서 스케줄링된 Task
+    locals: Vec<Local>,
+    sleep: Mutex<()>,
+    sleeping: AtomicUsize, // 잠든 Worker 수
+    work: Condvar,         // 잠든 Worker를 깨움
+    idle: Condvar,         // run을 깨움
+    // Worker에서 오래 걸린 poll(strict). run이나 block_on이 꺼내서 호출한 스레드에서 panic한다.
+    panic: Mutex<Option<Box<dyn Any + Send + 'static>>>,
+    blocked: Mutex<Vec<thread::Thread>>, // block_on에서 블록 중인 스레드
+}
+
+// lifo 슬롯의 Task를 연속해서 실행하는 최대 횟수. 서로 wake하는 두 Task가 다른 Task를 굶기지 않도록 한다.
+const MAX_LIFO_POLLS: u32 = 3;
+// 이 횟수마다 로컬 큐보다 injector를 먼저 확인한다.
+const INJECTOR_INTERVAL: u32 = 61;
+
+thread_local! {
+    // 현재 스레드가 Worker면 (Pool의 주소, Worker 번호)
+    static WORKER: Cell<Option<(usize, usize)>> = const { Cell::new(None) };
+}
+
+impl Pool {
+    fn new(workers: usize) -> Arc<Self> {
+        Arc::new(Pool {
+            injector: Mutex::new(VecDeque::new()),
+            locals: (0..workers)
+                .map(|_| Local {
+                    lifo: Mutex::new(None),
+                    deque: Mutex::new(VecDeque::new()),
+                })
+                .collect(),
+            sleep: Mutex::new(()),
+            sleeping: AtomicUsize::new(0),
+            work: Condvar::new(),
+            idle: Condvar::new(),
+            panic: Mutex::new(None),
+            blocked: Mutex::new(Vec::new()),
+        })
+    }
+
+    // 현재 스레드가 이 Pool의 Worker면 그 번호
+    fn current(&self) -> Option<usize> {
+        let me = self as *const Pool as usize;
+        WORKER.with(|w| w.get()).and_then(|(pool, idx)| (pool == me).then_some(idx))
+    }
+
+    // yielded면 lifo 슬롯 대신 로컬 큐의 뒤에 넣는다.
+    fn schedule(&self, task: usize, yielded: bool) {
+        match self.current() {
+            Some(idx) if yielded => {
+                self.locals[idx].deque.lock().unwrap().push_back(task);
+                if self.sleeping.load(Ordering::SeqCst) > 0 {
+                    let _g = self.sleep.lock().unwrap();
+                    self.work.notify_one();
+                }
+            }
+            Some(idx) => {
+                // Worker 안에서의 wake는 lifo 슬롯에. 원래 있던 Task는 로컬 큐로 옮긴다.
+                let local = &self.locals[idx];
+                if let Some(prev) = local.lifo.lock().unwrap().replace(task) {
+                    local.deque.lock().unwrap().push_back(prev);
+                    // 훔쳐갈 수 있는 Task가 생겼으므로 잠든 Worker가 있으면 깨운다.
+                    if self.sleeping.load(Ordering::SeqCst) > 0 {
+                        let _g = self.sleep.lock().unwrap();
+                        self.work.notify_one();
+                    }
+                }
+            }
+            None => {
+                self.injector.lock().unwrap().push_back(task);
+                let _g = self.sleep.lock().unwrap();
+                self.work.notify_one();
+            }
+        }
+    }
+
+    fn notify_all(&self) {
+        let _g = self.sleep.lock().unwrap();
+        self.work.notify_all();
+        self.idle.notify_all();
+    }
+
+    // Worker idx가 다음에 실행할 Task
+    fn next(&self, idx: usize, tick: &mut u32, lifo_polls: &mut u32) -> Option<usize> {
+        let local = &self.locals[idx];
+        *tick = tick.wrapping_add(1);
+        if tick.is_multiple_of(INJECTOR_INTERVAL) {
+            if let Some(t) = self.injector.lock().unwrap().pop_front() {
+                return Some(t);
+            }
+        }
+        let lifo = local.lifo.lock().unwrap().take();
+        if let Some(t) = lifo {
+            if *lifo_polls < MAX_LIFO_POLLS {
+                *lifo_polls += 1;
+                return Some(t);
+            }
+            local.deque.lock().unwrap().push_back(t);
+        }
+        *lifo_polls = 0;
+        if let Some(t) = local.deque.lock().unwrap().pop_front() {
+            return Some(t);
+        }
+        if let Some(t) = self.injector.lock().unwrap().pop_front() {
+            return Some(t);
+        }
+        self.steal(idx)
+    }
+
+    // 다른 Worker의 로컬 큐 뒤쪽 절반을 훔침
+    fn steal(&self, idx: usize) -> Option<usize> {
+        let n = self.locals.len();
+        for i in 1..n {
+            let victim = &self.locals[(idx + i) % n];
+            let mut stolen = {
+                let mut q = victim.deque.lock().unwrap();
+                let half = q.len() - q.len() / 2;
+                let at = q.len() - half;
+                q.split_off(at)
+            };
+            if let Some(t) = stolen.pop_front() {
+                self.locals[idx].deque.lock().unwrap().extend(stolen);
+                return Some(t);
+            }
+        }
+        None
+    }
+
+    // 훔치거나 꺼낼 수 있는 Task가 있는가
+    fn has_work(&self) -> bool {
+        !self.injector.lock().unwrap().is_empty() || self.locals.iter().any(|l| !l.deque.lock().unwrap().is_empty())
+    }
+
+    // 일이 생길 때까지 잠듦. shutdown되었으면 false
+    fn park(&self, shared: &Shared) -> bool {
+        let mut g = self.sleep.lock().unwrap();
+        loop {
+            if shared.is_shutdown() {
+                return false;
+            }
+            if self.has_work() {
+                return true;
+            }
+            self.sleeping.fetch_add(1, Ordering::SeqCst);
+            g = self.work.wait(g).unwrap();
+            self.sleeping.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    // Worker에서 일어난 panic을 기록하고 run과 block_on을 깨운다. 먼저 기록한 것만 남긴다.
+    fn report(&self, panic: Box<dyn Any + Send + 'static>) {
+        self.panic.lock().unwrap().get_or_insert(panic);
+        for t in self.blocked.lock().unwrap().iter() {
+            t.unpark();
+        }
+        self.notify_all();
+    }
+
+    fn take_panic(&self) -> Option<Box<dyn Any + Send + 'static>> {
+        self.panic.lock().unwrap().take()
+    }
+
+    // 큐에 남은 Task를 모두 꺼냄
+    fn drain(&self) -> Vec<usize> {
+        let mut tasks: Vec<usize> = self.injector.lock().unwrap().drain(..).collect();
+        for l in self.locals.iter() {
+            tasks.extend(l.lifo.lock().unwrap().take());
+            tasks.extend(l.deque.lock().unwrap().drain(..));
+        }
+        tasks
+    }
+}
+
+fn worker(shared: Arc<Shared>, pool: Arc<Pool>, idx: usize) {
+    WORKER.with(|w| w.set(Some((Arc::as_ptr(&pool) as usize, idx))));
+    let (mut tick, mut lifo_polls) = (0, 0);
+    while !shared.is_shutdown() {
+        match pool.next(idx, &mut tick, &mut lifo_polls) {
+            Some(task) => shared.run(task),
+            None => {
+                if !pool.park(&shared) {
+                    break;
+                }
+            }
+        }
+    }
+    WORKER.with(|w| w.set(None));
+}
+
+/// 여러 Worker 스레드로 Task를 실행하는 work-stealing Executor.
+/// Worker마다 로컬 큐와 lifo 슬롯을 가지고, Worker 밖에서 스케줄링된 Task는 injector에 넣는다. 자신의 큐가 비면
+/// injector에서 꺼내고, 그래도 없으면 다른 Worker의 로컬 큐에서 절반을 훔친다. Worker는 생성할 때 시작하며
+/// shutdown 또는 drop할 때까지 실행한다.
+pub struct MultiThreadExecutor {
+    shared: Arc<Shared>,
+    pool: Arc<Pool>,
+    workers: Mutex<Vec<thread::JoinHandle<()>>>,
+}
+
+impl MultiThreadExecutor {
+    pub fn new(workers: usize) -> Self {
+        assert!(workers > 0);
+        let pool = Pool::new(workers);
+        let shared = Shared::new(Queue::Pool(pool.clone()));
+        let handles = (0..workers)
+            .map(|idx| {
+                let (shared, pool) = (shared.clone(), pool.clone());
+                thread::Builder::new()
+                    .name(format!("runtime-worker-{}", idx))
+                    .spawn(move || worker(shared, pool, idx))
+                    .unwrap()
+            })
+            .collect();
+        MultiThreadExecutor {
+            shared,
+            pool,
+            workers: Mutex::new(handles),
+        }
+    }
+
+    pub fn get_spawner(&self) -> Spawner {
+        Spawner {
+            shared: self.shared.clone(),
+        }
+    }
+
+    pub fn live_tasks(&self) -> usize {
+        self.shared.live_tasks()
+    }
+
+    pub fn set_budget(&self, budget: u32) {
+        self.shared.budget.store(budget, Ordering::Relaxed);
+    }
+
+    // Executor::set_slow_poll과 같다. strict면 Worker 스레드 대신 run(또는 block_on)을 호출한 스레드에서 panic하며,
+    // Worker는 계속 실행한다.
+    pub fn set_slow_poll(&self, threshold: Option<Duration>, strict: bool) {
+        self.shared.set_slow_poll(threshold, strict);
+    }
+
+    pub fn metrics(&self) -> Vec<TaskMetrics> {
+        self.shared.metrics()
+    }
+
+    pub fn dump(&self) -> String {
+        self.shared.dump()
+    }
+
+    pub fn set_blocking_limits(&self, max_threads: usize, idle_timeout: Duration) {
+        self.shared.blocking.set_limits(max_threads, idle_timeout);
+    }
+
+    pub fn blocking_metrics(&self) -> BlockingMetrics {
+        self.shared.blocking.metrics()
+    }
+
+    /// 모든 Task가 완료하거나 shutdown될 때까지 기다린다.
+    pub fn run(&self) {
+        let mut g = self.pool.sleep.lock().unwrap();
+        loop {
+            if let Some(p) = self.pool.take_panic() {
+                drop(g);
+                std::panic::resume_unwind(p);
+            }
+            if self.shared.is_shutdown() || self.live_tasks() == 0 {
+                return;
+            }
+            g = self.pool.idle.wait(g).unwrap();
+        }
+    }
+
+    /// future를 Task로 실행하고 결과가 나올 때까지 현재 스레드를 블록한다. Worker 안에서 호출하면 안 된다.
+    #[track_caller]
+    pub fn block_on<F>(&self, future: F) -> F::Output
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        assert!(self.pool.current().is_none(), "block_on: called from a worker thread");
+        let mut handle = self.get_spawner().spawn(future);
+        let me = thread::current();
+        let waker = waker(Arc::new(ThreadWaker(me.clone())));
+        let mut ctx = Context::from_waker(&waker);
+        self.pool.blocked.lock().unwrap().push(me.clone());
+        let r = loop {
+            if let Some(p) = self.pool.take_panic() {
+                break Err(p);
+            }
+            match Pin::new(&mut handle).poll(&mut ctx) {
+                Poll::Ready(r) => break Ok(r),
+                Poll::Pending => thread::park(),
+            }
+        };
+        self.pool.blocked.lock().unwrap().retain(|t| t.id() != me.id());
+        match r {
+            Ok(r) => unwrap_block_on(r, self.shared.is_shutdown()),
+            Err(p) => std::panic::resume_unwind(p),
+        }
+    }
+
+    /// 완료하지 않은 Task를 모두 버리고 Worker를 멈춘다. Worker 밖에서 호출하면 Worker의 종료를 기다린다.
+    pub fn shutdown(&self) {
+        self.shared.shutdown();
+        if self.pool.current().is_none() {
+            let workers: Vec<_> = self.workers.lock().unwrap().drain(..).collect();
+            for w in workers {
+                let _ = w.join();
+            }
+        }
+        for task in self.pool.drain() {
+            self.shared.run(task); // shutdown 후이므로 버린다.
+        }
+    }
+}
+
+impl Drop for MultiThreadExecutor {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+// block_on에서 현재 스레드를 깨우는 Waker
+struct ThreadWaker(thread::Thread);
+
+impl ArcWake for ThreadWaker {
+    fn wake_by_ref(arc_self: &Arc<Self>) {
+        arc_self.0.unpark();
+    }
+}
+
+/// Future를 Task로 감싸서 실행 Queue에 넣는 핸들
+#[derive(Clone)]
+pub struct Spawner {
+    shared: Arc<Shared>,
+}
+
+impl Spawner {
+    /// future를 Task로 실행한다. 반환한 JoinHandle을 버려도 Task는 계속 실행된다. 호출한 위치를 Task에 기록한다.
+    #[track_caller]
+    pub fn spawn<F>(&self, future: F) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        let (future, handle) = with_join_handle(future);
+        if self.shared.is_shutdown() {
+            return handle; // future와 함께 Completer가 버려지므로 Cancelled
+        }
+
+        // Slab에 넣고 실행 Queue에 인큐
+        self.shared.spawn(future.boxed(), Location::caller());
+        handle
+    }
+
+    /// f를 블로킹 스레드 풀에서 실행한다. runtime::spawn_blocking과 같으며 Task 밖에서도 호출할 수 있다.
+    pub fn spawn_blocking<F, T>(&self, f: F) -> JoinHandle<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        self.shared.blocking.spawn(f)
+    }
+
+    // Executor::shutdown과 같다. Task 안에서 Executor를 멈출 때 이용한다.
+    pub fn shutdown(&self) {
+        self.shared.shutdown();
+    }
+
+    // Executor::metrics와 같다.
+    pub fn metrics(&self) -> Vec<TaskMetrics> {
+        self.shared.metrics()
+    }
+
+    // Executor::dump와 같다.
+    pub fn dump(&self) -> String {
+        self.shared.dump()
+    }
+
+    // Executor::blocking_metrics와 같다.
+    pub fn blocking_metrics(&self) -> BlockingMetrics {
+        self.shared.blocking.metrics()
+    }
+}
+
+/// SIGUSR1을 받을 때마다 spawner의 Executor에서 완료하지 않은 Task의 목록(Executor::dump)을 표준 에러에 출력한다.
+/// Executor가 멈춰 있어도 시그널 수신 스레드에서 출력하므로 어느 Task가 무엇을 기다리는지 알 수 있다.
+// $ kill -USR1 <pid>
+pub fn install_sigusr1_dump(spawner: &Spawner) -> Result<DumpHandle, Box<dyn Error>> {
+    let shared = Arc::downgrade(&spawner.shared); // 덤프 스레드가 Executor를 살려 두지 않도록
+    on_signal(SIGUSR1, move || {
+        if let Some(shared) = shared.upgrade() {
+            eprint!("{}", shared.dump());
+        }
+    })
+}
+
+/// Task가 실패한 이유
+pub enum JoinError {
+    Panic(Box<dyn Any + Send + 'static>), // poll 중에 panic. panic!에 전달한 값을 가진다.
+    Cancelled,                            // 완료하기 전에 shutdown되었거나, 아무도 wake할 수 없게 되어 버려짐
+}
+
+impl JoinError {
+    pub fn is_panic(&self) -> bool {
+        matches!(self, JoinError::Panic(_))
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        matches!(self, JoinError::Cancelled)
+    }
+
+    // panic!에 전달한 값. std::panic::resume_unwind로 다시 panic시킬 수 있다.
+    pub fn into_panic(self) -> Box<dyn Any + Send + 'static> {
+        match self {
+            JoinError::Panic(p) => p,
+            JoinError::Cancelled => panic!("JoinError::into_panic: task was cancelled"),
+        }
+    }
+}
+
+impl fmt::Display for JoinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JoinError::Panic(p) => {
+                let msg = p
+                    .downcast_ref::<&str>()
+                    .copied()
+                    .or_else(|| p.downcast_ref::<String>().map(|s| s.as_str()))
+                    .unwrap_or("Box<dyn Any>");
+                write!(f, "task panicked: {}", msg)
+            }
+            JoinError::Cancelled => write!(f, "task was cancelled"),
+        }
+    }
+}
+
+impl fmt::Debug for JoinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "JoinError({})", self)
+    }
+}
+
+impl std::error::Error for JoinError {}
+
+// Task와 JoinHandle이 공유하는 결과
+struct JoinState<T> {
+    result: Option<Result<T, JoinError>>,
+    waker: Option<Waker>, // 결과를 기다리는 Task
+}
+
+// Task의 Future가 가지고 있다가 결과를 기록한다. 결과를 기록하기 전에 버려지면 Cancelled를 기록한다.
+struct Completer<T>(Arc<Mutex<JoinState<T>>>);
+
+impl<T> Completer<T> {
+    fn complete(&self, result: Result<T, JoinError>) {
+        let mut j = self.0.lock().unwrap();
+        j.result = Some(result);
+        if let Some(w) = j.waker.take() {
+            w.wake();
+        }
+    }
+}
+
+impl<T> Drop for Completer<T> {
+    fn drop(&mut self) {
+        let done = self.0.lock().unwrap().result.is_some();
+        if !done {
+            self.complete(Err(JoinError::Cancelled));
+        }
+    }
+}
+
+// future의 결과를 JoinHandle로 전달하는 future를 만든다. poll 중의 panic을 잡아서 JoinHandle로 전달한다.
+fn with_join_handle<F: Future + 'static>(future: F) -> (impl Future<Output = ()>, JoinHandle<F::Output>) {
+    let join = Arc::new(Mutex::new(JoinState {
+        result: None,
+        waker: None,
+    }));
+    let completer = Completer(join.clone());
+    let future = async move {
+        let result = AssertUnwindSafe(future).catch_unwind().await.map_err(JoinError::Panic);
+        completer.complete(result);
+    };
+    (future, JoinHandle { join })
+}
+
+/// spawn한 Task의 결과를 기다리는 Future
+pub struct JoinHandle<T> {
+    join: Arc<Mutex<JoinState<T>>>,
+}
+
+impl<T> JoinHandle<T> {
+    // 결과가 나왔으면 꺼냄
+    fn try_take(&self) -> Option<Result<T, JoinError>> {
+        self.join.lock().unwrap().result.take()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.join.lock().unwrap().result.is_some()
+    }
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = Result<T, JoinError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        ready!(poll_budget(cx));
+        let mut j = self.join.lock().unwrap();
+        match j.result.take() {
+            Some(r) => {
+                spend_budget();
+                Poll::Ready(r)
+            }
+            None => {
+                j.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+// spawn_blocking의 기본값
+pub const DEFAULT_MAX_BLOCKING_THREADS: usize = 512;
+pub const DEFAULT_BLOCKING_IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+// spawn_blocking의 스레드 풀. 스레드는 처음부터 만들지 않고, 작업을 넣을 때 기다리는 스레드가 부족하면 max_threads까지
+// 하나씩 늘린다. idle_timeout 동안 작업이 없던 스레드는 종료한다. max_threads개가 모두 일하는 중이면 작업은 큐에서
+// 기다린다.
+struct BlockingPool {
+    state: Mutex<BlockingState>,
+    cond: Condvar, // 작업을 기다리는 스레드를 깨움
+}
+
+struct BlockingState {
+    queue: VecDeque<Job>,
+    threads: usize, // 살아 있는 스레드 수
+    idle: usize,    // 작업을 기다리는 스레드 수
+    spawned: usize, // 지금까지 생성한 스레드 수
+    max_threads: usize,
+    idle_timeout: Duration,
+    shutdown: bool,
+}
+
+/// spawn_blocking 스레드 풀의 지표
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockingMetrics {
+    pub threads: usize,         // 살아 있는 스레드 수
+    pub idle_threads: usize,    // 작업을 기다리는 스레드 수
+    pub queue_depth: usize,     // 스레드가 비기를 기다리는 작업 수
+    pub spawned_threads: usize, // 지금까지 생성한 스레드 수
+}
+
+impl BlockingPool {
+    fn new() -> Arc<Self> {
+        Arc::new(BlockingPool {
+            state: Mutex::new(BlockingState {
+                queue: VecDeque::new(),
+                threads: 0,
+                idle: 0,
+                spawned: 0,
+                max_threads: DEFAULT_MAX_BLOCKING_THREADS,
+                idle_timeout: DEFAULT_BLOCKING_IDLE_TIMEOUT,
+                shutdown: false,
+            }),
+            cond: Condvar::new(),
+        })
+    }
+
+    // f를 풀의 스레드에서 실행하고 결과를 JoinHandle로 전달한다. 결과를 기록하면 기다리는 Task의 Waker를 호출한다.
+    fn spawn<F, T>(self: &Arc<Self>, f: F) -> JoinHandle<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let join = Arc::new(Mutex::new(JoinState {
+            result: None,
+            waker: None,
+        }));
+        let completer = Completer(join.clone());
+        let job: Job = Box::new(move || {
+            let result = std::panic::catch_unwind(AssertUnwindSafe(f)).map_err(JoinError::Panic);
+            completer.complete(result);
+        });
+
+        let mut st = self.state.lock().unwrap();
+        if st.shutdown {
+            drop(st);
+            drop(job); // Completer가 버려지므로 Cancelled
+            return JoinHandle { join };
+        }
+        st.queue.push_back(job);
+        if st.queue.len() <= st.idle {
+            self.cond.notify_one();
+        } else if st.threads < st.max_threads {
+            st.threads += 1;
+            st.spawned += 1;
+            let pool = self.clone();
+            thread::Builder::new()
+                .name(format!("runtime-blocking-{}", st.spawned))
+                .spawn(move || pool.worker())
+                .unwrap();
+        }
+        JoinHandle { join }
+    }
+
+    fn worker(&self) {
+        let mut st = self.state.lock().unwrap();
+        loop {
+            if let Some(job) = st.queue.pop_front() {
+                drop(st);
+                job(); // panic은 job 안에서 잡는다.
+                st = self.state.lock().unwrap();
+                continue;
+            }
+            if st.shutdown || st.threads > st.max_threads {
+                break;
+            }
+            st.idle += 1;
+            let timeout = st.idle_timeout;
+            let (g, r) = self.cond.wait_timeout(st, timeout).unwrap();
+            st = g;
+            st.idle -= 1;
+            if r.timed_out() && st.queue.is_empty() {
+                break;
+            }
+        }
+        st.threads -= 1;
+    }
+
+    fn set_limits(&self, max_threads: usize, idle_timeout: Duration) {
+        assert!(max_threads > 0);
+        let mut st = self.state.lock().unwrap();
+        st.max_threads = max_threads;
+        st.idle_timeout = idle_timeout;
+        self.cond.notify_all(); // 기다리는 스레드가 새 값을 보도록
+    }
+
+    fn metrics(&self) -> BlockingMetrics {
+        let st = self.state.lock().unwrap();
+        BlockingMetrics {
+            threads: st.threads,
+            idle_threads: st.idle,
+            queue_depth: st.queue.len(),
+            spawned_threads: st.spawned,
+        }
+    }
+
+    // 큐의 작업을 버리고 기다리는 스레드를 종료한다. 실행 중인 작업은 끝날 때까지 실행한다.
+    fn shutdown(&self) {
+        let jobs: Vec<Job> = {
+            let mut st = self.state.lock().unwrap();
+            st.shutdown = true;
+            st.queue.drain(..).collect()
+        };
+        self.cond.notify_all();
+        drop(jobs);
+    }
+}
+
+/// 현재 Task를 실행하는 Executor의 블로킹 스레드 풀에서 f를 실행한다. 반환한 JoinHandle을 await하면 f의 결과를
+/// 얻으며, f의 panic은 JoinError::Panic이 된다. Executor와 MultiThreadExecutor의 Task 안에서만 호출할 수 있고,
+/// Task 밖에서는 Spawner::spawn_blocking을 이용한다.
+pub fn spawn_blocking<F, T>(f: F) -> JoinHandle<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let shared = EXECUTOR.get();
+    assert!(!shared.is_null(), "spawn_blocking: no executor is running on this thread");
+    unsafe { &*shared }.blocking.spawn(f) // poll 중에는 Executor가 살아 있다.
+}
+
+// eventfd에 n을 씀. IOSelector에 알리기 위해 1을 쓴다.
+fn write_eventfd(fd: RawFd, n: usize) {
+    // usize를 *const u8로 변환
+    let ptr = &n as *const usize as *const u8;
+    let val = unsafe { std::slice::from_raw_parts(ptr, std::mem::size_of_val(&n)) };
+    // write 시스템 콜 호출
+    write(fd, val).unwrap();
+}
+
+// IOSelector에 대한 요청
+enum IOOps {
+    Add(EpollFlags, RawFd, Waker), // epoll에 추가
+    Remove(RawFd),                 // epoll에서 삭제
+    Shutdown,                      // 전용 스레드 종료
+}
+
+/// epoll로 파일 디스크립터를 감시하고 이벤트가 발생하면 대응하는 Waker를 호출한다.
+/// 요청은 IO Queue에 넣고 eventfd로 알린다. 채널이 아니라 eventfd를 이용하는 이유는 전용 스레드가 epoll_wait으로
+/// 파일 디스크립터 감시도 함께 수행해야 하기 때문이다.
+/// 전용 스레드는 shutdown을 호출할 때까지 IOSelector를 붙잡고 있으며, Runtime은 drop될 때 shutdown을 호출한다.
+pub struct IOSelector {
+    wakers: Mutex<HashMap<RawFd, Waker>>, // fd에서 waker
+    queue: Mutex<VecDeque<IOOps>>,        // IO Queue
+    epfd: RawFd,                          // epoll의 fd
+    event: RawFd,                         // eventfd의 fd
+}
+
+impl IOSelector {
+    // epoll용 스레드를 생성하고 select 함수를 호출
+    pub fn new() -> Arc<Self> {
+        let s = IOSelector {
+            wakers: Mutex::new(HashMap::new()),
+            queue: Mutex::new(VecDeque::new()),
+            epfd: epoll_create1(EpollCreateFlags::empty()).unwrap(),
+            event: eventfd(0, EfdFlags::empty()).unwrap(),
+        };
+        let result = Arc::new(s);
+        let s = result.clone();
+        std::thread::spawn(move || s.select());
+        result
+    }
+
+    // fd를 epoll에 추가하고 Waker와 연관짓는다.
+    fn add_event(&self, flag: EpollFlags, fd: RawFd, waker: Waker, wakers: &mut HashMap<RawFd, Waker>) {
+        // 각 정의의 숏컷
+        let epoll_add = EpollOp::EpollCtlAdd;
+        let epoll_mod = EpollOp::EpollCtlMod;
+        let epoll_one = EpollFlags::EPOLLONESHOT;
+
+        // EPOLLONESHOT을 지정하여 일단 event가 발생하면 재설정하기 전까지 그 fd의 알림이 발생하지 않게 한다.
+        let mut ev = EpollEvent::new(flag | epoll_one, fd as u64);
+
+        // 감시 대상에 추가
+        if let Err(err) = epoll_ctl(self.epfd, epoll_add, fd, &mut ev) {
+            match err {
+                nix::Error::Sys(Errno::EEXIST) => {
+                    // 이미 추가되어 있는 경우에 재설정. EPOLLONESHOT으로 비활성화된 event를 다시 설정하기 위해 필요하다.
+                    epoll_ctl(self.epfd, epoll_mod, fd, &mut ev).unwrap();
+                }
+                _ => {
+                    panic!("epoll_ctl: {}", err);
+                }
+            }
+        }
+
+        assert!(!wakers.contains_key(&fd));
+        wakers.insert(fd, waker);
+    }
+
+    // fd를 epoll의 감시 대상에서 삭제하고 Waker와의 연관성도 삭제
+    fn rm_event(&self, fd: RawFd, wakers: &mut HashMap<RawFd, Waker>) {
+        let epoll_del = EpollOp::EpollCtlDel;
+        let mut ev = EpollEvent::new(EpollFlags::empty(), fd as u64);
+        epoll_ctl(self.epfd, epoll_del, fd, &mut ev).ok();
+        wakers.remove(&fd);
+    }
+
+    // 전용 스레드로 파일 디스크립터를 감시
+    fn select(&self) {
+        // 각 정의의 숏컷
+        let epoll_in = EpollFlags::EPOLLIN;
+        let epoll_add = EpollOp::EpollCtlAdd;
+
+        // eventfd를 epoll의 감시 대상에 추가
+        let mut ev = EpollEvent::new(epoll_in, self.event as u64);
+        epoll_ctl(self.epfd, epoll_add, self.event, &mut ev).unwrap();
+
+        let mut events = vec![EpollEvent::empty(); 1024];
+        // event 발생 감시
+        'select: while let Ok(nfds) = epoll_wait(self.epfd, &mut events, -1) {
+            crate::chaos::point(); // chaos feature에서 무작위 지연 주입(chaos.rs)
+            let mut t = self.wakers.lock().unwrap();
+            for ev in events.iter().take(nfds) {
+                if ev.data() == self.event as u64 {
+                    // eventfd의 경우 file descriptor와 Waker를 등록 및 삭제 요구 처리. 카운터를 읽어서 0으로
+                    // 되돌리지 않으면 epoll_wait이 계속 반환되어 바쁜 대기가 된다.
+                    read(self.event, &mut [0; 8]).unwrap();
+                    let mut q = self.queue.lock().unwrap();
+                    while let Some(op) = q.pop_front() {
+                        match op {
+                            IOOps::Add(flag, fd, waker) => self.add_event(flag, fd, waker, &mut t),
+                            IOOps::Remove(fd) => self.rm_event(fd, &mut t),
+                            IOOps::Shutdown => {
+                                // 남은 Waker를 버려서 Task -> Future -> IOSelector로 이어지는 순환 참조를 끊는다.
+                                q.clear();
+                                t.clear();
+                                break 'select;
+                            }
+                        }
+                    }
+                } else {
+                    // file descriptor의 경우에는 Waker의 wake_by_ref를 호출해 실행 큐에 추가
+                    let data = ev.data() as i32;
+                    let waker = t.remove(&data).unwrap();
+                    waker.wake_by_ref();
+                }
+            }
+        }
+    }
+
+    /// fd와 Waker를 등록한다. Future가 IO Queue에 요청을 넣기 위해 이용한다.
+    pub fn register(&self, flags: EpollFlags, fd: RawFd, waker: Waker) {
+        wait_on(WaitOn::Fd(fd));
+        let mut q = self.queue.lock().unwrap();
+        q.push_back(IOOps::Add(flags, fd, waker));
+        crate::chaos::point();
+        write_eventfd(self.event, 1);
+    }
+
+    /// fd와 Waker의 연관성을 삭제
+    pub fn unregister(&self, fd: RawFd) {
+        let mut q = self.queue.lock().unwrap();
+        q.push_back(IOOps::Remove(fd));
+        crate::chaos::point();
+        write_eventfd(self.event, 1);
+    }
+
+    /// 전용 스레드를 종료하고 등록된 Waker를 버린다. 이후 등록한 fd는 더 이상 감시하지 않는다.
+    /// 마지막 Arc가 drop되면 epoll과 eventfd의 fd를 닫는다.
+    pub fn shutdown(&self) {
+        let mut q = self.queue.lock().unwrap();
+        q.push_back(IOOps::Shutdown);
+        write_eventfd(self.event, 1);
+    }
+}
+
+impl Drop for IOSelector {
+    fn drop(&mut self) {
+        close(self.epfd).ok();
+        close(self.event).ok();
+    }
+}
+
+/// 비동기 TCP listen. non-blocking으로 설정한 TcpListener와 IOSelector를 가질 뿐이다.
+pub struct AsyncListener {
+    listener: TcpListener,
+    selector: Arc<IOSelector>,
+}
+
+impl AsyncListener {
+    pub fn listen(addr: &str, selector: Arc<IOSelector>) -> AsyncListener {
+        // listen 주소 지정
+        let listener = TcpListener::bind(addr).unwrap();
+        // non-blocking으로 설정
+        listener.set_nonblocking(true).unwrap();
+        AsyncListener { listener, selector }
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.listener.local_addr().unwrap()
+    }
+
+    // connection request를 받아들이는 Future를 반환. accept().await로 실제 request를 비동기로 받아들인다.
+    pub fn accept(&self) -> Accept<'_> {
+        Accept { listener: self }
+    }
+}
+
+impl Drop for AsyncListener {
+    fn drop(&mut self) {
+        self.selector.unregister(self.listener.as_raw_fd());
+    }
+}
+
+/// request를 non-blocking으로 받아들이는 Future. 받아들일 connection이 없으면 listen socket을 epoll에 등록하고 중단한다.
+pub struct Accept<'a> {
+    listener: &'a AsyncListener,
+}
+
+impl<'a> Future for Accept<'a> {
+    type Output = (
+        AsyncReader,          // 비동기 읽기 스트림
+        BufWriter<TcpStream>, // 쓰기 스트림
+        SocketAddr,           // 주소
+    );
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        ready!(poll_budget(cx));
+        // request를 non-blocking으로 받아들임
+        match self.listener.listener.accept() {
+            Ok((stream, addr)) => {
+                spend_budget();
+                // 요청을 받아들이면 읽기와 쓰기용 객체 스트림을 생성하고 객체 및 주소 반환
+                let stream0 = stream.try_clone().unwrap();
+                Poll::Ready((
+                    AsyncReader::new(stream0, self.listener.selector.clone()),
+                    BufWriter::new(stream),
+                    addr,
+                ))
+            }
+            Err(err) => {
+                // 받아들일 connection이 없는 경우 epoll의 감시 대상에 listen socket을 등록
+                if err.kind() == std::io::ErrorKind::WouldBlock {
+                    self.listener.selector.register(
+                        EpollFlags::EPOLLIN,
+                        self.listener.listener.as_raw_fd(),
+                        cx.waker().clone(),
+                    );
+                    Poll::Pending
+                } else {
+                    panic!("accept: {}", err);
+                }
+            }
+        }
+    }
+}
+
+/// 비동기 읽기 스트림. TcpStream을 non-blocking으로 설정해서 1행씩 읽는다.
+/// (쓰기에 대해서도 비동기화가 필요하지만 구현을 단순하게 하기 위해 생략)
+pub struct AsyncReader {
+    fd: RawFd,
+    reader: BufReader<TcpStream>,
+    selector: Arc<IOSelector>,
+}
+
+impl AsyncReader {
+    pub fn new(stream: TcpStream, selector: Arc<IOSelector>) -> AsyncReader {
+        // TcpStream을 non-blocking으로 설정
+        stream.set_nonblocking(true).unwrap();
+        AsyncReader {
+            fd: stream.as_raw_fd(),
+            reader: BufReader::new(stream),
+            selector,
+        }
+    }
+
+    // 1행을 읽기 위한 Future 반환
+    pub fn read_line(&mut self) -> ReadLine<'_> {
+        ReadLine { reader: self }
+    }
+}
+
+impl Drop for AsyncReader {
+    fn drop(&mut self) {
+        self.selector.unregister(self.fd);
+    }
+}
+
+/// 1행을 비동기로 읽는 Future. connection이 닫히면 None
+pub struct ReadLine<'a> {
+    reader: &'a mut AsyncReader,
+}
+
+impl<'a> Future for ReadLine<'a> {
+    type Output = Option<String>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        ready!(poll_budget(cx)); // 항상 읽을 수 있는 connection이 다른 Task를 굶기지 않도록 한다.
+        let mut line = String::new();
+        // 비동기 읽기
+        let r = self.reader.reader.read_line(&mut line);
+        if r.is_ok() {
+            spend_budget();
+        }
+        match r {
+            Ok(0) => Poll::Ready(None),       // connection 클로즈
+            Ok(_) => Poll::Ready(Some(line)), // 1행 읽기 성공
+            Err(err) => {
+                // 읽을 수 없으면 epoll에 등록
+                if err.kind() == std::io::ErrorKind::WouldBlock {
+                    self.reader.selector.register(EpollFlags::EPOLLIN, self.reader.fd, cx.waker().clone());
+                    Poll::Pending
+                } else {
+                    Poll::Ready(None)
+                }
+            }
+        }
+    }
+}
+
+/// duration이 지나면 완료하는 Future를 반환. timerfd를 IOSelector에 등록해서 기다린다.
+pub fn sleep(duration: Duration, selector: Arc<IOSelector>) -> Sleep {
+    Sleep {
+        deadline: Instant::now() + duration,
+        timer: None,
+        selector,
+    }
+}
+
+/// sleep이 반환하는 Future. 처음 Pending을 반환할 때 만료 시각에 읽을 수 있게 되는 timerfd를 만들어서 epoll에
+/// 등록하고, 같은 Task 안에서 await한다고 보고 다시 등록하지 않는다.
+pub struct Sleep {
+    deadline: Instant,
+    timer: Option<TimerFd>,
+    selector: Arc<IOSelector>,
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let now = Instant::now();
+        if now >= self.deadline {
+            return Poll::Ready(());
+        }
+        if self.timer.is_none() {
+            let timer = TimerFd::new(ClockId::CLOCK_MONOTONIC, TimerFlags::TFD_NONBLOCK).unwrap();
+            let left = TimeSpec::from(self.deadline - now);
+            timer.set(Expiration::OneShot(left), TimerSetTimeFlags::empty()).unwrap();
+            self.selector.register(EpollFlags::EPOLLIN, timer.as_raw_fd(), cx.waker().clone());
+            self.timer = Some(timer);
+        }
+        wait_on(WaitOn::Timer(self.deadline));
+        Poll::Pending
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        if let Some(timer) = &self.timer {
+            self.selector.unregister(timer.as_raw_fd());
+        }
+    }
+}
+
+// LocalExecutor의 실행 가능한 Task 번호. Waker는 다른 스레드로 보내질 수 있으므로 이 부분만 스레드 사이에서 공유한다.
+struct ReadyQueue {
+    ids: Mutex<Vec<usize>>,
+    thread: thread::Thread, // LocalExecutor를 실행하는 스레드
+}
+
+// LocalExecutor의 Task별 Waker. scheduled로 중복된 wake를 합친다. refs는 Task 밖에 있는 Waker의 수로, 0이면
+// 아무도 wake할 수 없으므로 Executor와 같이 Task를 버린다.
+struct LocalWaker {
+    id: usize,
+    scheduled: AtomicBool,
+    refs: AtomicUsize,
+    ready: Arc<ReadyQueue>,
+}
+
+impl LocalWaker {
+    fn wake_by_ref(&self) {
+        if !self.scheduled.swap(true, Ordering::AcqRel) {
+            self.push();
+        }
+    }
+
+    fn push(&self) {
+        self.ready.ids.lock().unwrap().push(self.id);
+        self.ready.thread.unpark();
+    }
+
+    // 아무도 wake할 수 없게 되었는지. poll 중이 아닐 때는 Task만 새 Waker를 만들 수 있으므로 한 번 true면 계속 true다.
+    fn abandoned(&self) -> bool {
+        self.refs.load(Ordering::Acquire) == 0 && !self.scheduled.load(Ordering::Acquire)
+    }
+
+    // poll에 넘기는 Waker. Task가 가진 참조를 빌려 쓰므로 refs를 세지 않는다.
+    fn waker(self: &Arc<Self>) -> ManuallyDrop<Waker> {
+        let raw = RawWaker::new(Arc::as_ptr(self) as *const (), &LOCAL_WAKER_VTABLE);
+        ManuallyDrop::new(unsafe { Waker::from_raw(raw) })
+    }
+}
+
+static LOCAL_WAKER_VTABLE: RawWakerVTable =
+    RawWakerVTable::new(clone_local_waker, wake_local, wake_local_by_ref, drop_local_waker);
+
+unsafe fn clone_local_waker(ptr: *const ()) -> RawWaker {
+    (*(ptr as *const LocalWaker)).refs.fetch_add(1, Ordering::Relaxed);
+    Arc::increment_strong_count(ptr as *const LocalWaker);
+    RawWaker::new(ptr, &LOCAL_WAKER_VTABLE)
+}
+
+unsafe fn wake_local(ptr: *const ()) {
+    wake_local_by_ref(ptr);
+    drop_local_waker(ptr);
+}
+
+unsafe fn wake_local_by_ref(ptr: *const ()) {
+    (*(ptr as *const LocalWaker)).wake_by_ref();
+}
+
+// 마지막 Waker가 버려지면 실행 루프가 Task를 버릴 수 있도록 Task 번호를 넣고 깨운다.
+unsafe fn drop_local_waker(ptr: *const ()) {
+    let waker = &*(ptr as *const LocalWaker);
+    if waker.refs.fetch_sub(1, Ordering::AcqRel) == 1 && !waker.scheduled.load(Ordering::Acquire) {
+        waker.push();
+    }
+    Arc::decrement_strong_count(ptr as *const LocalWaker);
+}
+
+struct LocalTask {
+    future: LocalBoxFuture<'static, ()>,
+    waker: Arc<LocalWaker>,
+}
+
+struct LocalInner {
+    tasks: RefCell<Vec<Option<LocalTask>>>, // Task 번호 -> Task. poll 중에는 꺼내 둔다.
+    free: RefCell<Vec<usize>>,              // 비어 있는 Task 번호
+    live: Cell<usize>,
+    ready: Arc<ReadyQueue>,
+}
+
+thread_local! {
+    // 현재 스레드에서 실행 중인 LocalExecutor. spawn_local이 이용한다.
+    static LOCAL: RefCell<Option<rc::Weak<LocalInner>>> = const { RefCell::new(None) };
+}
+
+/// 단일 스레드에서 Send가 아닌 future(Rc, RefCell 등을 가지는)를 실행하는 Executor.
+/// Task는 Mutex 없이 RefCell에 두고 그 스레드에서만 poll한다. Waker는 Task 번호를 공유 큐에 넣을 뿐이며,
+/// 실행 루프는 큐를 통째로 꺼내서 한 번의 락으로 여러 Task를 실행한다. Executor와 같이 아무도 wake할 수 없게 된
+/// Task는 버려지고 JoinHandle은 Cancelled가 된다.
+pub struct LocalExecutor {
+    inner: Rc<LocalInner>,
+}
+
+impl Default for LocalExecutor {
+    fn default() -> Self {
+        LocalExecutor::new()
+    }
+}
+
+impl LocalExecutor {
+    pub fn new() -> Self {
+        LocalExecutor {
+            inner: Rc::new(LocalInner {
+                tasks: RefCell::new(Vec::new()),
+                free: RefCell::new(Vec::new()),
+                live: Cell::new(0),
+                ready: Arc::new(ReadyQueue {
+                    ids: Mutex::new(Vec::new()),
+                    thread: thread::current(),
+                }),
+            }),
+        }
+    }
+
+    /// future를 이 Executor의 Task로 실행한다. Task 안에서는 runtime::spawn_local을 이용한다.
+    pub fn spawn_local<F: Future + 'static>(&self, future: F) -> JoinHandle<F::Output> {
+        self.inner.spawn(future)
+    }
+
+    pub fn live_tasks(&self) -> usize {
+        self.inner.live.get()
+    }
+
+    /// 모든 Task가 완료할 때까지 실행한다.
+    pub fn run(&self) {
+        self.inner.enter(|| {
+            while self.inner.live.get() > 0 {
+                self.inner.tick();
+            }
+        })
+    }
+
+    /// future를 Task로 실행하고 그 결과가 나올 때까지만 실행한다.
+    pub fn block_on<F: Future + 'static>(&self, future: F) -> F::Output {
+        let handle = self.spawn_local(future);
+        self.inner.enter(|| loop {
+            if let Some(r) = handle.try_take() {
+                return unwrap_block_on(r, false); // LocalExecutor에는 shutdown이 없다.
+            }
+            self.inner.tick();
+        })
+    }
+}
+
+impl LocalInner {
+    fn spawn<F: Future + 'static>(&self, future: F) -> JoinHandle<F::Output> {
+        let (future, handle) = with_join_handle(future);
+        let mut tasks = self.tasks.borrow_mut();
+        let id = self.free.borrow_mut().pop().unwrap_or_else(|| {
+            tasks.push(None);
+            tasks.len() - 1
+        });
+        let waker = Arc::new(LocalWaker {
+            id,
+            scheduled: AtomicBool::new(false),
+            refs: AtomicUsize::new(0),
+            ready: self.ready.clone(),
+        });
+        tasks[id] = Some(LocalTask {
+            future: future.boxed_local(),
+            waker: waker.clone(),
+        });
+        self.live.set(self.live.get() + 1);
+        drop(tasks);
+        waker.wake_by_ref();
+        handle
+    }
+
+    // 현재 스레드의 LocalExecutor로 설정하고 f를 실행
+    fn enter<R>(self: &Rc<Self>, f: impl FnOnce() -> R) -> R {
+        struct Reset(Option<rc::Weak<LocalInner>>);
+        impl Drop for Reset {
+            fn drop(&mut self) {
+                LOCAL.with(|l| *l.borrow_mut() = self.0.take());
+            }
+        }
+        let _reset = Reset(LOCAL.with(|l| l.borrow_mut().replace(Rc::downgrade(self))));
+        f()
+    }
+
+    // 실행 가능한 Task를 한 차례 실행. 없으면 wake될 때까지 잠든다.
+    fn tick(&self) {
+        let ids = std::mem::take(&mut *self.ready.ids.lock().unwrap());
+        if ids.is_empty() {
+            thread::park();
+            return;
+        }
+        for id in ids {
+            // 이미 완료한 Task의 번호일 수 있다.
+            let Some(mut task) = self.tasks.borrow_mut().get_mut(id).and_then(|t| t.take()) else {
+                continue;
+            };
+            // 마지막 Waker가 버려져서 들어온 번호면 poll하지 않고 버린다.
+            if !task.waker.abandoned() {
+                task.waker.scheduled.store(false, Ordering::Release);
+                let waker = task.waker.waker();
+                let mut ctx = Context::from_waker(&waker);
+                if task.future.as_mut().poll(&mut ctx).is_pending() && !task.waker.abandoned() {
+                    self.tasks.borrow_mut()[id] = Some(task);
+                    continue;
+                }
+            }
+            // 완료했거나 버려진 Task. 버려진 Task의 JoinHandle은 future와 함께 Completer가 버려지므로 Cancelled
+            drop(task);
+            self.free.borrow_mut().push(id);
+            self.live.set(self.live.get() - 1);
+        }
+    }
+}
+
+/// 현재 스레드에서 실행 중인 LocalExecutor에 Task를 추가한다. LocalExecutor의 Task 안에서만 호출할 수 있다.
+pub fn spawn_local<F: Future + 'static>(future: F) -> JoinHandle<F::Output> {
+    let inner = LOCAL
+        .with(|l| l.borrow().as_ref().and_then(|w| w.upgrade()))
+        .expect("spawn_local: no LocalExecutor is running on this thread");
+    inner.spawn(future)
+}
+
+// Runtime이 이용하는 Executor
+enum Flavor {
+    CurrentThread(Executor),
+    MultiThread(MultiThreadExecutor),
+}
+
+/// Executor와 IOSelector를 묶은 런타임
+pub struct Runtime {
+    executor: Flavor,
+    selector: Arc<IOSelector>,
+}
+
+impl Default for Runtime {
+    fn default() -> Self {
+        Runtime::new()
+    }
+}
+
+impl Runtime {
+    // block_on, run을 호출한 스레드에서 Task를 실행하는 런타임
+    pub fn new() -> Self {
+        Runtime {
+            executor: Flavor::CurrentThread(Executor::new()),
+            selector: IOSelector::new(),
+        }
+    }
+
+    // workers개의 Worker 스레드로 Task를 실행하는 런타임
+    pub fn multi_thread(workers: usize) -> Self {
+        Runtime {
+            executor: Flavor::MultiThread(MultiThreadExecutor::new(workers)),
+            selector: IOSelector::new(),
+        }
+    }
+
+    pub fn spawner(&self) -> Spawner {
+        match &self.executor {
+            Flavor::CurrentThread(e) => e.get_spawner(),
+            Flavor::MultiThread(e) => e.get_spawner(),
+        }
+    }
+
+    pub fn selector(&self) -> Arc<IOSelector> {
+        self.selector.clone()
+    }
+
+    // future의 결과가 나올 때까지 실행
+    #[track_caller]
+    pub fn block_on<F>(&self, future: F) -> F::Output
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        match &self.executor {
+            Flavor::CurrentThread(e) => e.block_on(future),
+            Flavor::MultiThread(e) => e.block_on(future),
+        }
+    }
+
+    // 모든 Task가 완료하거나 shutdown될 때까지 실행
+    pub fn run(&self) {
+        match &self.executor {
+            Flavor::CurrentThread(e) => e.run(),
+            Flavor::MultiThread(e) => e.run(),
+        }
+    }
+
+    pub fn shutdown(&self) {
+        match &self.executor {
+            Flavor::CurrentThread(e) => e.shutdown(),
+            Flavor::MultiThread(e) => e.shutdown(),
+        }
+    }
+
+    pub fn set_budget(&self, budget: u32) {
+        match &self.executor {
+            Flavor::CurrentThread(e) => e.set_budget(budget),
+            Flavor::MultiThread(e) => e.set_budget(budget),
+        }
+    }
+
+    pub fn set_slow_poll(&self, threshold: Option<Duration>, strict: bool) {
+        match &self.executor {
+            Flavor::CurrentThread(e) => e.set_slow_poll(threshold, strict),
+            Flavor::MultiThread(e) => e.set_slow_poll(threshold, strict),
+        }
+    }
+
+    pub fn dump(&self) -> String {
+        match &self.executor {
+            Flavor::CurrentThread(e) => e.dump(),
+            Flavor::MultiThread(e) => e.dump(),
+        }
+    }
+
+    pub fn set_blocking_limits(&self, max_threads: usize, idle_timeout: Duration) {
+        match &self.executor {
+            Flavor::CurrentThread(e) => e.set_blocking_limits(max_threads, idle_timeout),
+            Flavor::MultiThread(e) => e.set_blocking_limits(max_threads, idle_timeout),
+        }
+    }
+
+    pub fn blocking_metrics(&self) -> BlockingMetrics {
+        match &self.executor {
+            Flavor::CurrentThread(e) => e.blocking_metrics(),
+            Flavor::MultiThread(e) => e.blocking_metrics(),
+        }
+    }
+}
+
+// Runtime이 drop되면 IOSelector의 전용 스레드도 종료한다. 종료하지 않으면 스레드가 가진 Arc 때문에 IOSelector와
+// epoll, eventfd의 fd가 해제되지 않는다.
+impl Drop for Runtime {
+    fn drop(&mut self) {
+        self.selector.shutdown();
+    }
+}
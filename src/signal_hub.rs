@@ -0,0 +1,347 @@
+// 4.6 시그널 수신 전용 스레드 재사용하기
+// func_167p에서는 시그널 수신 전용 스레드를 만들고 받은 시그널을 출력만 했다. 실제 데몬에서는 SIGHUP을 받으면 설정을
+// 다시 읽고, SIGTERM/SIGINT를 받으면 처리 중인 작업을 마무리하고 종료하는 식으로 여러 컴포넌트가 각자 다른 시그널에
+// 반응해야 한다. 그래서 시그널 수신 스레드는 하나만 두고, 받은 시그널을 구독(subscribe)한 컴포넌트에게 전달하는
+// SignalHub를 구현해보자.
+//
+//  [커널] --시그널--> [signal_hook 수신 스레드] --+--> Subscription (SIGHUP)          : 설정 재로드
+//                                              +--> Subscription (SIGTERM, SIGINT) : 종료 처리
+//                                              +--> Subscription (SIGUSR1)         : 진단 정보 출력
+//
+// 시그널 핸들러 안에서 락을 잡지 않는다는 원칙은 그대로이며, 락은 signal_hook이 동기적으로 시그널을 넘겨준 뒤
+// 일반 스레드(수신 스레드) 위에서만 잡는다.
+//
+// signal_hook은 등록을 해제해도 자신의 시그널 핸들러를 남겨 두므로 SIGTERM, SIGINT 같은 시그널이 기본 동작(종료) 대신
+// 계속 무시된다. 그래서 시그널마다 살아 있는 hub의 수를 세고, 마지막 hub가 멈추면 처음 hub가 등록하기 전의 처리 방법이
+// SIG_DFL이었던 시그널은 SIG_DFL로 되돌린다. 떼어 둔 signal_hook의 핸들러는 다음 hub가 등록할 때 다시 설치한다.
+// hub가 살아 있는 동안 signal_hook으로 같은 시그널을 직접 등록한 코드가 있으면, 그 등록은 hub가 모두 멈춘 뒤에는
+// 시그널을 받지 못한다.
+//
+// 구독자마다 우편함(mailbox)을 하나 두고 Mutex와 Condvar로 대기한다(3.8.5의 channel과 같은 방식). 같은 시그널이
+// 구독자가 꺼내기 전에 여러 번 도착하면 큐에 쌓지 않고 횟수만 더한다(coalescing). 시그널은 원래 "몇 번 왔는가"보다
+// "왔는가"가 중요하고, 커널도 같은 종류의 대기 중 시그널은 하나로 합치기 때문이다.
+
+use signal_hook::iterator::{Handle, Signals};
+use nix::libc;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::io;
+use std::os::raw::c_int;
+use std::ptr;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+pub use signal_hook::{SIGHUP, SIGINT, SIGTERM, SIGUSR1};
+
+/// 구독자가 받는 알림. count는 이전 알림 이후 합쳐진(coalesced) 수신 횟수.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Notification {
+    pub signal: c_int,
+    pub count: u64,
+}
+
+// 구독자별 우편함. pending에는 아직 꺼내지 않은 시그널별 수신 횟수가 도착 순서대로 들어 있다.
+struct Mailbox {
+    pending: Vec<(c_int, u64)>,
+    closed: bool,
+}
+
+struct Subscriber {
+    signals: Vec<c_int>,
+    mailbox: Mutex<Mailbox>,
+    cond: Condvar,
+}
+
+impl Subscriber {
+    fn deliver(&self, signal: c_int) {
+        let mut mb = self.mailbox.lock().unwrap();
+        match mb.pending.iter_mut().find(|(s, _)| *s == signal) {
+            Some((_, n)) => *n += 1, // 아직 꺼내지 않았으면 횟수만 더함
+            None => mb.pending.push((signal, 1)),
+        }
+        self.cond.notify_one();
+    }
+
+    fn close(&self) {
+        self.mailbox.lock().unwrap().closed = true;
+        self.cond.notify_all();
+    }
+}
+
+/// SignalHub::subscribe가 반환하는 수신단
+pub struct Subscription {
+    sub: Arc<Subscriber>,
+}
+
+impl Subscription {
+    // 알림이 올 때까지 대기. hub가 shutdown되어 더 이상 알림이 없으면 None.
+    pub fn recv(&self) -> Option<Notification> {
+        let mb = self.sub.mailbox.lock().unwrap();
+        let mut mb = self
+            .sub
+            .cond
+            .wait_while(mb, |mb| mb.pending.is_empty() && !mb.closed)
+            .unwrap();
+        pop(&mut mb)
+    }
+
+    // 최대 timeout만큼 대기
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<Notification> {
+        let deadline = Instant::now() + timeout;
+        let mut mb = self.sub.mailbox.lock().unwrap();
+        while mb.pending.is_empty() && !mb.closed {
+            let now = Instant::now();
+            if now >= deadline {
+                return None;
+            }
+            mb = self.sub.cond.wait_timeout(mb, deadline - now).unwrap().0;
+        }
+        pop(&mut mb)
+    }
+
+    // 대기하지 않고 확인
+    pub fn try_recv(&self) -> Option<Notification> {
+        pop(&mut self.sub.mailbox.lock().unwrap())
+    }
+
+    pub fn signals(&self) -> &[c_int] {
+        &self.sub.signals
+    }
+}
+
+fn pop(mb: &mut Mailbox) -> Option<Notification> {
+    if mb.pending.is_empty() {
+        return None;
+    }
+    let (signal, count) = mb.pending.remove(0);
+    Some(Notification { signal, count })
+}
+
+struct Shared {
+    subscribers: Mutex<Vec<Arc<Subscriber>>>,
+    counts: Mutex<HashMap<c_int, u64>>, // 시그널별 누적 수신 횟수
+}
+
+// 프로세스 전체에서의 시그널별 처리 방법
+struct Disposition {
+    hubs: usize,                // 이 시그널을 등록한 살아 있는 hub 수
+    default: bool,              // 처음 hub가 등록하기 전의 처리 방법이 SIG_DFL이었는가
+    saved: Option<SavedAction>, // SIG_DFL로 되돌리면서 떼어 둔 signal_hook의 핸들러
+}
+
+struct SavedAction(libc::sigaction);
+
+// 핸들러의 주소와 플래그뿐이므로 다른 스레드로 보낼 수 있다.
+unsafe impl Send for SavedAction {}
+
+static DISPOSITIONS: Mutex<BTreeMap<c_int, Disposition>> = Mutex::new(BTreeMap::new());
+
+// libc::sigaction의 반환값 확인
+fn check(ret: c_int) -> io::Result<()> {
+    if ret == -1 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+// hub가 signal을 signal_hook에 등록하기 전에 호출
+fn acquire(signal: c_int) -> io::Result<()> {
+    let mut d = DISPOSITIONS.lock().unwrap();
+    let e = d.entry(signal).or_insert(Disposition {
+        hubs: 0,
+        default: false,
+        saved: None,
+    });
+    if e.hubs == 0 {
+        match e.saved.take() {
+            // signal_hook은 이미 핸들러를 설치했다고 알고 있으므로 직접 되돌린다.
+            Some(act) => {
+                if let Err(err) = check(unsafe { libc::sigaction(signal, &act.0, ptr::null_mut()) }) {
+                    e.saved = Some(act);
+                    return Err(err);
+                }
+            }
+            None => {
+                let mut cur: libc::sigaction = unsafe { std::mem::zeroed() };
+                check(unsafe { libc::sigaction(signal, ptr::null(), &mut cur) })?;
+                e.default = cur.sa_sigaction == libc::SIG_DFL;
+            }
+        }
+    }
+    e.hubs += 1;
+    Ok(())
+}
+
+// hub가 signal의 등록을 해제한 뒤에 호출. 실패하면 signal_hook의 핸들러가 남아 시그널이 계속 무시된다.
+fn release(signal: c_int) -> io::Result<()> {
+    let mut d = DISPOSITIONS.lock().unwrap();
+    let e = d.get_mut(&signal).unwrap();
+    e.hubs -= 1;
+    if e.hubs == 0 && e.default {
+        let mut dfl: libc::sigaction = unsafe { std::mem::zeroed() };
+        dfl.sa_sigaction = libc::SIG_DFL;
+        let mut old: libc::sigaction = unsafe { std::mem::zeroed() };
+        check(unsafe { libc::sigaction(signal, &dfl, &mut old) })?;
+        e.saved = Some(SavedAction(old));
+    }
+    Ok(())
+}
+
+// 정리 중(drop, 등록 실패 후의 되돌리기)에는 실패를 돌려줄 곳이 없으므로 출력만 한다.
+fn release_or_log(signal: c_int) {
+    if let Err(e) = release(signal) {
+        eprintln!("signal hub: failed to restore the disposition of signal {}: {}", signal, e);
+    }
+}
+
+/// 시그널 수신 전용 스레드와 구독자 목록. shutdown하거나 drop하면 수신 스레드를 멈추고, 다른 hub가 없으면 시그널의
+/// 처리 방법을 hub가 등록하기 전으로 되돌린다(SIGTERM, SIGINT는 다시 프로세스를 종료시킨다).
+///
+/// 되돌릴 때 signal_hook의 핸들러를 떼어 내므로, 같은 시그널을 signal_hook으로 직접 등록한 다른 코드(다른 크레이트
+/// 포함)와 함께 쓰면 마지막 hub가 멈춘 뒤로는 그 등록도 시그널을 받지 못한다. hub를 쓰는 시그널은 hub로만 받아야 한다.
+pub struct SignalHub {
+    shared: Arc<Shared>,
+    handle: Handle, // 수신 스레드의 forever 루프를 멈추기 위한 핸들
+    thread: Option<JoinHandle<()>>,
+    registered: Mutex<Vec<c_int>>, // 이 hub가 등록한 시그널
+}
+
+impl SignalHub {
+    // signals에 지정한 시그널을 수신하는 전용 스레드를 생성한다.
+    pub fn new(signals: &[c_int]) -> Result<Self, Box<dyn Error>> {
+        let mut registered = signals.to_vec();
+        registered.sort_unstable();
+        registered.dedup();
+        for (i, s) in registered.iter().enumerate() {
+            if let Err(e) = acquire(*s) {
+                registered[..i].iter().for_each(|s| release_or_log(*s));
+                return Err(e.into());
+            }
+        }
+        let sigs = match Signals::new(signals) {
+            Ok(sigs) => sigs,
+            Err(e) => {
+                registered.iter().for_each(|s| release_or_log(*s));
+                return Err(e.into());
+            }
+        };
+        let handle = sigs.handle();
+        let shared = Arc::new(Shared {
+            subscribers: Mutex::new(Vec::new()),
+            counts: Mutex::new(HashMap::new()),
+        });
+
+        let shared0 = shared.clone();
+        let thread = thread::spawn(move || {
+            // handle.close()가 호출되면 forever 루프가 끝난다.
+            for sig in sigs.forever() {
+                *shared0.counts.lock().unwrap().entry(sig).or_insert(0) += 1;
+                for s in shared0.subscribers.lock().unwrap().iter() {
+                    if s.signals.contains(&sig) {
+                        s.deliver(sig);
+                    }
+                }
+            }
+        });
+
+        Ok(SignalHub {
+            shared,
+            handle,
+            thread: Some(thread),
+            registered: Mutex::new(registered),
+        })
+    }
+
+    // 관심 있는 시그널을 지정해 구독. hub에 등록되지 않은 시그널은 추가로 등록한다.
+    pub fn subscribe(&self, signals: &[c_int]) -> Result<Subscription, Box<dyn Error>> {
+        let mut registered = self.registered.lock().unwrap();
+        for sig in signals {
+            if !registered.contains(sig) {
+                acquire(*sig)?;
+                if let Err(e) = self.handle.add_signal(*sig) {
+                    release_or_log(*sig);
+                    return Err(e.into());
+                }
+                registered.push(*sig);
+            }
+        }
+        drop(registered);
+        let sub = Arc::new(Subscriber {
+            signals: signals.to_vec(),
+            mailbox: Mutex::new(Mailbox {
+                pending: Vec::new(),
+                closed: self.handle.is_closed(),
+            }),
+            cond: Condvar::new(),
+        });
+        self.shared.subscribers.lock().unwrap().push(sub.clone());
+        Ok(Subscription { sub })
+    }
+
+    // signal의 누적 수신 횟수
+    pub fn count(&self, signal: c_int) -> u64 {
+        *self.shared.counts.lock().unwrap().get(&signal).unwrap_or(&0)
+    }
+
+    // 수신 스레드를 정지하고 모든 구독자의 recv를 깨운다(None 반환). 다른 hub가 등록하지 않은 시그널은 처리 방법을
+    // 등록 전으로 되돌린다.
+    pub fn shutdown(mut self) {
+        self.stop();
+    }
+
+    fn stop(&mut self) {
+        self.handle.close();
+        if let Some(t) = self.thread.take() {
+            t.join().unwrap(); // 수신 스레드가 가진 Signals가 drop되어 signal_hook의 등록이 해제된다.
+        }
+        for sig in self.registered.lock().unwrap().drain(..) {
+            release_or_log(sig);
+        }
+        for s in self.shared.subscribers.lock().unwrap().iter() {
+            s.close();
+        }
+    }
+}
+
+impl Drop for SignalHub {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// on_signal이 반환하는 핸들. drop하면 콜백을 호출하던 스레드가 종료되고, 같은 시그널을 등록한 다른 SignalHub가 없으면
+/// 시그널의 처리 방법도 설치 전(SIGUSR1이라면 보통은 프로세스 종료)으로 돌아간다. drop한 뒤에 보낸 시그널에는 콜백이
+/// 호출되지 않는다.
+pub struct DumpHandle {
+    hub: Option<SignalHub>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for DumpHandle {
+    fn drop(&mut self) {
+        if let Some(hub) = self.hub.take() {
+            hub.shutdown(); // 구독자의 recv가 None을 반환해 콜백 스레드의 루프가 끝남
+        }
+        if let Some(t) = self.thread.take() {
+            t.join().unwrap();
+        }
+    }
+}
+
+/// signal을 받을 때마다 전용 스레드에서 f를 호출한다. 진단 정보 덤프(thread_registry, runtime의
+/// install_sigusr1_dump)처럼 시그널 하나에 동작 하나만 붙이는 경우에 쓴다.
+pub fn on_signal(signal: c_int, mut f: impl FnMut() + Send + 'static) -> Result<DumpHandle, Box<dyn Error>> {
+    let hub = SignalHub::new(&[signal])?;
+    let sub = hub.subscribe(&[signal])?;
+    let thread = thread::spawn(move || {
+        while sub.recv().is_some() {
+            f();
+        }
+    });
+    Ok(DumpHandle {
+        hub: Some(hub),
+        thread: Some(thread),
+    })
+}
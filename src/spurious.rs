@@ -0,0 +1,164 @@
+// 4.5 의사 각성(spurious wakeup) 주입
+// 3.8.2의 child, 3.8.5의 Semaphore와 channel은 wait 뒤에 반드시 조건을 다시 확인(wait_while 또는 while 루프)해서
+// 의사 각성에 대비하고 있다. 하지만 현재 리눅스에서는 의사 각성이 거의 일어나지 않으므로 조건 확인을 잊은 코드도
+// 테스트를 통과해버린다. 그래서 테스트 빌드에서만 std::sync::Condvar 대신 이용하는, 일정 확률로 알림 없이 wait에서
+// 돌아오는 조건 변수를 구현해보자.
+//
+// - 확률과 seed를 지정하면 wait 호출 순서에 대해 의사 각성 여부가 결정적(deterministic)으로 정해진다.
+// - 확률을 1.0으로 하면 모든 wait이 즉시 돌아오므로 조건 루프가 없는 코드는 반드시 실패한다.
+// - 기본 설정은 환경 변수 SPURIOUS_WAKEUP="확률:seed"(예: 0.3:42)로 지정하며, 지정하지 않으면 0(주입 안 함)이다.
+//   $ SPURIOUS_WAKEUP=0.5:7 cargo test
+//
+// 의사 각성은 timeout 0인 wait_timeout으로 만든다. 락을 한 번 해제했다가 다시 획득하고 돌아오므로 실제 의사 각성과
+// 똑같이 다른 스레드가 그 사이에 끼어들 수 있다.
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Condvar, LockResult, Mutex, MutexGuard, WaitTimeoutResult};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Config {
+    pub probability: f64, // wait 1회당 의사 각성 확률
+    pub seed: u64,
+}
+
+// set_default로 바꾸기 전까지는 환경 변수의 값을 이용
+static DEFAULT: Mutex<Option<Config>> = Mutex::new(None);
+
+fn config_from_env() -> Config {
+    let mut config = Config {
+        probability: 0.0,
+        seed: 0,
+    };
+    if let Ok(v) = std::env::var("SPURIOUS_WAKEUP") {
+        let mut it = v.split(':');
+        if let Some(p) = it.next().and_then(|p| p.parse().ok()) {
+            config.probability = p;
+        }
+        if let Some(s) = it.next().and_then(|s| s.parse().ok()) {
+            config.seed = s;
+        }
+    }
+    config
+}
+
+pub fn default_config() -> Config {
+    *DEFAULT.lock().unwrap().get_or_insert_with(config_from_env)
+}
+
+// 이후 생성되는 SpuriousCondvar::new()의 설정을 변경. 이미 생성된 조건 변수에는 영향이 없다.
+pub fn set_default(config: Config) {
+    *DEFAULT.lock().unwrap() = Some(config);
+}
+
+/// 일정 확률로 의사 각성을 일으키는 조건 변수. std::sync::Condvar와 같은 인터페이스를 가진다.
+pub struct SpuriousCondvar {
+    cond: Condvar,
+    probability: f64,
+    rng: Mutex<StdRng>,
+    spurious: AtomicU64, // 주입한 의사 각성 횟수
+}
+
+impl Default for SpuriousCondvar {
+    fn default() -> Self {
+        SpuriousCondvar::new()
+    }
+}
+
+#[allow(dead_code)] // std::sync::Condvar를 대신하므로 크레이트에서 쓰지 않는 메서드도 갖춘다
+impl SpuriousCondvar {
+    pub fn new() -> Self {
+        SpuriousCondvar::with_config(default_config())
+    }
+
+    pub fn with_config(config: Config) -> Self {
+        SpuriousCondvar {
+            cond: Condvar::new(),
+            probability: config.probability,
+            rng: Mutex::new(StdRng::seed_from_u64(config.seed)),
+            spurious: AtomicU64::new(0),
+        }
+    }
+
+    pub fn spurious_count(&self) -> u64 {
+        self.spurious.load(Ordering::Relaxed)
+    }
+
+    // 이번 wait에서 의사 각성을 일으킬지 결정
+    fn inject(&self) -> bool {
+        if self.probability <= 0.0 {
+            return false;
+        }
+        let hit = self.rng.lock().unwrap().gen_bool(self.probability.min(1.0));
+        if hit {
+            self.spurious.fetch_add(1, Ordering::Relaxed);
+        }
+        hit
+    }
+
+    pub fn wait<'a, T>(&self, guard: MutexGuard<'a, T>) -> LockResult<MutexGuard<'a, T>> {
+        if self.inject() {
+            // 락을 해제했다가 다시 획득하고 알림 없이 돌아옴
+            return match self.cond.wait_timeout(guard, Duration::ZERO) {
+                Ok((g, _)) => Ok(g),
+                Err(e) => Err(std::sync::PoisonError::new(e.into_inner().0)),
+            };
+        }
+        self.cond.wait(guard)
+    }
+
+    // 술어가 true인 동안 대기. 내부에서 wait을 반복하므로 의사 각성이 일어나도 올바르게 작동한다.
+    pub fn wait_while<'a, T, F>(
+        &self,
+        mut guard: MutexGuard<'a, T>,
+        mut condition: F,
+    ) -> LockResult<MutexGuard<'a, T>>
+    where
+        F: FnMut(&mut T) -> bool,
+    {
+        while condition(&mut *guard) {
+            guard = self.wait(guard)?;
+        }
+        Ok(guard)
+    }
+
+    pub fn wait_timeout<'a, T>(
+        &self,
+        guard: MutexGuard<'a, T>,
+        dur: Duration,
+    ) -> LockResult<(MutexGuard<'a, T>, WaitTimeoutResult)> {
+        let dur = if self.inject() { Duration::ZERO } else { dur };
+        self.cond.wait_timeout(guard, dur)
+    }
+
+    pub fn wait_timeout_while<'a, T, F>(
+        &self,
+        mut guard: MutexGuard<'a, T>,
+        dur: Duration,
+        mut condition: F,
+    ) -> LockResult<(MutexGuard<'a, T>, WaitTimeoutResult)>
+    where
+        F: FnMut(&mut T) -> bool,
+    {
+        let deadline = Instant::now() + dur;
+        loop {
+            let now = Instant::now();
+            if now >= deadline || !condition(&mut *guard) {
+                break;
+            }
+            guard = self.wait_timeout(guard, deadline - now)?.0;
+        }
+        // WaitTimeoutResult는 직접 생성할 수 없으므로 마지막 판정은 std에 맡긴다(timeout 0이라 대기하지 않음).
+        self.cond.wait_timeout_while(guard, Duration::ZERO, condition)
+    }
+
+    pub fn notify_one(&self) {
+        self.cond.notify_one();
+    }
+
+    pub fn notify_all(&self) {
+        self.cond.notify_all();
+    }
+}
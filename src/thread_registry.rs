@@ -0,0 +1,222 @@
+// 스레드 레지스트리와 SIGUSR1 진단 덤프
+// 4.1의 데드락 예제(func_144p, func_147p_1 등)가 멈췄을 때 어떤 스레드가 어떤 락을 잡고 무엇을 기다리는지 알려면
+// 지금은 디버거를 붙이는 수밖에 없다. 그래서 스레드를 생성할 때 이름, 시작 시각, 현재 상태, 잡고 있는 락을 기록해두고
+// SIGUSR1을 받으면 전체 스레드의 스냅샷을 출력(또는 파일에 기록)하는 레지스트리를 구현해보자.
+//
+// 스레드 상태는 1.1절의 프로세스 상태를 그대로 따른다. 실행 전 상태는 spawn 직후 아주 짧으므로 기록하지 않는다.
+// - Running: 실행 상태
+// - Waiting(무엇을): 대기 상태. 락, 채널, 조건 변수 등 무엇을 기다리는지 함께 기록
+// - 종료 상태: 종료한 스레드(패닉 포함)는 레지스트리에서 제거된다.
+//
+// 락 획득/해제 기록은 TrackedMutex로 감싼 락에서만 이뤄진다. 시그널 수신은 4.6절처럼 전용 스레드(SignalHub)에서
+// 동기적으로 하므로 덤프 중에 락을 잡아도 데드락이 생기지 않는다.
+
+pub use crate::signal_hub::DumpHandle;
+use crate::signal_hub::{on_signal, SIGUSR1};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::Write as _;
+use std::ops::{Deref, DerefMut};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ThreadState {
+    Running,
+    Waiting(String), // 무엇을 기다리고 있는지
+}
+
+struct Entry {
+    id: u64,
+    name: String,
+    started: Instant,
+    state: Mutex<ThreadState>,
+    held: Mutex<Vec<String>>, // 잡고 있는 락 이름(획득 순서)
+}
+
+// 등록된 스레드 목록. id 순으로 출력하기 위해 BTreeMap 사용.
+static THREADS: Mutex<BTreeMap<u64, Arc<Entry>>> = Mutex::new(BTreeMap::new());
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+thread_local! {
+    // 현재 스레드의 엔트리. registry::spawn으로 생성되지 않은 스레드는 None
+    static CURRENT: RefCell<Option<Arc<Entry>>> = const { RefCell::new(None) };
+}
+
+// 스레드 종료 시(패닉 포함) 레지스트리에서 제거하기 위한 가드
+struct Unregister(u64);
+
+impl Drop for Unregister {
+    fn drop(&mut self) {
+        THREADS.lock().unwrap().remove(&self.0);
+    }
+}
+
+/// thread::spawn 대신 이용하는 생성 함수. 스레드 이름도 지정된다.
+pub fn spawn<F, T>(name: &str, f: F) -> JoinHandle<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let entry = Arc::new(Entry {
+        id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+        name: name.to_string(),
+        started: Instant::now(),
+        state: Mutex::new(ThreadState::Running),
+        held: Mutex::new(Vec::new()),
+    });
+    THREADS.lock().unwrap().insert(entry.id, entry.clone());
+
+    thread::Builder::new()
+        .name(name.to_string())
+        .spawn(move || {
+            let _unregister = Unregister(entry.id);
+            CURRENT.with(|c| *c.borrow_mut() = Some(entry));
+            f()
+        })
+        .unwrap()
+}
+
+fn with_current(f: impl FnOnce(&Entry)) {
+    CURRENT.with(|c| {
+        if let Some(e) = c.borrow().as_ref() {
+            f(e);
+        }
+    });
+}
+
+// 현재 스레드의 상태를 기록. 등록되지 않은 스레드에서 호출하면 아무것도 하지 않는다.
+pub fn set_state(state: ThreadState) {
+    with_current(|e| *e.state.lock().unwrap() = state);
+}
+
+// what을 기다리는 동안 f를 실행하고 끝나면 Running으로 되돌림. 채널 recv 등을 감쌀 때 이용.
+pub fn waiting<R>(what: &str, f: impl FnOnce() -> R) -> R {
+    set_state(ThreadState::Waiting(what.to_string()));
+    let r = f();
+    set_state(ThreadState::Running);
+    r
+}
+
+/// 획득/해제를 레지스트리에 기록하는 Mutex
+pub struct TrackedMutex<T> {
+    name: String,
+    mutex: Mutex<T>,
+}
+
+pub struct TrackedGuard<'a, T> {
+    name: &'a str,
+    guard: MutexGuard<'a, T>,
+}
+
+impl<T> TrackedMutex<T> {
+    pub fn new(name: &str, v: T) -> Self {
+        TrackedMutex {
+            name: name.to_string(),
+            mutex: Mutex::new(v),
+        }
+    }
+
+    pub fn lock(&self) -> TrackedGuard<'_, T> {
+        let guard = waiting(&self.name, || self.mutex.lock().unwrap());
+        with_current(|e| e.held.lock().unwrap().push(self.name.clone()));
+        TrackedGuard {
+            name: &self.name,
+            guard,
+        }
+    }
+}
+
+impl<'a, T> Drop for TrackedGuard<'a, T> {
+    fn drop(&mut self) {
+        with_current(|e| {
+            let mut held = e.held.lock().unwrap();
+            if let Some(i) = held.iter().rposition(|n| n == self.name) {
+                held.remove(i);
+            }
+        });
+    }
+}
+
+impl<'a, T> Deref for TrackedGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+impl<'a, T> DerefMut for TrackedGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.guard
+    }
+}
+
+/// 스냅샷 한 줄분의 정보
+#[derive(Debug, Clone)]
+pub struct ThreadInfo {
+    pub id: u64,
+    pub name: String,
+    pub age: std::time::Duration,
+    pub state: ThreadState,
+    pub held: Vec<String>,
+}
+
+pub fn threads() -> Vec<ThreadInfo> {
+    THREADS
+        .lock()
+        .unwrap()
+        .values()
+        .map(|e| ThreadInfo {
+            id: e.id,
+            name: e.name.clone(),
+            age: e.started.elapsed(),
+            state: e.state.lock().unwrap().clone(),
+            held: e.held.lock().unwrap().clone(),
+        })
+        .collect()
+}
+
+// 사람이 읽기 위한 스냅샷 문자열
+pub fn snapshot() -> String {
+    let mut s = String::new();
+    let threads = threads();
+    writeln!(s, "=== thread registry: {} threads ===", threads.len()).unwrap();
+    for t in threads {
+        let state = match &t.state {
+            ThreadState::Running => "running".to_string(),
+            ThreadState::Waiting(what) => format!("waiting for {}", what),
+        };
+        writeln!(
+            s,
+            "#{} {:<16} up {:>8.3}s  {:<24} held: [{}]",
+            t.id,
+            t.name,
+            t.age.as_secs_f64(),
+            state,
+            t.held.join(", ")
+        )
+        .unwrap();
+    }
+    s
+}
+
+// SIGUSR1을 받을 때마다 스냅샷을 출력한다. path를 지정하면 출력 대신 해당 파일에 덮어쓴다.
+// $ kill -USR1 <pid>
+pub fn install_sigusr1_dump(path: Option<PathBuf>) -> Result<DumpHandle, Box<dyn Error>> {
+    on_signal(SIGUSR1, move || {
+        let s = snapshot();
+        match &path {
+            Some(p) => {
+                if let Err(e) = std::fs::write(p, &s) {
+                    eprintln!("thread registry: failed to write {:?}: {}", p, e);
+                }
+            }
+            None => print!("{}", s),
+        }
+    })
+}
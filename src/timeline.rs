@@ -0,0 +1,338 @@
+// 1.1 프로세스의 상태와 1.2/1.3 동시성·병렬성을 실행 기록으로 확인하기
+// ch01에서는 프로세스를 네 가지 상태(실행 전, 실행, 대기, 종료)로 정의하고, 계산 중 상태(실행 + 대기)가 겹치는 구간을
+// 동시 실행, 실행 상태가 겹치는 구간을 병렬 실행이라고 했다. 스레드, 태스크, 그리고 이 크레이트의 Semaphore와
+// channel이 상태 전이를 기록하게 해서 실제 실행에서 이 구간들을 눈으로 확인할 수 있는 가벼운 기록기를 구현해보자.
+//
+// - Timeline::spawn으로 만든 스레드는 시작 시 Running, 종료 시 Terminated를 자동으로 기록한다.
+// - 그 스레드 안에서 ch03의 Semaphore::wait, Receiver::recv가 블록되거나 BakeryLock::lock, ch04의 SpinLock::lock이
+//   다른 스레드를 기다리면 Waiting, 깨어나거나 락을 획득하면 Running을 기록한다.
+//   기록 대상 스레드가 아니면 emit은 아무것도 하지 않으므로 평소에는 비용이 거의 없다.
+// - 스레드가 아닌 태스크는 Timeline::process로 만든 Process 핸들로 상태를 직접 기록한다.
+// - 결과는 Chrome trace event 형식의 JSON(chrome://tracing, Perfetto에서 열 수 있음)과 ASCII 간트 차트로 출력한다.
+//   간트 차트에서 '#'는 실행, '-'는 대기이며 아래의 concurrent/parallel 행은 각각 둘 이상의 프로세스가 계산 중/실행
+//   중인 구간이다.
+
+use std::cell::RefCell;
+use std::fmt::Write as _;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// ch01의 프로세스 상태
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    PreRun,
+    Running,
+    Waiting,
+    Terminated,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    pub pid: usize,
+    pub state: State,
+    pub at: Duration, // 기록 시작부터의 경과 시간
+    pub reason: &'static str,
+}
+
+/// 상태가 유지된 구간
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Segment {
+    pub pid: usize,
+    pub state: State,
+    pub start: Duration,
+    pub end: Duration,
+    pub reason: &'static str,
+}
+
+/// 동시/병렬 실행 구간의 합계
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Overlap {
+    pub concurrent: Duration, // 둘 이상의 프로세스가 계산 중(실행 또는 대기)인 시간
+    pub parallel: Duration,   // 둘 이상의 프로세스가 실행 중인 시간
+}
+
+#[derive(Debug)]
+pub struct Timeline {
+    start: Instant,
+    names: Mutex<Vec<String>>, // pid -> 이름
+    events: Mutex<Vec<Event>>,
+}
+
+thread_local! {
+    // 현재 스레드가 기록 대상이면 (기록기, pid)
+    static CURRENT: RefCell<Option<(Arc<Timeline>, usize)>> = const { RefCell::new(None) };
+}
+
+/// 현재 스레드의 상태 전이를 기록. 기록 대상 스레드가 아니면 아무것도 하지 않는다.
+pub fn emit(state: State, reason: &'static str) {
+    CURRENT.with(|c| {
+        if let Some((t, pid)) = c.borrow().as_ref() {
+            t.record(*pid, state, reason);
+        }
+    });
+}
+
+impl Timeline {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Timeline {
+            start: Instant::now(),
+            names: Mutex::new(Vec::new()),
+            events: Mutex::new(Vec::new()),
+        })
+    }
+
+    fn add_process(&self, name: &str) -> usize {
+        let mut names = self.names.lock().unwrap();
+        names.push(name.to_string());
+        names.len() - 1
+    }
+
+    pub fn record(&self, pid: usize, state: State, reason: &'static str) {
+        self.record_at(pid, state, self.start.elapsed(), reason);
+    }
+
+    // 시각을 직접 지정해서 기록. 다른 곳에서 얻은 기록을 가져올 때 이용한다.
+    pub fn record_at(&self, pid: usize, state: State, at: Duration, reason: &'static str) {
+        self.events.lock().unwrap().push(Event {
+            pid,
+            state,
+            at,
+            reason,
+        });
+    }
+
+    // 현재 스레드를 실행 중인 프로세스로 등록
+    pub fn register(self: &Arc<Self>, name: &str) -> usize {
+        let pid = self.add_process(name);
+        self.record(pid, State::Running, "register");
+        CURRENT.with(|c| *c.borrow_mut() = Some((self.clone(), pid)));
+        pid
+    }
+
+    /// 기록 대상 스레드 생성. 생성 시 PreRun, 시작 시 Running, 종료 시 Terminated를 기록한다.
+    pub fn spawn<F, T>(self: &Arc<Self>, name: &str, f: F) -> JoinHandle<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let pid = self.add_process(name);
+        self.record(pid, State::PreRun, "spawn");
+        let t = self.clone();
+        let handle = thread::spawn(move || {
+            t.record(pid, State::Running, "start");
+            CURRENT.with(|c| *c.borrow_mut() = Some((t.clone(), pid)));
+            let v = f();
+            CURRENT.with(|c| *c.borrow_mut() = None);
+            t.record(pid, State::Terminated, "exit");
+            v
+        });
+        JoinHandle(handle)
+    }
+
+    // 스레드가 아닌 태스크용 프로세스. PreRun 상태로 시작한다.
+    pub fn process(self: &Arc<Self>, name: &str) -> Process {
+        let pid = self.add_process(name);
+        self.record(pid, State::PreRun, "new");
+        Process {
+            timeline: self.clone(),
+            pid,
+        }
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.names.lock().unwrap().clone()
+    }
+
+    pub fn events(&self) -> Vec<Event> {
+        let mut events = self.events.lock().unwrap().clone();
+        events.sort_by_key(|e| e.at); // 안정 정렬이므로 같은 시각이면 기록 순
+        events
+    }
+
+    // 프로세스별로 상태가 유지된 구간. 종료하지 않은 프로세스는 마지막 이벤트 시각까지로 한다.
+    pub fn segments(&self) -> Vec<Segment> {
+        let events = self.events();
+        let end = events.last().map_or(Duration::ZERO, |e| e.at);
+        let mut last: Vec<Option<&Event>> = vec![None; self.names.lock().unwrap().len()];
+        let mut segments = Vec::new();
+        let mut close = |prev: Option<&Event>, at: Duration| {
+            if let Some(p) = prev {
+                if p.state != State::Terminated && at > p.at {
+                    segments.push(Segment {
+                        pid: p.pid,
+                        state: p.state,
+                        start: p.at,
+                        end: at,
+                        reason: p.reason,
+                    });
+                }
+            }
+        };
+        for e in events.iter() {
+            close(last[e.pid], e.at);
+            last[e.pid] = Some(e);
+        }
+        for prev in last {
+            close(prev, end);
+        }
+        segments.sort_by_key(|s| (s.pid, s.start));
+        segments
+    }
+
+    pub fn overlap(&self) -> Overlap {
+        let segments = self.segments();
+        // 구간의 경계마다 실행 중/계산 중인 프로세스 수를 세면서 훑는다.
+        let mut points: Vec<(Duration, i32, i32)> = Vec::new(); // (시각, 실행 수 변화, 계산 중 수 변화)
+        for s in segments.iter() {
+            let (run, busy) = match s.state {
+                State::Running => (1, 1),
+                State::Waiting => (0, 1),
+                _ => (0, 0),
+            };
+            points.push((s.start, run, busy));
+            points.push((s.end, -run, -busy));
+        }
+        points.sort_by_key(|p| p.0);
+        let (mut run, mut busy) = (0, 0);
+        let mut prev = Duration::ZERO;
+        let mut overlap = Overlap {
+            concurrent: Duration::ZERO,
+            parallel: Duration::ZERO,
+        };
+        for (at, dr, db) in points {
+            if busy >= 2 {
+                overlap.concurrent += at - prev;
+            }
+            if run >= 2 {
+                overlap.parallel += at - prev;
+            }
+            run += dr;
+            busy += db;
+            prev = at;
+        }
+        overlap
+    }
+
+    /// Chrome trace event 형식의 JSON. 상태 구간 하나가 complete event("ph": "X") 하나가 된다.
+    pub fn chrome_trace(&self) -> String {
+        let mut out = String::from("[\n");
+        for (pid, name) in self.names().iter().enumerate() {
+            let _ = writeln!(
+                out,
+                "  {{\"name\": \"thread_name\", \"ph\": \"M\", \"pid\": 1, \"tid\": {}, \"args\": {{\"name\": \"{}\"}}}},",
+                pid,
+                escape(name)
+            );
+        }
+        for s in self.segments() {
+            let _ = writeln!(
+                out,
+                "  {{\"name\": \"{:?}\", \"cat\": \"state\", \"ph\": \"X\", \"ts\": {}, \"dur\": {}, \"pid\": 1, \"tid\": {}, \"args\": {{\"reason\": \"{}\"}}}},",
+                s.state,
+                s.start.as_micros(),
+                (s.end - s.start).as_micros(),
+                s.pid,
+                escape(s.reason)
+            );
+        }
+        if out.ends_with(",\n") {
+            out.truncate(out.len() - 2);
+            out.push('\n');
+        }
+        out.push(']');
+        out
+    }
+
+    /// width 칸의 ASCII 간트 차트
+    pub fn gantt(&self, width: usize) -> String {
+        let segments = self.segments();
+        let names = self.names();
+        let end = segments.iter().map(|s| s.end).max().unwrap_or(Duration::ZERO);
+        if end.is_zero() || width == 0 {
+            return String::new();
+        }
+        // c번째 칸이 나타내는 구간과 겹치는 상태
+        let cell = |c: usize| (end * c as u32 / width as u32, end * (c as u32 + 1) / width as u32);
+        let has = |pid: usize, state: State, c: usize| {
+            let (a, b) = cell(c);
+            segments
+                .iter()
+                .any(|s| s.pid == pid && s.state == state && s.start < b && s.end > a)
+        };
+        let label = names.iter().map(|n| n.len()).max().unwrap_or(0).max("concurrent".len());
+        let mut out = String::new();
+        for (pid, name) in names.iter().enumerate() {
+            let row: String = (0..width)
+                .map(|c| {
+                    if has(pid, State::Running, c) {
+                        '#'
+                    } else if has(pid, State::Waiting, c) {
+                        '-'
+                    } else {
+                        ' '
+                    }
+                })
+                .collect();
+            let _ = writeln!(out, "{:>label$} |{}|", name, row, label = label);
+        }
+        // 칸의 중간 시각에 둘 이상이 계산 중/실행 중인가
+        let count = |states: &[State], c: usize| {
+            let (a, b) = cell(c);
+            let mid = (a + b) / 2;
+            segments
+                .iter()
+                .filter(|s| states.contains(&s.state) && s.start <= mid && mid < s.end)
+                .count()
+        };
+        for (title, states, mark) in [
+            ("concurrent", &[State::Running, State::Waiting][..], '~'),
+            ("parallel", &[State::Running][..], '='),
+        ] {
+            let row: String = (0..width)
+                .map(|c| if count(states, c) >= 2 { mark } else { ' ' })
+                .collect();
+            let _ = writeln!(out, "{:>label$} |{}|", title, row, label = label);
+        }
+        let _ = writeln!(out, "{:>label$}  0{:>w$?}", "", end, label = label, w = width - 1);
+        out
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Timeline::spawn으로 만든 스레드의 핸들. join으로 기다리는 동안 호출한 스레드는 Waiting으로 기록된다.
+pub struct JoinHandle<T>(thread::JoinHandle<T>);
+
+impl<T> JoinHandle<T> {
+    pub fn join(self) -> thread::Result<T> {
+        emit(State::Waiting, "join");
+        let r = self.0.join();
+        emit(State::Running, "join");
+        r
+    }
+}
+
+/// 스레드가 아닌 태스크의 상태를 기록하기 위한 핸들. drop 시 종료 상태를 기록한다.
+pub struct Process {
+    timeline: Arc<Timeline>,
+    pid: usize,
+}
+
+impl Process {
+    pub fn pid(&self) -> usize {
+        self.pid
+    }
+
+    pub fn set(&self, state: State, reason: &'static str) {
+        self.timeline.record(self.pid, state, reason);
+    }
+}
+
+impl Drop for Process {
+    fn drop(&mut self) {
+        self.set(State::Terminated, "drop");
+    }
+}
@@ -0,0 +1,134 @@
+// 4.1 데드락 데모를 위한 watchdog
+// func_144p, func_145p, func_147p_1, func_161p는 의도적으로 데드락을 일으키는 예제이므로 it_works에서 직접 호출하면
+// cargo test 전체가 멈춰버린다. 그래서 데모 하나를 자식 프로세스에서 실행하고 timeout이 지나면 kill해서 결과를
+// 분류하는 watchdog을 구현해보자. 데드락은 자식 프로세스 안에 갇히므로 테스트 프로세스는 계속 진행할 수 있다.
+//
+// 자식 프로세스는 테스트 바이너리 자신(current_exe)을 다시 실행해서 만든다.
+//   부모: <test binary> --exact <entry> --nocapture  (환경 변수 WATCHDOG_DEMO=<데모 이름>)
+//   자식: entry 테스트가 WATCHDOG_DEMO를 보고 child_main으로 해당 데모만 실행
+//
+// 결과 분류
+// - Completed : 정상 종료
+// - TimedOut  : timeout까지 끝나지 않음(데드락 또는 라이브락으로 간주)
+// - Panicked  : 패닉(테스트 하니스는 실패한 테스트가 있으면 종료 코드 101로 끝난다)
+// - Signaled  : 시그널로 종료(SIGSEGV, SIGABRT 등)
+// - Exited    : 그 밖의 종료 코드
+
+use std::io::Read;
+use std::os::unix::process::ExitStatusExt;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub const ENV_DEMO: &str = "WATCHDOG_DEMO";
+
+// 데모 출력과 테스트 하니스 출력을 구분하기 위한 표시
+const BEGIN: &str = "--- watchdog: begin ---";
+const END: &str = "--- watchdog: end ---";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Outcome {
+    Completed,
+    TimedOut,
+    Panicked,
+    Signaled(i32),
+    Exited(i32),
+}
+
+#[derive(Debug)]
+pub struct Report {
+    pub demo: String,
+    pub outcome: Outcome,
+    pub elapsed: Duration,
+    pub stdout: String, // 데모가 출력한 내용만
+    pub stderr: String,
+}
+
+/// 자식 프로세스 측. WATCHDOG_DEMO에 지정된 데모를 demos에서 찾아 실행한다. 지정되지 않았으면 아무것도 하지 않는다.
+pub fn child_main(demos: &[(&str, fn())]) {
+    let name = match std::env::var(ENV_DEMO) {
+        Ok(name) => name,
+        Err(_) => return,
+    };
+    let f = demos
+        .iter()
+        .find(|(n, _)| *n == name)
+        .unwrap_or_else(|| panic!("watchdog: unknown demo {}", name))
+        .1;
+    println!("{}", BEGIN);
+    f();
+    println!("{}", END);
+}
+
+/// 부모 측. entry는 child_main을 호출하는 테스트의 전체 경로(예: tests::watchdog_child).
+pub fn run(entry: &str, demo: &str, timeout: Duration) -> Report {
+    let exe = std::env::current_exe().unwrap();
+    let mut child = Command::new(exe)
+        .args([entry, "--exact", "--nocapture", "--test-threads=1"])
+        .env(ENV_DEMO, demo)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    // 파이프가 가득 차서 자식이 멈추지 않도록 별도 스레드에서 계속 읽어둔다.
+    let mut out = child.stdout.take().unwrap();
+    let mut err = child.stderr.take().unwrap();
+    let out_reader = thread::spawn(move || {
+        let mut s = String::new();
+        let _ = out.read_to_string(&mut s);
+        s
+    });
+    let err_reader = thread::spawn(move || {
+        let mut s = String::new();
+        let _ = err.read_to_string(&mut s);
+        s
+    });
+
+    let start = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait().unwrap() {
+            break Some(status);
+        }
+        if start.elapsed() >= timeout {
+            let _ = child.kill(); // SIGKILL
+            child.wait().unwrap();
+            break None;
+        }
+        thread::sleep(Duration::from_millis(10));
+    };
+    let elapsed = start.elapsed();
+
+    let outcome = match status {
+        None => Outcome::TimedOut,
+        Some(s) if s.success() => Outcome::Completed,
+        Some(s) => match (s.code(), s.signal()) {
+            (Some(101), _) => Outcome::Panicked,
+            (Some(code), _) => Outcome::Exited(code),
+            (None, Some(sig)) => Outcome::Signaled(sig),
+            (None, None) => unreachable!(),
+        },
+    };
+
+    Report {
+        demo: demo.to_string(),
+        outcome,
+        elapsed,
+        stdout: demo_output(&out_reader.join().unwrap()),
+        stderr: err_reader.join().unwrap(),
+    }
+}
+
+// 하니스의 "running 1 test" 등을 제외하고 BEGIN과 END 사이만 꺼낸다. 끝나지 않았으면 BEGIN 이후 전부.
+fn demo_output(s: &str) -> String {
+    let s = match s.find(BEGIN) {
+        Some(i) => &s[i + BEGIN.len()..],
+        None => return String::new(),
+    };
+    let s = match s.find(END) {
+        Some(i) => &s[..i],
+        None => s,
+    };
+    s.trim_start_matches('\n').to_string()
+}
@@ -0,0 +1,452 @@
+// 4.7 메모리 배리어와 store buffer 시뮬레이터
+// 3.9절의 BakeryLock::lock은 read/write 앞뒤에 fence(Ordering::SeqCst)를 넣고, 주석에서 각각이 out-of-order 실행을
+// 막기 위해 필요하다고 설명한다. 하지만 실제로 어떤 fence를 빼면 어떤 CPU에서 상호 배제가 깨지는지는 x86-64 머신에서
+// 테스트를 돌려봐서는 거의 알 수 없다. 그래서 락 알고리즘을 작은 공유 메모리 명령어로 적고, 스레드별 store buffer를
+// 가진 메모리 모델 위에서 도달 가능한 모든 상태를 탐색하는 시뮬레이터를 구현해보자.
+//
+// 메모리 모델
+// - SC : 순차 일관성. 쓰기는 즉시 메모리에 반영된다.
+// - TSO: x86-64와 같은 모델. 스레드마다 FIFO store buffer가 있어 쓰기는 buffer에 들어갔다가 임의의 시점에 오래된
+//        것부터 메모리에 반영된다. 읽기는 자신의 buffer에 같은 주소가 있으면 그 값을 읽는다. 즉, W->R만 리오더링된다.
+// - PSO: 주소별로 buffer가 나뉘어 있어 다른 주소에 대한 쓰기끼리도 순서가 바뀐다(W->W, W->R 리오더링).
+// fence는 자신의 store buffer가 빌 때까지 진행하지 못한다.
+//
+// 각 스레드는 락 획득, 임계 구역(Cs 명령), 락 해제를 한 번 실행한다. 두 스레드가 동시에 Cs 명령에 있는 상태에 도달하면
+// 상호 배제 위반이며, 그 상태까지의 실행 순서(어떤 스레드가 무엇을 실행했고 어떤 쓰기가 언제 메모리에 반영됐는지)를
+// 반례로 보여준다. 상태 수가 유한하므로 스핀 루프가 있어도 탐색은 끝난다.
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+
+pub type Addr = usize;
+pub type Reg = usize;
+
+const NUM_REGS: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Val {
+    Imm(i64),
+    Reg(Reg),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Cond {
+    Eq(Val, Val),
+    Ne(Val, Val),
+    Lt(Val, Val),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Label(usize);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Instr {
+    Load(Reg, Addr),
+    Store(Addr, Val),
+    Mov(Reg, Val),
+    Add(Reg, Val),
+    Max(Reg, Val),
+    Fence(&'static str), // 이름은 어떤 fence를 제거할지 지정할 때 이용
+    Jump(Label),
+    JumpIf(Cond, Label),
+    Cs, // 임계 구역
+}
+
+/// 스레드 하나의 명령열
+#[derive(Debug, Clone, Default)]
+pub struct Program {
+    code: Vec<Instr>,
+    labels: Vec<Option<usize>>,
+}
+
+impl Program {
+    pub fn new() -> Self {
+        Program::default()
+    }
+
+    pub fn label(&mut self) -> Label {
+        self.labels.push(None);
+        Label(self.labels.len() - 1)
+    }
+
+    // 다음 명령의 위치에 label을 붙인다.
+    pub fn bind(&mut self, l: Label) {
+        self.labels[l.0] = Some(self.code.len());
+    }
+
+    pub fn push(&mut self, i: Instr) {
+        self.code.push(i);
+    }
+
+    fn target(&self, l: Label) -> usize {
+        self.labels[l.0].expect("unbound label")
+    }
+}
+
+/// 시뮬레이션 대상. 스레드별 명령열과 공유 메모리 크기, 알고리즘에 포함된 fence 이름 목록.
+pub struct System {
+    pub name: &'static str,
+    pub programs: Vec<Program>,
+    pub memory: usize,
+    pub fences: Vec<&'static str>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Model {
+    Sc,
+    Tso,
+    Pso,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct State {
+    pcs: Vec<usize>,
+    regs: Vec<[i64; NUM_REGS]>,
+    mem: Vec<i64>,
+    buffers: Vec<Vec<(Addr, i64)>>, // 스레드별 store buffer(오래된 것이 앞)
+}
+
+/// 탐색 결과
+#[derive(Debug, Clone)]
+pub struct Outcome {
+    pub states: usize,
+    pub violation: Option<Vec<String>>, // 상호 배제 위반까지의 실행 순서
+}
+
+impl Outcome {
+    pub fn safe(&self) -> bool {
+        self.violation.is_none()
+    }
+}
+
+fn val(regs: &[i64; NUM_REGS], v: Val) -> i64 {
+    match v {
+        Val::Imm(x) => x,
+        Val::Reg(r) => regs[r],
+    }
+}
+
+impl System {
+    // 도달 가능한 모든 상태를 너비 우선으로 탐색. 반례는 가장 짧은 것이 된다.
+    pub fn check(&self, model: Model) -> Outcome {
+        let n = self.programs.len();
+        let init = State {
+            pcs: vec![0; n],
+            regs: vec![[0; NUM_REGS]; n],
+            mem: vec![0; self.memory],
+            buffers: vec![Vec::new(); n],
+        };
+
+        let mut states = vec![init.clone()];
+        let mut parent: Vec<Option<(usize, String)>> = vec![None];
+        let mut index = HashMap::new();
+        index.insert(init, 0);
+        let mut queue = VecDeque::from([0]);
+
+        while let Some(i) = queue.pop_front() {
+            let s = states[i].clone();
+            if self.violates(&s) {
+                let mut trace = Vec::new();
+                let mut cur = i;
+                while let Some((p, step)) = &parent[cur] {
+                    trace.push(step.clone());
+                    cur = *p;
+                }
+                trace.reverse();
+                return Outcome {
+                    states: states.len(),
+                    violation: Some(trace),
+                };
+            }
+            for (step, next) in self.successors(&s, model) {
+                if !index.contains_key(&next) {
+                    index.insert(next.clone(), states.len());
+                    states.push(next);
+                    parent.push(Some((i, step)));
+                    queue.push_back(states.len() - 1);
+                }
+            }
+        }
+
+        Outcome {
+            states: states.len(),
+            violation: None,
+        }
+    }
+
+    fn violates(&self, s: &State) -> bool {
+        let in_cs = (0..self.programs.len())
+            .filter(|&t| self.programs[t].code.get(s.pcs[t]) == Some(&Instr::Cs))
+            .count();
+        in_cs >= 2
+    }
+
+    fn successors(&self, s: &State, model: Model) -> Vec<(String, State)> {
+        let mut v = Vec::new();
+        for (t, prog) in self.programs.iter().enumerate() {
+            // 명령 실행
+            if let Some(instr) = prog.code.get(s.pcs[t]) {
+                if let Some(next) = self.step(s, t, instr, model) {
+                    v.push((format!("T{}: {}", t, describe(instr, &s.regs[t])), next));
+                }
+            }
+            // store buffer에서 메모리로 반영
+            for (k, &(addr, x)) in s.buffers[t].iter().enumerate() {
+                let flushable = match model {
+                    Model::Sc => false,
+                    Model::Tso => k == 0,
+                    Model::Pso => s.buffers[t][..k].iter().all(|(a, _)| *a != addr),
+                };
+                if flushable {
+                    let mut next = s.clone();
+                    next.buffers[t].remove(k);
+                    next.mem[addr] = x;
+                    v.push((format!("T{}: flush [{}] = {}", t, addr, x), next));
+                }
+            }
+        }
+        v
+    }
+
+    fn step(&self, s: &State, t: usize, instr: &Instr, model: Model) -> Option<State> {
+        let prog = &self.programs[t];
+        let mut next = s.clone();
+        let regs = &mut next.regs[t];
+        let mut pc = s.pcs[t] + 1;
+        match *instr {
+            Instr::Load(r, addr) => {
+                // 자신의 buffer에 있는 가장 새로운 값을 우선(store forwarding)
+                regs[r] = s.buffers[t]
+                    .iter()
+                    .rev()
+                    .find(|(a, _)| *a == addr)
+                    .map_or(s.mem[addr], |(_, x)| *x);
+            }
+            Instr::Store(addr, v) => {
+                let x = val(regs, v);
+                if model == Model::Sc {
+                    next.mem[addr] = x;
+                } else {
+                    next.buffers[t].push((addr, x));
+                }
+            }
+            Instr::Mov(r, v) => regs[r] = val(regs, v),
+            Instr::Add(r, v) => regs[r] += val(regs, v),
+            Instr::Max(r, v) => regs[r] = regs[r].max(val(regs, v)),
+            Instr::Fence(_) => {
+                if !s.buffers[t].is_empty() {
+                    return None; // buffer가 빌 때까지 대기
+                }
+            }
+            Instr::Jump(l) => pc = prog.target(l),
+            Instr::JumpIf(c, l) => {
+                let taken = match c {
+                    Cond::Eq(a, b) => val(regs, a) == val(regs, b),
+                    Cond::Ne(a, b) => val(regs, a) != val(regs, b),
+                    Cond::Lt(a, b) => val(regs, a) < val(regs, b),
+                };
+                if taken {
+                    pc = prog.target(l);
+                }
+            }
+            Instr::Cs => {}
+        }
+        // 값이 바뀌지 않는 스핀(같은 값을 다시 읽고 점프)도 상태가 같으므로 탐색은 자연히 끝난다.
+        next.pcs[t] = pc;
+        Some(next)
+    }
+}
+
+fn describe(instr: &Instr, regs: &[i64; NUM_REGS]) -> String {
+    match instr {
+        Instr::Load(r, a) => format!("r{} = [{}]", r, a),
+        Instr::Store(a, v) => format!("[{}] = {}", a, val(regs, *v)),
+        Instr::Fence(name) => format!("fence {}", name),
+        Instr::Cs => "enter critical section".to_string(),
+        i => format!("{:?}", i),
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.violation {
+            None => write!(f, "safe ({} states)", self.states),
+            Some(trace) => {
+                writeln!(f, "mutual exclusion violated ({} states)", self.states)?;
+                for step in trace {
+                    writeln!(f, "  {}", step)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+// removed에 포함된 이름의 fence는 넣지 않는다.
+fn fence(p: &mut Program, name: &'static str, removed: &[&str]) {
+    if !removed.contains(&name) {
+        p.push(Instr::Fence(name));
+    }
+}
+
+/// 3.9 베이커리 알고리즘. 메모리 배치는 entering[0..n], tickets[n..2n].
+/// fence 이름은 BakeryLock의 fence 위치에 대응한다.
+/// - F1/F2: entering[idx] = true 앞뒤, F3/F4: entering[idx] = false 앞뒤, F5: 대기 종료 후, F6: 해제(Drop) 전
+pub fn bakery(n: usize, removed: &[&str]) -> System {
+    let entering = |i: usize| i;
+    let tickets = |i: usize| n + i;
+    let programs = (0..n)
+        .map(|idx| {
+            let mut p = Program::new();
+            fence(&mut p, "F1", removed);
+            p.push(Instr::Store(entering(idx), Val::Imm(1)));
+            fence(&mut p, "F2", removed);
+            // r0 = max(tickets) + 1
+            p.push(Instr::Mov(0, Val::Imm(0)));
+            for i in 0..n {
+                p.push(Instr::Load(1, tickets(i)));
+                p.push(Instr::Max(0, Val::Reg(1)));
+            }
+            p.push(Instr::Add(0, Val::Imm(1)));
+            p.push(Instr::Store(tickets(idx), Val::Reg(0)));
+            fence(&mut p, "F3", removed);
+            p.push(Instr::Store(entering(idx), Val::Imm(0)));
+            fence(&mut p, "F4", removed);
+
+            for i in (0..n).filter(|&i| i != idx) {
+                // while entering[i] {}
+                let wait_entering = p.label();
+                p.bind(wait_entering);
+                p.push(Instr::Load(1, entering(i)));
+                p.push(Instr::JumpIf(Cond::Ne(Val::Reg(1), Val::Imm(0)), wait_entering));
+                // tickets[i]가 None이거나 (ticket, idx) < (tickets[i], i)가 될 때까지 대기
+                let wait_ticket = p.label();
+                let next = p.label();
+                p.bind(wait_ticket);
+                p.push(Instr::Load(1, tickets(i)));
+                p.push(Instr::JumpIf(Cond::Eq(Val::Reg(1), Val::Imm(0)), next));
+                p.push(Instr::JumpIf(Cond::Lt(Val::Reg(0), Val::Reg(1)), next));
+                if idx < i {
+                    p.push(Instr::JumpIf(Cond::Eq(Val::Reg(0), Val::Reg(1)), next));
+                }
+                p.push(Instr::Jump(wait_ticket));
+                p.bind(next);
+            }
+            fence(&mut p, "F5", removed);
+            p.push(Instr::Cs);
+            fence(&mut p, "F6", removed);
+            p.push(Instr::Store(tickets(idx), Val::Imm(0)));
+            p
+        })
+        .collect();
+    System {
+        name: "bakery",
+        programs,
+        memory: 2 * n,
+        fences: vec!["F1", "F2", "F3", "F4", "F5", "F6"],
+    }
+}
+
+/// 피터슨 알고리즘(2스레드). 메모리 배치는 flag[0], flag[1], turn.
+/// - F1: flag[i] = true와 turn = j 사이, F2: turn = j 후 대기 전, F3: 해제 전
+pub fn peterson(removed: &[&str]) -> System {
+    const TURN: Addr = 2;
+    let programs = (0..2)
+        .map(|i| {
+            let j = 1 - i;
+            let mut p = Program::new();
+            p.push(Instr::Store(i, Val::Imm(1)));
+            fence(&mut p, "F1", removed);
+            p.push(Instr::Store(TURN, Val::Imm(j as i64)));
+            fence(&mut p, "F2", removed);
+            // while flag[j] && turn == j {}
+            let wait = p.label();
+            let enter = p.label();
+            p.bind(wait);
+            p.push(Instr::Load(1, j));
+            p.push(Instr::JumpIf(Cond::Eq(Val::Reg(1), Val::Imm(0)), enter));
+            p.push(Instr::Load(1, TURN));
+            p.push(Instr::JumpIf(Cond::Eq(Val::Reg(1), Val::Imm(j as i64)), wait));
+            p.bind(enter);
+            p.push(Instr::Cs);
+            fence(&mut p, "F3", removed);
+            p.push(Instr::Store(i, Val::Imm(0)));
+            p
+        })
+        .collect();
+    System {
+        name: "peterson",
+        programs,
+        memory: 3,
+        fences: vec!["F1", "F2", "F3"],
+    }
+}
+
+/// 데커 알고리즘(2스레드). 메모리 배치는 flag[0], flag[1], turn.
+/// - F1: flag[i] = true 후, F2: 양보 후 flag[i] = true를 다시 쓴 후, F3: turn = j와 flag[i] = false 사이
+pub fn dekker(removed: &[&str]) -> System {
+    const TURN: Addr = 2;
+    let programs = (0..2)
+        .map(|i| {
+            let j = 1 - i;
+            let mut p = Program::new();
+            p.push(Instr::Store(i, Val::Imm(1)));
+            fence(&mut p, "F1", removed);
+            // while flag[j] { if turn != i { flag[i] = false; while turn != i {}; flag[i] = true; } }
+            let check = p.label();
+            let enter = p.label();
+            p.bind(check);
+            p.push(Instr::Load(1, j));
+            p.push(Instr::JumpIf(Cond::Eq(Val::Reg(1), Val::Imm(0)), enter));
+            p.push(Instr::Load(1, TURN));
+            p.push(Instr::JumpIf(Cond::Eq(Val::Reg(1), Val::Imm(i as i64)), check));
+            p.push(Instr::Store(i, Val::Imm(0)));
+            let wait_turn = p.label();
+            p.bind(wait_turn);
+            p.push(Instr::Load(1, TURN));
+            p.push(Instr::JumpIf(Cond::Ne(Val::Reg(1), Val::Imm(i as i64)), wait_turn));
+            p.push(Instr::Store(i, Val::Imm(1)));
+            fence(&mut p, "F2", removed);
+            p.push(Instr::Jump(check));
+            p.bind(enter);
+            p.push(Instr::Cs);
+            p.push(Instr::Store(TURN, Val::Imm(j as i64)));
+            fence(&mut p, "F3", removed);
+            p.push(Instr::Store(i, Val::Imm(0)));
+            p
+        })
+        .collect();
+    System {
+        name: "dekker",
+        programs,
+        memory: 3,
+        fences: vec!["F1", "F2", "F3"],
+    }
+}
+
+/// fence를 하나씩 제거했을 때 각 메모리 모델에서 상호 배제가 유지되는지 표로 출력하고, (제거한 fence, [SC, TSO, PSO]) 목록을 반환
+pub fn fence_report(build: fn(&[&str]) -> System) -> Vec<(&'static str, [bool; 3])> {
+    let all = build(&[]);
+    let mut cases: Vec<(&'static str, Vec<&'static str>)> = vec![("(none)", vec![])];
+    for &f in all.fences.iter() {
+        cases.push((f, vec![f]));
+    }
+    cases.push(("(all)", all.fences.clone()));
+
+    println!("{}: fence removal    SC    TSO   PSO", all.name);
+    let mut result = Vec::new();
+    for (name, removed) in cases {
+        let sys = build(&removed);
+        let safe = [Model::Sc, Model::Tso, Model::Pso].map(|m| sys.check(m).safe());
+        let mark = |b: bool| if b { "ok" } else { "BROKEN" };
+        println!(
+            "  {:<20} {:<6} {:<6} {:<6}",
+            name,
+            mark(safe[0]),
+            mark(safe[1]),
+            mark(safe[2])
+        );
+        result.push((name, safe));
+    }
+    result
+}